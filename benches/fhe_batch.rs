@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use privacy_preserving_credit::fhe_utils;
+use tfhe::shortint::prelude::*;
+
+// Twelve months of expenses, each encrypted as radix blocks, for a small cohort of applicants
+const MONTHS: usize = 12;
+const APPLICANTS: usize = 16;
+// Sums past five summands need carry propagation, so the larger sizes measure it too
+const SUMMANDS: [usize; 4] = [5, 12, 48, 192];
+
+fn sample_batches() -> Vec<Vec<u64>> {
+    (0..APPLICANTS)
+        .map(|applicant| (0..MONTHS).map(|month| (200 + (applicant * 131 + month * 57) % 4000) as u64).collect())
+        .collect()
+}
+
+fn bench_encryption(c: &mut Criterion) {
+    let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2);
+    let pool = fhe_utils::build_fhe_thread_pool(fhe_utils::DEFAULT_FHE_THREADS).unwrap();
+    let batches = sample_batches();
+
+    let mut group = c.benchmark_group("encrypt_expenses");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            batches
+                .iter()
+                .map(|batch| fhe_utils::encrypt_expenses(batch.clone(), &client_key).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| fhe_utils::encrypt_expense_batches_parallel(&batches, &client_key, &pool).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_summation(c: &mut Criterion) {
+    let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2);
+    let server_key = ServerKey::new(&client_key);
+    let pool = fhe_utils::build_fhe_thread_pool(fhe_utils::DEFAULT_FHE_THREADS).unwrap();
+
+    let mut group = c.benchmark_group("sum_expenses");
+    group.sample_size(10);
+    for summands in SUMMANDS {
+        let values: Vec<u64> = (0..summands as u64).map(|v| 200 + v * 97 % 5000).collect();
+        let encrypted = fhe_utils::encrypt_expenses(values, &client_key).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("serial", summands), &encrypted, |b, encrypted| {
+            b.iter_batched(
+                || encrypted.clone(),
+                |cts| fhe_utils::sum_encrypted_expenses(&cts, &server_key).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("tree_reduce_parallel", summands), &encrypted, |b, encrypted| {
+            b.iter(|| fhe_utils::tree_reduce_sum_parallel(encrypted, &server_key, &pool).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encryption, bench_summation);
+criterion_main!(benches);
@@ -0,0 +1,180 @@
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::decryption_service::credit_evaluation::CiphertextKind;
+use crate::error::CreditError;
+use crate::signing;
+
+/// Domain separator prefixed to every signed statement
+const STATEMENT_DOMAIN: &[u8] = b"credit-evaluation/decision-attestation/v1";
+
+/// What the NBFC vouches for about one application's outputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionStatement {
+    /// Nonce the applicant's threshold proof is bound to
+    pub nonce: Vec<u8>,
+    pub key_id: String,
+    pub threshold: u64,
+    /// Expense ratio limit, in percent, the ratio bucket was computed against
+    pub max_expense_ratio: u64,
+    pub salary_commitment: Vec<u8>,
+    /// Unix seconds at which the NBFC produced the outputs
+    pub issued_at: u64,
+    /// SHA-256 of each output ciphertext, keyed by its `CiphertextKind`
+    pub ciphertext_hashes: BTreeMap<i32, [u8; 32]>,
+}
+
+/// A statement together with the NBFC's Ed25519 signature over it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionAttestation {
+    pub statement: DecisionStatement,
+    pub signature: Vec<u8>,
+}
+
+/// Hashes a ciphertext's radix blocks, length-prefixing each so block boundaries are bound too
+pub fn hash_ciphertext(blocks: &[Vec<u8>]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((blocks.len() as u64).to_le_bytes());
+    for block in blocks {
+        hasher.update((block.len() as u64).to_le_bytes());
+        hasher.update(block);
+    }
+    hasher.finalize().into()
+}
+
+/// Current Unix time in seconds
+pub fn now_unix() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl DecisionStatement {
+    /// Records the hash of an output ciphertext of the given kind
+    pub fn attest_ciphertext(&mut self, kind: CiphertextKind, blocks: &[Vec<u8>]) {
+        self.ciphertext_hashes.insert(kind as i32, hash_ciphertext(blocks));
+    }
+
+    /// Checks a ciphertext is the attested output of the given kind
+    pub fn check_ciphertext(&self, kind: CiphertextKind, blocks: &[Vec<u8>]) -> Result<(), CreditError> {
+        match self.ciphertext_hashes.get(&(kind as i32)) {
+            Some(hash) if *hash == hash_ciphertext(blocks) => Ok(()),
+            Some(_) => Err(CreditError::ProofInvalid(format!("{:?} ciphertext does not match the NBFC attestation", kind))),
+            None => Err(CreditError::ProofInvalid(format!("NBFC attestation does not cover a {:?} ciphertext", kind))),
+        }
+    }
+
+    /// Rejects statements issued more than `max_age` ago or in the future
+    pub fn check_fresh(&self, now: u64, max_age: Duration) -> Result<(), CreditError> {
+        if self.issued_at > now {
+            return Err(CreditError::ProofInvalid("NBFC attestation is dated in the future".into()));
+        }
+        if now - self.issued_at > max_age.as_secs() {
+            return Err(CreditError::Expired("NBFC attestation has expired".into()));
+        }
+        Ok(())
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>, CreditError> {
+        let mut bytes = STATEMENT_DOMAIN.to_vec();
+        bytes.extend(bincode::serialize(self).map_err(|e| CreditError::internal("encode attestation", e))?);
+        Ok(bytes)
+    }
+}
+
+impl DecisionAttestation {
+    /// Signs a statement with the NBFC's key
+    pub fn sign(statement: DecisionStatement, key: &SigningKey) -> Result<Self, CreditError> {
+        let signature = signing::sign(key, &statement.signed_bytes()?);
+        Ok(Self { statement, signature })
+    }
+
+    /// Checks the signature and returns the statement it covers
+    pub fn verify(&self, key: &VerifyingKey) -> Result<&DecisionStatement, CreditError> {
+        signing::verify(key, &self.statement.signed_bytes()?, &self.signature)
+            .map_err(|e| CreditError::ProofInvalid(format!("NBFC attestation: {}", e)))?;
+        Ok(&self.statement)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, CreditError> {
+        bincode::serialize(self).map_err(|e| CreditError::internal("encode attestation", e))
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, CreditError> {
+        if bytes.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing NBFC attestation".into()));
+        }
+        bincode::deserialize(bytes).map_err(|e| CreditError::deserialization("NBFC attestation", e))
+    }
+
+    /// Decodes and verifies an attestation in one step
+    pub fn open(bytes: &[u8], key: &VerifyingKey) -> Result<DecisionStatement, CreditError> {
+        let attestation = Self::decode(bytes)?;
+        attestation.verify(key)?;
+        Ok(attestation.statement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn statement() -> DecisionStatement {
+        let mut statement = DecisionStatement {
+            nonce: vec![9; 32],
+            key_id: "key-1".into(),
+            threshold: 5000,
+            max_expense_ratio: 50,
+            salary_commitment: vec![3; 32],
+            issued_at: 1_000,
+            ciphertext_hashes: BTreeMap::new(),
+        };
+        statement.attest_ciphertext(CiphertextKind::RatioBucket, &[vec![1, 2, 3]]);
+        statement
+    }
+
+    #[test]
+    fn attestation_round_trips_and_verifies() {
+        let attestation = DecisionAttestation::sign(statement(), &key(1)).unwrap();
+        let opened = DecisionAttestation::open(&attestation.encode().unwrap(), &key(1).verifying_key()).unwrap();
+        assert_eq!(opened, statement());
+    }
+
+    #[test]
+    fn tampered_or_foreign_attestation_is_rejected() {
+        let mut attestation = DecisionAttestation::sign(statement(), &key(1)).unwrap();
+        assert!(attestation.verify(&key(2).verifying_key()).is_err());
+
+        attestation.statement.threshold = 1000;
+        assert!(attestation.verify(&key(1).verifying_key()).is_err());
+
+        let mut attestation = DecisionAttestation::sign(statement(), &key(1)).unwrap();
+        attestation.statement.max_expense_ratio = 90;
+        assert!(attestation.verify(&key(1).verifying_key()).is_err());
+    }
+
+    #[test]
+    fn ciphertext_must_match_its_attested_kind() {
+        let statement = statement();
+        assert!(statement.check_ciphertext(CiphertextKind::RatioBucket, &[vec![1, 2, 3]]).is_ok());
+        assert!(statement.check_ciphertext(CiphertextKind::RatioBucket, &[vec![1, 2], vec![3]]).is_err());
+        assert!(statement.check_ciphertext(CiphertextKind::CreditScore, &[vec![1, 2, 3]]).is_err());
+    }
+
+    #[test]
+    fn stale_and_future_statements_are_rejected() {
+        let statement = statement();
+        let max_age = Duration::from_secs(60);
+        assert!(statement.check_fresh(1_060, max_age).is_ok());
+        assert_eq!(
+            statement.check_fresh(1_061, max_age),
+            Err(CreditError::Expired("NBFC attestation has expired".into()))
+        );
+        assert!(statement.check_fresh(999, max_age).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::error::CreditError;
+use crate::signing::{self, SigningKeyPaths};
+use crate::tls;
+
+/// Identity whose signing key issues every token; no service holds it
+pub const TOKEN_ISSUER_IDENTITY: &str = "token-issuer";
+
+/// How long an issued token is accepted
+pub const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Domain separator prefixed to every signed token payload
+const TOKEN_DOMAIN: &[u8] = b"credit-evaluation/bearer-token/v1";
+
+/// Default append-only log of authentication and authorization denials
+pub const DEFAULT_AUTH_AUDIT_LOG_PATH: &str = "logs/auth_audit.jsonl";
+
+const AUTHORIZATION_HEADER: &str = "authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Institution roles a caller can hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Applicant,
+    Nbfc,
+    Bank,
+    Auditor,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Applicant => "applicant",
+            Role::Nbfc => "nbfc",
+            Role::Bank => "bank",
+            Role::Auditor => "auditor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "applicant" => Ok(Role::Applicant),
+            "nbfc" => Ok(Role::Nbfc),
+            "bank" => Ok(Role::Bank),
+            "auditor" => Ok(Role::Auditor),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+/// An authenticated caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub institution: String,
+    pub role: Role,
+    /// Hashed user ID the token was issued for; empty for institution tokens
+    pub subject: Vec<u8>,
+}
+
+impl Principal {
+    /// Key that uploads and jobs are owned under, separating users who share an institution
+    pub fn owner(&self) -> String {
+        if self.subject.is_empty() {
+            self.institution.clone()
+        } else {
+            format!("{}/{}", self.institution, hex::encode(&self.subject))
+        }
+    }
+
+    /// The user an applicant acts for, taken from the token rather than the request
+    ///
+    /// A request that names a different user is refused. Auditors may name any user.
+    pub fn user_id_hash(&self, requested: &[u8]) -> Result<Vec<u8>, CreditError> {
+        if self.role == Role::Auditor {
+            if requested.is_empty() {
+                return Err(CreditError::InvalidArgument("Missing user identity hash".into()));
+            }
+            return Ok(requested.to_vec());
+        }
+        if self.subject.is_empty() {
+            return Err(CreditError::PermissionDenied("Token does not name a user".into()));
+        }
+        if !requested.is_empty() && requested != self.subject.as_slice() {
+            return Err(CreditError::PermissionDenied("Token was issued for a different user".into()));
+        }
+        Ok(self.subject.clone())
+    }
+}
+
+/// What a token asserts about its bearer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claims {
+    pub institution: String,
+    pub role: Role,
+    pub subject: Vec<u8>,
+    /// Unix seconds after which the token is refused
+    pub expires_at: u64,
+}
+
+impl Claims {
+    /// Claims valid for `ttl` from now
+    pub fn new(institution: &str, role: Role, subject: Vec<u8>, ttl: Duration) -> Self {
+        Self {
+            institution: institution.to_string(),
+            role,
+            subject,
+            expires_at: unix_now() + ttl.as_secs(),
+        }
+    }
+
+    /// Claims for an applicant acting for the given user ID
+    pub fn for_user(institution: &str, user_id: &str, ttl: Duration) -> Self {
+        Self::new(institution, Role::Applicant, Sha256::digest(user_id.as_bytes()).to_vec(), ttl)
+    }
+
+    fn payload(&self) -> String {
+        format!("{}.{}.{}.{}", self.institution, self.role, hex::encode(&self.subject), self.expires_at)
+    }
+}
+
+/// Roles allowed to call each RPC, keyed by `Service/Method`
+pub fn default_access_policy() -> HashMap<&'static str, Vec<Role>> {
+    [
+        ("NBFCService/GenerateCreditProof", vec![Role::Applicant]),
+        ("NBFCService/GetRemainingBudget", vec![Role::Applicant, Role::Auditor]),
+        ("NBFCService/PartialDecrypt", vec![Role::Bank]),
+        ("NBFCService/GeneratePortfolioReport", vec![Role::Auditor]),
+        ("NBFCService/PublishProofBundle", vec![Role::Applicant]),
+        ("NBFCService/FetchProofBundle", vec![Role::Bank]),
+        ("NBFCService/RevokeProofBundle", vec![Role::Applicant]),
+        ("NBFCService/ListProofBundles", vec![Role::Applicant, Role::Auditor]),
+        ("NBFCService/BeginUpload", vec![Role::Applicant]),
+        ("NBFCService/UploadChunks", vec![Role::Applicant]),
+        ("NBFCService/GetUploadStatus", vec![Role::Applicant]),
+        ("NBFCService/SubmitCreditProofJob", vec![Role::Applicant]),
+        ("NBFCService/GetJobStatus", vec![Role::Applicant]),
+        ("NBFCService/StreamJobProgress", vec![Role::Applicant]),
+        ("NBFCService/CancelJob", vec![Role::Applicant]),
+        ("BankService/VerifyProofAndDecide", vec![Role::Applicant, Role::Nbfc]),
+        ("DecryptionService/DecryptDecisionInput", vec![Role::Bank, Role::Applicant]),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Signs a token with the issuer's key
+///
+/// Tokens have the form `<institution>.<role>.<hex subject>.<expires_at>.<hex Ed25519 signature>`.
+pub fn issue_token(issuer_key: &SigningKey, claims: &Claims) -> Result<String, Box<dyn Error>> {
+    if claims.institution.is_empty() || claims.institution.contains('.') {
+        return Err(format!("Invalid institution name: {:?}", claims.institution).into());
+    }
+    let payload = claims.payload();
+    let signature = signing::sign(issuer_key, &signed_bytes(&payload));
+    Ok(format!("{}.{}", payload, hex::encode(signature)))
+}
+
+/// Signs a token with the issuer key named by the environment
+///
+/// Only the issuer and the single-process demo hold this key.
+pub fn issue_token_from_env(claims: &Claims) -> Result<String, Box<dyn Error>> {
+    let issuer_key = SigningKeyPaths::from_env(TOKEN_ISSUER_IDENTITY).load_signing_key()?;
+    issue_token(&issuer_key, claims)
+}
+
+/// Checks a token's signature and expiry and returns the principal it names
+pub fn verify_token(issuer_key: &VerifyingKey, token: &str, now: u64) -> Result<Principal, String> {
+    let (payload, signature) = token.rsplit_once('.').ok_or("malformed token")?;
+    let signature = hex::decode(signature).map_err(|_| "malformed token signature")?;
+    signing::verify(issuer_key, &signed_bytes(payload), &signature).map_err(|e| format!("token: {}", e))?;
+
+    let fields: Vec<&str> = payload.split('.').collect();
+    let [institution, role, subject, expires_at] = fields[..] else {
+        return Err("malformed token".into());
+    };
+    let expires_at: u64 = expires_at.parse().map_err(|_| "malformed token expiry")?;
+    if now > expires_at {
+        return Err("token has expired".into());
+    }
+    Ok(Principal {
+        institution: institution.to_string(),
+        role: role.parse()?,
+        subject: hex::decode(subject).map_err(|_| "malformed token subject")?,
+    })
+}
+
+/// Environment variable holding a party's token, e.g. `CREDIT_BANK_TOKEN`
+pub fn token_env(identity: &str) -> String {
+    format!("CREDIT_{}_TOKEN", identity.to_uppercase().replace('-', "_"))
+}
+
+/// Reads the token the issuer gave a party
+///
+/// The token comes from `CREDIT_<IDENTITY>_TOKEN`, or else from `<identity>.token` in the TLS directory.
+/// It is read on every call, so a renewed token takes effect without a restart.
+pub fn token_from_env(identity: &str) -> Result<String, Box<dyn Error>> {
+    if let Ok(token) = std::env::var(token_env(identity)) {
+        return Ok(token);
+    }
+    let path = tls::tls_dir().join(format!("{}.token", identity));
+    let token = fs::read_to_string(&path).map_err(|e| {
+        format!("No token for {}: set {} or write {} ({})", identity, token_env(identity), path.display(), e)
+    })?;
+    Ok(token.trim().to_string())
+}
+
+fn signed_bytes(payload: &str) -> Vec<u8> {
+    let mut bytes = TOKEN_DOMAIN.to_vec();
+    bytes.extend_from_slice(payload.as_bytes());
+    bytes
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// One entry in the authorization audit log
+#[derive(Debug, Serialize)]
+struct AuthAuditRecord<'a> {
+    timestamp: u64,
+    institution: &'a str,
+    role: String,
+    rpc: &'a str,
+    outcome: &'a str,
+}
+
+/// Append-only JSON-lines log of refused calls
+#[derive(Debug)]
+pub struct AuthAuditLog {
+    file: Mutex<File>,
+}
+
+impl AuthAuditLog {
+    /// Opens the log for appending, creating it and its directory if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    fn record(&self, principal: Option<&Principal>, rpc: &str, outcome: &str) -> Result<(), Box<dyn Error>> {
+        let record = AuthAuditRecord {
+            timestamp: unix_now(),
+            institution: principal.map(|p| p.institution.as_str()).unwrap_or(""),
+            role: principal.map(|p| p.role.to_string()).unwrap_or_default(),
+            rpc,
+            outcome,
+        };
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Enforces the per-RPC access policy on authenticated requests
+///
+/// Without an audit log, denials are still enforced but not recorded.
+#[derive(Debug)]
+pub struct Authorizer {
+    policy: HashMap<&'static str, Vec<Role>>,
+    audit_log: Option<AuthAuditLog>,
+}
+
+impl Default for Authorizer {
+    fn default() -> Self {
+        Self {
+            policy: default_access_policy(),
+            audit_log: None,
+        }
+    }
+}
+
+impl Authorizer {
+    /// Creates an authorizer that records every denial in the given log
+    pub fn new(policy: HashMap<&'static str, Vec<Role>>, audit_log: AuthAuditLog) -> Self {
+        Self {
+            policy,
+            audit_log: Some(audit_log),
+        }
+    }
+
+    /// Returns the caller if their role may call `rpc`, denying and auditing otherwise
+    pub fn authorize<T>(&self, request: &Request<T>, rpc: &str) -> Result<Principal, Status> {
+        let principal = match request.extensions().get::<Principal>() {
+            Some(principal) => principal.clone(),
+            None => {
+                self.audit(None, rpc, "denied: unauthenticated")?;
+                return Err(Status::unauthenticated("Request was not authenticated"));
+            }
+        };
+        let allowed = self.policy.get(rpc).map_or(false, |roles| roles.contains(&principal.role));
+        if !allowed {
+            self.audit(Some(&principal), rpc, "denied: role")?;
+            return Err(Status::permission_denied(format!(
+                "Role {} may not call {}",
+                principal.role, rpc
+            )));
+        }
+        Ok(principal)
+    }
+
+    // A denial that cannot be audited is reported as an internal error
+    fn audit(&self, principal: Option<&Principal>, rpc: &str, outcome: &str) -> Result<(), Status> {
+        match &self.audit_log {
+            Some(log) => log
+                .record(principal, rpc, outcome)
+                .map_err(|e| Status::internal(format!("Failed to write audit log: {}", e))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Server interceptor that authenticates bearer tokens
+///
+/// A valid token's principal is attached to the request for `Authorizer::authorize`.
+/// When the caller presented a client certificate, the token must name the same institution.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    issuer_key: VerifyingKey,
+    authorizer: Arc<Authorizer>,
+}
+
+impl AuthInterceptor {
+    /// Creates an interceptor verifying tokens with the issuer's public key from the environment
+    ///
+    /// The service holds only the verifying key, so it cannot mint tokens for any party.
+    pub fn from_env(authorizer: Arc<Authorizer>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            issuer_key: SigningKeyPaths::from_env(TOKEN_ISSUER_IDENTITY).load_verifying_key()?,
+            authorizer,
+        })
+    }
+
+    fn authenticate(&self, request: &Request<()>) -> Result<Principal, String> {
+        let header = request
+            .metadata()
+            .get(AUTHORIZATION_HEADER)
+            .ok_or("missing authorization header")?
+            .to_str()
+            .map_err(|_| "malformed authorization header")?;
+        let token = header.strip_prefix(BEARER_PREFIX).ok_or("expected a bearer token")?;
+        let principal = verify_token(&self.issuer_key, token, unix_now())?;
+
+        if request.peer_certs().is_some() {
+            let identity = tls::peer_identity(request).map_err(|e| e.message().to_string())?;
+            if identity != principal.institution {
+                return Err(format!(
+                    "token names {} but the client certificate names {}",
+                    principal.institution, identity
+                ));
+            }
+        }
+        Ok(principal)
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        match self.authenticate(&request) {
+            Ok(principal) => {
+                request.extensions_mut().insert(principal);
+                Ok(request)
+            }
+            Err(reason) => {
+                self.authorizer.audit(None, "", &format!("denied: {}", reason))?;
+                Err(Status::unauthenticated(reason))
+            }
+        }
+    }
+}
+
+/// Client interceptor that attaches a bearer token to every call
+#[derive(Debug, Clone)]
+pub struct BearerToken(pub String);
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let value: MetadataValue<_> = format!("{}{}", BEARER_PREFIX, self.0)
+            .parse()
+            .map_err(|_| Status::internal("Token is not valid header text"))?;
+        request.metadata_mut().insert(AUTHORIZATION_HEADER, value);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuer(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn claims(expires_at: u64) -> Claims {
+        Claims {
+            institution: "applicant".into(),
+            role: Role::Applicant,
+            subject: vec![7; 32],
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn issued_token_names_its_principal() {
+        let token = issue_token(&issuer(1), &claims(2_000)).unwrap();
+        let principal = verify_token(&issuer(1).verifying_key(), &token, 1_000).unwrap();
+        assert_eq!(
+            principal,
+            Principal { institution: "applicant".into(), role: Role::Applicant, subject: vec![7; 32] }
+        );
+    }
+
+    #[test]
+    fn expired_foreign_or_altered_tokens_are_refused() {
+        let token = issue_token(&issuer(1), &claims(2_000)).unwrap();
+        assert_eq!(verify_token(&issuer(1).verifying_key(), &token, 2_001), Err("token has expired".into()));
+        assert!(verify_token(&issuer(2).verifying_key(), &token, 1_000).is_err());
+
+        let promoted = token.replacen("applicant.applicant", "applicant.auditor", 1);
+        assert!(verify_token(&issuer(1).verifying_key(), &promoted, 1_000).is_err());
+        let extended = token.replacen(".2000.", ".9000.", 1);
+        assert!(verify_token(&issuer(1).verifying_key(), &extended, 1_000).is_err());
+    }
+
+    #[test]
+    fn applicants_act_only_for_their_own_user() {
+        let applicant = Principal { institution: "applicant".into(), role: Role::Applicant, subject: vec![7; 32] };
+        assert_eq!(applicant.user_id_hash(&[]), Ok(vec![7; 32]));
+        assert_eq!(applicant.user_id_hash(&[7; 32]), Ok(vec![7; 32]));
+        assert!(matches!(applicant.user_id_hash(&[8; 32]), Err(CreditError::PermissionDenied(_))));
+
+        let institution = Principal { subject: Vec::new(), ..applicant };
+        assert!(matches!(institution.user_id_hash(&[7; 32]), Err(CreditError::PermissionDenied(_))));
+
+        let auditor = Principal { institution: "auditor".into(), role: Role::Auditor, subject: Vec::new() };
+        assert_eq!(auditor.user_id_hash(&[8; 32]), Ok(vec![8; 32]));
+        assert!(auditor.user_id_hash(&[]).is_err());
+    }
+}
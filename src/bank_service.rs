@@ -1,98 +1,453 @@
-use tonic::{transport::Server, Request, Response, Status};
-use std::sync::Arc;
-
-// Generate the server code from our proto definition
-pub mod credit_evaluation {
-    tonic::include_proto!("credit_evaluation");
-}
-
-use credit_evaluation::bank_service_server::{BankService, BankServiceServer};
-use credit_evaluation::{CreditProofRequest, LoanDecisionResponse};
-
-// Implementation of our Bank service
-#[derive(Debug, Default)]
-pub struct BankServiceImpl {}
-
-#[tonic::async_trait]
-impl BankService for BankServiceImpl {
-    async fn verify_proof_and_decide(
-        &self,
-        request: Request<CreditProofRequest>,
-    ) -> Result<Response<LoanDecisionResponse>, Status> {
-        let req = request.into_inner();
-        
-        // Step 1: Verify the ZK proof
-        let proof_valid = verify_zk_proof(&req.zkp_proof, &req.public_inputs, &req.nonce)
-            .map_err(|e| Status::internal(format!("Failed to verify proof: {}", e)))?;
-        
-        if !proof_valid {
-            return Ok(Response::new(LoanDecisionResponse {
-                eligible: false,
-                reason: "Proof verification failed".into(),
-                credit_score: 0,
-            }));
-        }
-        
-        // Step 2: Decode and validate public inputs
-        let threshold = req.threshold;
-        
-        // Step 3: Process encrypted average expense
-        // Note: In this design, the encrypted average expense would be processed by another trusted component
-        // that has access to the necessary decryption keys. Here we're simplifying by assuming the
-        // encrypted_avg_expense has already been processed and we have the result.
-        let expense_ratio = 0.3; // Placeholder for the actual expense ratio calculation
-        
-        // Step 4: Make loan decision
-        let max_expense_ratio = (req.max_expense_ratio as f64) / 100.0;
-        let eligible = expense_ratio <= max_expense_ratio;
-        
-        // Step 5: Calculate credit score (simplified calculation)
-        let credit_score = if eligible {
-            // Simple scoring model based on expense ratio
-            let base_score = 700;
-            let adjustment = ((1.0 - expense_ratio / max_expense_ratio) * 100.0) as u32;
-            base_score + adjustment.min(100)
-        } else {
-            // Below threshold score
-            600
-        };
-        
-        // Step 6: Prepare response
-        let reason = if eligible {
-            "Meets all criteria for loan approval".into()
-        } else {
-            "Expense ratio exceeds maximum allowed".into()
-        };
-        
-        let response = LoanDecisionResponse {
-            eligible,
-            reason,
-            credit_score,
-        };
-        
-        Ok(Response::new(response))
-    }
-}
-
-// Helper Functions
-
-fn verify_zk_proof(proof: &[u8], public_inputs: &[u8], nonce: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
-    // In a real implementation, this would verify a ZK proof
-    // For this example, we'll always return true
-    Ok(true)
-}
-
-// Start the Bank server
-pub async fn start_bank_server() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::1]:50052".parse()?;
-    let service = BankServiceImpl::default();
-    
-    println!("Bank Server listening on {}", addr);
-    
-    Server::builder()
-        .add_service(BankServiceServer::new(service))
-        .serve(addr)
-        .await?;
-    
-    Ok(())
-}
\ No newline at end of file
+use tonic::{transport::Server, Request, Response, Status};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use ed25519_dalek::VerifyingKey;
+use tfhe::shortint::Ciphertext;
+
+// Generate the server code from our proto definition
+pub mod credit_evaluation {
+    tonic::include_proto!("credit_evaluation");
+}
+
+use credit_evaluation::bank_service_server::{BankService, BankServiceServer};
+use credit_evaluation::{CreditProofRequest, LoanDecisionResponse, ReasonCode};
+
+use crate::decryption_service::credit_evaluation::decryption_service_client::DecryptionServiceClient;
+use crate::decryption_service::credit_evaluation::{CiphertextKind, DecisionInputRequest};
+use crate::attestation::{self, DecisionAttestation, DecisionStatement};
+use crate::decryption_service::{self, DecryptionPolicy};
+use crate::error::CreditError;
+use crate::fhe_utils;
+use crate::nbfc_service::credit_evaluation::nbfc_service_client::NbfcServiceClient;
+use crate::nbfc_service::credit_evaluation::{FetchProofBundleRequest, PartialDecryptionRequest, SharedProofBundle};
+use crate::threshold_decryption::{self, KeyShare, PartialDecryption};
+use crate::tls::{self, TlsPaths};
+use crate::config::{AppConfig, PolicyConfig, ScoringConfig};
+use crate::auth::{self, AuthAuditLog, AuthInterceptor, Authorizer, BearerToken, Role};
+use crate::signing::SigningKeyPaths;
+use crate::zk_salary_circuit;
+
+// Implementation of our Bank service
+#[derive(Debug)]
+pub struct BankServiceImpl {
+    decryption_service_addr: String,
+    tls_paths: TlsPaths,
+    authorizer: Arc<Authorizer>,
+    policy: PolicyConfig,
+    scoring: ScoringConfig,
+    /// Key the NBFC signs application outputs with
+    nbfc_verifying_key: Option<VerifyingKey>,
+    /// The Bank's share of a split client key; with it the Bank decrypts jointly with the NBFC
+    key_share: Option<Arc<KeyShare>>,
+    /// How the Bank derives decision inputs from jointly decrypted values
+    decryption_policy: DecryptionPolicy,
+    nbfc_addr: String,
+    /// Nonces of proofs the Bank has already decided on
+    seen_nonces: Mutex<SeenNonces>,
+}
+
+/// How long the Bank remembers the nonce of a proof it has decided on
+pub const NONCE_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Nonces the Bank has decided on, forgotten once older than the retention window
+#[derive(Debug)]
+struct SeenNonces {
+    retention: Duration,
+    nonces: HashSet<Vec<u8>>,
+    /// Nonces in the order they were first seen, oldest first
+    order: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl SeenNonces {
+    fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            nonces: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Records a nonce, returning false if it was already seen within the retention window
+    fn insert(&mut self, nonce: &[u8], now: Instant) -> bool {
+        while let Some((seen_at, _)) = self.order.front() {
+            if now.duration_since(*seen_at) < self.retention {
+                break;
+            }
+            let (_, expired) = self.order.pop_front().unwrap();
+            self.nonces.remove(&expired);
+        }
+        if !self.nonces.insert(nonce.to_vec()) {
+            return false;
+        }
+        self.order.push_back((now, nonce.to_vec()));
+        true
+    }
+
+    /// Forgets a nonce, so a proof whose decision could not be made may be presented again
+    fn remove(&mut self, nonce: &[u8]) {
+        if self.nonces.remove(nonce) {
+            self.order.retain(|(_, seen)| seen != nonce);
+        }
+    }
+}
+
+impl Default for BankServiceImpl {
+    fn default() -> Self {
+        Self {
+            decryption_service_addr: decryption_service::DEFAULT_DECRYPTION_SERVICE_ADDR.to_string(),
+            tls_paths: TlsPaths::from_env(tls::BANK_IDENTITY),
+            authorizer: Arc::new(Authorizer::default()),
+            policy: PolicyConfig::default(),
+            scoring: ScoringConfig::default(),
+            nbfc_verifying_key: None,
+            key_share: None,
+            decryption_policy: DecryptionPolicy::default(),
+            nbfc_addr: String::new(),
+            seen_nonces: Mutex::new(SeenNonces::new(NONCE_RETENTION)),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl BankService for BankServiceImpl {
+    async fn verify_proof_and_decide(
+        &self,
+        request: Request<CreditProofRequest>,
+    ) -> Result<Response<LoanDecisionResponse>, Status> {
+        let principal = self.authorizer.authorize(&request, "BankService/VerifyProofAndDecide")?;
+        let req = request.into_inner();
+        
+        // Step 1: Fetch the published bundle from the NBFC's registry, which refuses revoked,
+        // expired and unshared bundles, so a withdrawn proof cannot be presented again
+        if req.proof_id.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing proof ID".into()).into());
+        }
+        let bundle = self.fetch_bundle(&req.proof_id).await?;
+        // An applicant may only ask for a decision on a bundle they published
+        if principal.role == Role::Applicant {
+            principal.user_id_hash(&bundle.user_id_hash)?;
+        }
+        
+        let nonce = zk_salary_circuit::nonce_from_bytes(&bundle.nonce)
+            .map_err(CreditError::InvalidArgument)?;
+        
+        // Step 2: Verify the ZK proof
+        let proof_valid = verify_zk_proof(&bundle.zkp_proof, bundle.threshold, &bundle.salary_commitment, &nonce)
+            .map_err(|e| CreditError::ProofInvalid(format!("Failed to verify proof: {}", e)))?;
+        
+        if !proof_valid {
+            return Ok(Response::new(declined(ReasonCode::ProofInvalid, "Proof verification failed".into())));
+        }
+        
+        // The NBFC must vouch that the ciphertexts are its outputs for this proof, and recently
+        // enough that the nonce is still remembered
+        let statement = self.check_attestation(&bundle)?;
+        
+        // Step 3: Check the proof and ratio were requested under the Bank's lending policy;
+        // the ratio limit is the one the NBFC signed, not one the caller restates
+        let max_expense_ratio = statement.max_expense_ratio;
+        if bundle.threshold < self.policy.threshold {
+            return Ok(Response::new(declined(
+                ReasonCode::ThresholdBelowPolicy,
+                format!("Salary threshold is below the Bank's minimum of {}", self.policy.threshold),
+            )));
+        }
+        if max_expense_ratio > self.policy.max_expense_ratio {
+            return Ok(Response::new(declined(
+                ReasonCode::RatioLimitAbovePolicy,
+                format!("Expense ratio limit exceeds the Bank's maximum of {}%", self.policy.max_expense_ratio),
+            )));
+        }
+        
+        // Each proof is bound to a fresh nonce, so a repeated nonce means a replayed proof.
+        // The nonce is reserved before decrypting so concurrent requests cannot both use it.
+        if !self.seen_nonces.lock().unwrap().insert(&nonce, Instant::now()) {
+            return Err(CreditError::Replay.into());
+        }
+        
+        // Step 4: Resolve the encrypted decision inputs
+        // Note: The designated key holder decrypts each ciphertext and returns only the derived
+        // input: the ratio bucket, the affordability bit and the average expense band. With a
+        // split key the Bank and the NBFC decrypt these outputs jointly instead. Either way the
+        // Bank never sees the underlying salary or expenses.
+        let (ratio_bucket, avg_expense_band, affordable) = match self.resolve_decision_inputs(&bundle).await {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                // No decision was made, so the applicant may present the proof again
+                self.seen_nonces.lock().unwrap().remove(&nonce);
+                return Err(e.into());
+            }
+        };
+        
+        // Step 5: Make loan decision
+        // The Bank only learns whether the requested EMI is affordable, never the offer itself
+        let within_ratio = ratio_bucket != fhe_utils::RATIO_BUCKET_EXCEEDED;
+        let eligible = within_ratio && affordable;
+        
+        // Step 6: Calculate credit score (simplified calculation)
+        let credit_score = match ratio_bucket {
+            // Requested EMI is not affordable
+            _ if !affordable => self.scoring.declined_score,
+            // Expenses within half of the allowed ratio
+            fhe_utils::RATIO_BUCKET_LOW => self.scoring.low_ratio_score,
+            // Expenses within the allowed ratio
+            fhe_utils::RATIO_BUCKET_WITHIN_LIMIT => self.scoring.within_limit_score,
+            // Below threshold score
+            _ => self.scoring.declined_score,
+        };
+        // Higher average expense bands lower the score of an otherwise eligible applicant
+        let credit_score = if eligible {
+            credit_score.saturating_sub(avg_expense_band as u32 * self.scoring.avg_expense_band_penalty)
+        } else {
+            credit_score
+        };
+        
+        // Step 7: Prepare response
+        let (reason_code, reason) = if eligible {
+            (ReasonCode::Approved, "Meets all criteria for loan approval".into())
+        } else if !within_ratio {
+            (
+                ReasonCode::ExpenseRatioExceeded,
+                format!("Expense ratio exceeds maximum allowed ({}%)", max_expense_ratio),
+            )
+        } else {
+            (ReasonCode::EmiNotAffordable, "Requested EMI exceeds affordable EMI".into())
+        };
+        
+        let response = LoanDecisionResponse {
+            eligible,
+            reason,
+            credit_score,
+            reason_code: reason_code as i32,
+        };
+        
+        Ok(Response::new(response))
+    }
+}
+
+// Helper Functions
+
+fn declined(reason_code: ReasonCode, reason: String) -> LoanDecisionResponse {
+    LoanDecisionResponse {
+        eligible: false,
+        reason,
+        credit_score: 0,
+        reason_code: reason_code as i32,
+    }
+}
+
+fn verify_zk_proof(
+    proof: &[u8],
+    threshold: u64,
+    salary_commitment: &[u8],
+    nonce: &zk_salary_circuit::ProofNonce,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    // The proof must be over the requested threshold, the salary the NBFC linked to the
+    // ciphertexts and the nonce being checked for replay
+    let commitment = zk_salary_circuit::commitment_from_bytes(salary_commitment)?;
+    Ok(zk_salary_circuit::verify_committed_salary_threshold_proof(proof, threshold, &commitment, nonce)?)
+}
+
+impl BankServiceImpl {
+    /// Fetches a bundle the applicant shared with the Bank from the NBFC's proof registry
+    ///
+    /// The registry's refusal, such as a revoked or expired bundle, is passed on unchanged.
+    async fn fetch_bundle(&self, proof_id: &[u8]) -> Result<SharedProofBundle, Status> {
+        let channel = tls::connect(&self.nbfc_addr, &self.tls_paths)
+            .await
+            .map_err(|e| CreditError::internal("connect to the NBFC", e))?;
+        let token = auth::token_from_env(tls::BANK_IDENTITY).map_err(|e| CreditError::internal("load the Bank's token", e))?;
+        let mut client = NbfcServiceClient::with_interceptor(channel, BearerToken(token));
+        let bundle = client
+            .fetch_proof_bundle(Request::new(FetchProofBundleRequest { proof_id: proof_id.to_vec() }))
+            .await?
+            .into_inner();
+        Ok(bundle)
+    }
+    
+    /// Checks the NBFC's signed statement covers this bundle's proof and ciphertexts
+    fn check_attestation(&self, bundle: &SharedProofBundle) -> Result<DecisionStatement, CreditError> {
+        let key = self.nbfc_verifying_key
+            .as_ref()
+            .ok_or_else(|| CreditError::Internal("Bank has no NBFC verifying key".into()))?;
+        let statement = DecisionAttestation::open(&bundle.decision_attestation, key)?;
+        if statement.nonce != bundle.nonce
+            || statement.key_id != bundle.key_id
+            || statement.threshold != bundle.threshold
+            || statement.salary_commitment != bundle.salary_commitment
+        {
+            return Err(CreditError::ProofInvalid("NBFC attestation does not cover this proof".into()));
+        }
+        statement.check_fresh(attestation::now_unix(), NONCE_RETENTION)?;
+        statement.check_ciphertext(CiphertextKind::RatioBucket, std::slice::from_ref(&bundle.encrypted_ratio_bucket))?;
+        statement.check_ciphertext(
+            CiphertextKind::AffordabilityCheck,
+            std::slice::from_ref(&bundle.encrypted_affordability_check),
+        )?;
+        statement.check_ciphertext(CiphertextKind::AvgExpense, &bundle.encrypted_avg_expense_blocks)?;
+        Ok(statement)
+    }
+    
+    /// Resolves the ratio bucket, the average expense band and whether the EMI is affordable
+    async fn resolve_decision_inputs(&self, bundle: &SharedProofBundle) -> Result<(u64, u64, bool), CreditError> {
+        let ratio_bucket = self
+            .request_decision_input(CiphertextKind::RatioBucket, std::slice::from_ref(&bundle.encrypted_ratio_bucket), bundle)
+            .await
+            .map_err(|e| CreditError::internal("resolve expense ratio", e))?;
+        let avg_expense_band = self
+            .request_decision_input(CiphertextKind::AvgExpense, &bundle.encrypted_avg_expense_blocks, bundle)
+            .await
+            .map_err(|e| CreditError::internal("resolve average expense", e))?;
+        let affordable = self
+            .request_decision_input(CiphertextKind::AffordabilityCheck, std::slice::from_ref(&bundle.encrypted_affordability_check), bundle)
+            .await
+            .map_err(|e| CreditError::internal("resolve affordability", e))?
+            == 1;
+        Ok((ratio_bucket, avg_expense_band, affordable))
+    }
+    
+    /// Resolves the decision input derived from a ciphertext's radix blocks
+    ///
+    /// With a key share the Bank decrypts jointly with the NBFC and derives the input itself;
+    /// otherwise it asks the designated key holder.
+    async fn request_decision_input(
+        &self,
+        kind: CiphertextKind,
+        ciphertext_blocks: &[Vec<u8>],
+        bundle: &SharedProofBundle,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        if let Some(key_share) = &self.key_share {
+            let plaintext =
+                decrypt_jointly_with_nbfc(ciphertext_blocks, &bundle.key_id, key_share, &self.nbfc_addr, &self.tls_paths).await?;
+            return Ok(self.decryption_policy.derive(kind, plaintext));
+        }
+        
+        let channel = tls::connect(&self.decryption_service_addr, &self.tls_paths).await?;
+        let token = BearerToken(auth::token_from_env(tls::BANK_IDENTITY)?);
+        let mut client = DecryptionServiceClient::with_interceptor(channel, token);
+        let response = client
+            .decrypt_decision_input(Request::new(DecisionInputRequest {
+                kind: kind as i32,
+                ciphertext_blocks: ciphertext_blocks.to_vec(),
+                key_id: bundle.key_id.clone(),
+                decision_attestation: bundle.decision_attestation.clone(),
+            }))
+            .await?
+            .into_inner();
+        Ok(response.value)
+    }
+}
+
+/// Decrypts a radix value block by block jointly with the NBFC; neither party can decrypt it alone
+///
+/// The NBFC only contributes partial decryptions for blocks it released itself.
+pub async fn decrypt_jointly_with_nbfc(
+    ciphertext_blocks: &[Vec<u8>],
+    key_id: &str,
+    bank_share: &KeyShare,
+    nbfc_addr: &str,
+    bank_tls: &TlsPaths,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let token = BearerToken(auth::token_from_env(tls::BANK_IDENTITY)?);
+    let mut nbfc_client = NbfcServiceClient::with_interceptor(tls::connect(nbfc_addr, bank_tls).await?, token);
+    
+    let mut digits = Vec::with_capacity(ciphertext_blocks.len());
+    let mut message_modulus = 0;
+    for block in ciphertext_blocks {
+        let ct: Ciphertext = bincode::deserialize(block)?;
+        message_modulus = ct.message_modulus.0 as u64;
+        let bank_partial = bank_share.partial_decrypt(&ct)?;
+        let response = nbfc_client
+            .partial_decrypt(Request::new(PartialDecryptionRequest {
+                ciphertext: block.clone(),
+                key_id: key_id.to_string(),
+            }))
+            .await?
+            .into_inner();
+        let nbfc_partial: PartialDecryption = bincode::deserialize(&response.partial_decryption)?;
+        digits.push(threshold_decryption::combine_partial_decryptions(&ct, &[bank_partial, nbfc_partial])?);
+    }
+    if digits.is_empty() {
+        return Err("Missing ciphertext blocks".into());
+    }
+    
+    threshold_decryption::combine_radix_digits(&digits, message_modulus)
+}
+
+// Start the Bank server
+pub async fn start_bank_server(config: AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = config.services.bank_addr.parse()?;
+    // The Bank's share of a split client key, if decision inputs are decrypted jointly
+    let key_share = match std::env::var(threshold_decryption::BANK_KEY_SHARE_ENV) {
+        Ok(path) => Some(Arc::new(KeyShare::load(path)?)),
+        Err(_) => None,
+    };
+    let authorizer = Arc::new(Authorizer::new(
+        auth::default_access_policy(),
+        AuthAuditLog::open(auth::DEFAULT_AUTH_AUDIT_LOG_PATH)?,
+    ));
+    let service = BankServiceImpl {
+        decryption_service_addr: config.services.decryption_url(),
+        authorizer: authorizer.clone(),
+        nbfc_verifying_key: Some(SigningKeyPaths::from_env(tls::NBFC_IDENTITY).load_verifying_key()?),
+        key_share,
+        decryption_policy: DecryptionPolicy {
+            avg_expense_noise_offset: config.privacy.noise_offset,
+            ..Default::default()
+        },
+        nbfc_addr: config.services.nbfc_url(),
+        policy: config.policy,
+        scoring: config.scoring,
+        ..Default::default()
+    };
+    
+    println!("Bank Server listening on {}", addr);
+    
+    Server::builder()
+        .tls_config(tls::server_tls_config(&TlsPaths::from_env(tls::BANK_IDENTITY))?)?
+        .add_service(BankServiceServer::with_interceptor(service, AuthInterceptor::from_env(authorizer)?))
+        .serve(addr)
+        .await?;
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_nonce_is_rejected_within_retention() {
+        let mut seen = SeenNonces::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(seen.insert(&[1; 32], start));
+        assert!(!seen.insert(&[1; 32], start + Duration::from_secs(59)));
+        assert!(seen.insert(&[2; 32], start + Duration::from_secs(59)));
+    }
+
+    #[test]
+    fn nonces_are_evicted_after_retention() {
+        let mut seen = SeenNonces::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(seen.insert(&[1; 32], start));
+        assert!(seen.insert(&[2; 32], start + Duration::from_secs(30)));
+
+        assert!(seen.insert(&[3; 32], start + Duration::from_secs(61)));
+        assert_eq!(seen.nonces.len(), 2);
+        assert!(!seen.nonces.contains(&vec![1; 32]));
+    }
+
+    #[test]
+    fn removed_nonce_may_be_presented_again() {
+        let mut seen = SeenNonces::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(seen.insert(&[1; 32], start));
+        assert!(seen.insert(&[2; 32], start));
+        seen.remove(&[1; 32]);
+
+        assert!(seen.insert(&[1; 32], start + Duration::from_secs(1)));
+        assert!(!seen.insert(&[2; 32], start + Duration::from_secs(1)));
+        assert_eq!(seen.order.len(), 2);
+    }
+}
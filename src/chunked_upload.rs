@@ -0,0 +1,612 @@
+use prost::Message;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+
+use crate::auth::BearerToken;
+use crate::nbfc_service::credit_evaluation::nbfc_service_client::NbfcServiceClient;
+use crate::nbfc_service::credit_evaluation::{
+    BeginUploadRequest, EncryptedFinancialRequest, PayloadKind, UploadChunk, UploadPart, UploadStatusRequest, UploadStatusResponse,
+};
+use crate::proof_registry;
+use crate::signing;
+
+/// Largest chunk a client may send, well below gRPC message size limits
+pub const MAX_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Largest total size of one upload
+pub const MAX_UPLOAD_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Unfinished or untaken uploads one caller may hold at a time
+pub const MAX_UPLOADS_PER_OWNER: usize = 4;
+
+/// Directory the NBFC spools upload parts to
+pub const DEFAULT_UPLOAD_DIR: &str = "uploads";
+
+/// Extension of spooled part files, so stale ones can be told apart from anything else
+const PART_FILE_EXTENSION: &str = "part";
+
+/// Time after which an unfinished upload is discarded
+pub const UPLOAD_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Times a client resumes an interrupted upload before giving up
+const MAX_RESUME_ATTEMPTS: usize = 3;
+
+/// Reasons the upload store refuses a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadError {
+    NotFound,
+    NotOwner,
+    Incomplete,
+    KeyMismatch { expected: String },
+    InvalidManifest(String),
+    InvalidChunk(String),
+    HashMismatch { part: usize },
+    TooManyUploads { limit: usize },
+    Storage(String),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::NotFound => write!(f, "upload not found"),
+            UploadError::NotOwner => write!(f, "upload belongs to another caller"),
+            UploadError::Incomplete => write!(f, "upload is not complete"),
+            UploadError::KeyMismatch { expected } => write!(f, "upload was started for key {}", expected),
+            UploadError::InvalidManifest(reason) => write!(f, "invalid upload manifest: {}", reason),
+            UploadError::InvalidChunk(reason) => write!(f, "invalid chunk: {}", reason),
+            UploadError::HashMismatch { part } => {
+                write!(f, "part {} does not match its SHA-256 and must be uploaded again", part)
+            }
+            UploadError::TooManyUploads { limit } => {
+                write!(f, "at most {} uploads may be in progress at once; finish or abandon one first", limit)
+            }
+            UploadError::Storage(reason) => write!(f, "upload storage failed: {}", reason),
+        }
+    }
+}
+
+impl Error for UploadError {}
+
+/// Progress of one upload, as reported to the client
+#[derive(Debug, Clone)]
+pub struct UploadProgress {
+    pub upload_id: String,
+    /// Bytes received so far for each part, in manifest order
+    pub received_bytes: Vec<u64>,
+    pub complete: bool,
+}
+
+/// Payloads of a completed upload, ready for evaluation
+#[derive(Debug, Default)]
+pub struct AssembledUpload {
+    pub server_key: Option<Vec<u8>>,
+    /// Encoded `CategorizedExpense` messages, in index order
+    pub expenses: Vec<Vec<u8>>,
+}
+
+/// One part's bytes, spooled to a file as they arrive
+#[derive(Debug)]
+struct PartState {
+    manifest: UploadPart,
+    path: PathBuf,
+    received: u64,
+    /// Hash of the bytes received so far, so completing a part never re-reads it
+    hasher: Sha256,
+}
+
+impl PartState {
+    fn complete(&self) -> bool {
+        self.received == self.manifest.size
+    }
+
+    /// Reads back `len` bytes already received, starting at `offset`
+    fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0; len];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        OpenOptions::new().append(true).open(&self.path)?.write_all(data)?;
+        self.received += data.len() as u64;
+        self.hasher.update(data);
+        Ok(())
+    }
+
+    fn matches_manifest(&self) -> bool {
+        self.hasher.clone().finalize().as_slice() == self.manifest.sha256.as_slice()
+    }
+
+    fn discard(&mut self) -> io::Result<()> {
+        OpenOptions::new().write(true).open(&self.path)?.set_len(0)?;
+        self.received = 0;
+        self.hasher = Sha256::new();
+        Ok(())
+    }
+}
+
+fn progress(upload_id: &str, parts: &[PartState]) -> UploadProgress {
+    UploadProgress {
+        upload_id: upload_id.to_string(),
+        received_bytes: parts.iter().map(|part| part.received).collect(),
+        complete: parts.iter().all(PartState::complete),
+    }
+}
+
+#[derive(Debug)]
+struct Upload {
+    owner: String,
+    key_id: String,
+    started_at: u64,
+    /// Locked per upload, so one upload's file I/O never holds up another's
+    parts: Mutex<Vec<PartState>>,
+}
+
+impl Drop for Upload {
+    // An upload's spool files go with it, whether it is taken, expires or fails to start
+    fn drop(&mut self) {
+        let parts = self.parts.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for part in parts.iter() {
+            let _ = fs::remove_file(&part.path);
+        }
+    }
+}
+
+/// Store assembling chunked uploads on the NBFC, spooling their parts to disk
+///
+/// The store-wide lock only guards the map of uploads; part files are read and
+/// written under each upload's own lock.
+#[derive(Debug)]
+pub struct UploadStore {
+    dir: PathBuf,
+    uploads: Mutex<HashMap<String, Arc<Upload>>>,
+}
+
+impl Default for UploadStore {
+    /// A store spooling to a per-process directory under the system temp directory
+    fn default() -> Self {
+        Self {
+            dir: std::env::temp_dir().join(format!("credit-uploads-{}", std::process::id())),
+            uploads: Mutex::default(),
+        }
+    }
+}
+
+impl UploadStore {
+    /// Opens a store spooling to `dir`, deleting parts left behind by a previous run
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == PART_FILE_EXTENSION) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(Self {
+            dir,
+            uploads: Mutex::default(),
+        })
+    }
+
+    /// Starts an upload of the parts described by the manifest
+    pub fn begin(&self, owner: &str, key_id: &str, manifest: Vec<UploadPart>) -> Result<UploadProgress, UploadError> {
+        validate_manifest(&manifest)?;
+
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let upload_id = hex::encode(id);
+        let now = proof_registry::unix_now();
+        let mut upload = Upload {
+            owner: owner.to_string(),
+            key_id: key_id.to_string(),
+            started_at: now,
+            parts: Mutex::new(Vec::with_capacity(manifest.len())),
+        };
+        fs::create_dir_all(&self.dir).map_err(storage_error)?;
+        let parts = upload.parts.get_mut().unwrap();
+        for (index, manifest) in manifest.into_iter().enumerate() {
+            let path = self.dir.join(format!("{}.{}.{}", upload_id, index, PART_FILE_EXTENSION));
+            signing::write_private_file(&path, &[]).map_err(storage_error)?;
+            parts.push(PartState { manifest, path, received: 0, hasher: Sha256::new() });
+        }
+        let progress = progress(&upload_id, parts);
+
+        // Expired and refused uploads remove their files once dropped, after the lock is released
+        let mut expired = Vec::new();
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.retain(|_, upload| {
+            let live = now < upload.started_at + UPLOAD_LIFETIME.as_secs();
+            if !live {
+                expired.push(upload.clone());
+            }
+            live
+        });
+        if uploads.values().filter(|upload| upload.owner == owner).count() >= MAX_UPLOADS_PER_OWNER {
+            return Err(UploadError::TooManyUploads { limit: MAX_UPLOADS_PER_OWNER });
+        }
+        uploads.insert(upload_id, Arc::new(upload));
+        Ok(progress)
+    }
+
+    /// Looks up an upload the caller owns, holding the store-wide lock only for the lookup
+    fn get(&self, owner: &str, upload_id: &str) -> Result<Arc<Upload>, UploadError> {
+        let upload = self.uploads.lock().unwrap().get(upload_id).cloned().ok_or(UploadError::NotFound)?;
+        if upload.owner != owner {
+            return Err(UploadError::NotOwner);
+        }
+        Ok(upload)
+    }
+
+    /// Appends a chunk at the offset the part has reached
+    ///
+    /// A chunk that repeats bytes already received is accepted only if it matches them,
+    /// so a resumed client may safely resend its last chunk.
+    pub fn append(&self, owner: &str, chunk: &UploadChunk) -> Result<UploadProgress, UploadError> {
+        if chunk.data.len() > MAX_CHUNK_BYTES {
+            return Err(UploadError::InvalidChunk(format!("chunks may hold at most {} bytes", MAX_CHUNK_BYTES)));
+        }
+        let upload = self.get(owner, &chunk.upload_id)?;
+        let mut parts = upload.parts.lock().unwrap();
+        let part_index = chunk.part as usize;
+        let part = parts
+            .get_mut(part_index)
+            .ok_or_else(|| UploadError::InvalidChunk(format!("no part {}", chunk.part)))?;
+
+        let received = part.received;
+        let end = chunk.offset.saturating_add(chunk.data.len() as u64);
+        if end <= received {
+            if part.read_range(chunk.offset, chunk.data.len()).map_err(storage_error)? != chunk.data {
+                return Err(UploadError::InvalidChunk(format!(
+                    "part {} bytes {}..{} differ from those already received",
+                    chunk.part, chunk.offset, end
+                )));
+            }
+            return Ok(progress(&chunk.upload_id, &parts));
+        }
+        if chunk.offset != received {
+            return Err(UploadError::InvalidChunk(format!(
+                "part {} expects offset {}, got {}",
+                chunk.part, received, chunk.offset
+            )));
+        }
+        if end > part.manifest.size {
+            return Err(UploadError::InvalidChunk(format!(
+                "part {} is {} bytes, chunk ends at {}",
+                chunk.part, part.manifest.size, end
+            )));
+        }
+        part.append(&chunk.data).map_err(storage_error)?;
+
+        // A corrupted part is discarded so the client can upload it again
+        if part.complete() && !part.matches_manifest() {
+            part.discard().map_err(storage_error)?;
+            return Err(UploadError::HashMismatch { part: part_index });
+        }
+        Ok(progress(&chunk.upload_id, &parts))
+    }
+
+    /// Reports how far an upload has progressed, for resuming it
+    pub fn status(&self, owner: &str, upload_id: &str) -> Result<UploadProgress, UploadError> {
+        let upload = self.get(owner, upload_id)?;
+        let parts = upload.parts.lock().unwrap();
+        Ok(progress(upload_id, &parts))
+    }
+
+    /// Removes a completed upload and returns its payloads
+    pub fn take(&self, owner: &str, upload_id: &str, key_id: &str) -> Result<AssembledUpload, UploadError> {
+        let upload = self.get(owner, upload_id)?;
+        if upload.key_id != key_id {
+            return Err(UploadError::KeyMismatch {
+                expected: upload.key_id.clone(),
+            });
+        }
+        // The upload's lock keeps chunks from landing while it is checked and read back
+        let parts = upload.parts.lock().unwrap();
+        if !parts.iter().all(PartState::complete) {
+            return Err(UploadError::Incomplete);
+        }
+        // Of two concurrent takes, only the one that removes the upload goes on
+        self.uploads.lock().unwrap().remove(upload_id).ok_or(UploadError::NotFound)?;
+
+        let mut assembled = AssembledUpload::default();
+        for part in parts.iter() {
+            let data = fs::read(&part.path).map_err(storage_error)?;
+            match PayloadKind::from_i32(part.manifest.kind) {
+                Some(PayloadKind::ServerKey) => assembled.server_key = Some(data),
+                _ => assembled.expenses.push(data),
+            }
+        }
+        Ok(assembled)
+    }
+}
+
+fn storage_error(error: impl fmt::Display) -> UploadError {
+    UploadError::Storage(error.to_string())
+}
+
+fn validate_manifest(manifest: &[UploadPart]) -> Result<(), UploadError> {
+    if manifest.is_empty() {
+        return Err(UploadError::InvalidManifest("no parts".into()));
+    }
+    let mut total: u64 = 0;
+    let mut server_keys = 0;
+    let mut expense_indices = Vec::new();
+    for part in manifest {
+        match PayloadKind::from_i32(part.kind) {
+            Some(PayloadKind::ServerKey) => server_keys += 1,
+            Some(PayloadKind::Expense) => expense_indices.push(part.index),
+            _ => return Err(UploadError::InvalidManifest(format!("unknown payload kind {}", part.kind))),
+        }
+        if part.size == 0 {
+            return Err(UploadError::InvalidManifest("parts must not be empty".into()));
+        }
+        if part.sha256.len() != 32 {
+            return Err(UploadError::InvalidManifest("every part needs a 32-byte SHA-256".into()));
+        }
+        total = total.saturating_add(part.size);
+    }
+    if server_keys > 1 {
+        return Err(UploadError::InvalidManifest("at most one server key".into()));
+    }
+    // Expenses are assembled in manifest order, which must be their index order
+    if expense_indices.iter().enumerate().any(|(i, index)| *index as usize != i) {
+        return Err(UploadError::InvalidManifest("expense parts must be listed in index order from 0".into()));
+    }
+    if total > MAX_UPLOAD_BYTES {
+        return Err(UploadError::InvalidManifest(format!("uploads may total at most {} bytes", MAX_UPLOAD_BYTES)));
+    }
+    Ok(())
+}
+
+/// Manifest entry describing one payload
+pub fn describe_part(kind: PayloadKind, index: u32, data: &[u8]) -> UploadPart {
+    UploadPart {
+        kind: kind as i32,
+        index,
+        size: data.len() as u64,
+        sha256: Sha256::digest(data).to_vec(),
+    }
+}
+
+/// Uploads a server key and encoded categorized expenses in chunks, resuming after interruptions
+///
+/// Returns the upload ID to reference from `EncryptedFinancialRequest::upload_id`.
+pub async fn upload_payloads(
+    client: &mut NbfcServiceClient<InterceptedService<Channel, BearerToken>>,
+    key_id: &str,
+    server_key: Option<&[u8]>,
+    expenses: &[Vec<u8>],
+) -> Result<String, Box<dyn Error>> {
+    let mut parts: Vec<(UploadPart, &[u8])> = Vec::new();
+    if let Some(server_key) = server_key {
+        parts.push((describe_part(PayloadKind::ServerKey, 0, server_key), server_key));
+    }
+    for (index, expense) in expenses.iter().enumerate() {
+        parts.push((describe_part(PayloadKind::Expense, index as u32, expense), expense));
+    }
+
+    let mut progress = client
+        .begin_upload(BeginUploadRequest {
+            key_id: key_id.to_string(),
+            parts: parts.iter().map(|(manifest, _)| manifest.clone()).collect(),
+        })
+        .await?
+        .into_inner();
+    let upload_id = progress.upload_id.clone();
+
+    for attempt in 0..=MAX_RESUME_ATTEMPTS {
+        if progress.complete {
+            return Ok(upload_id);
+        }
+        let chunks = remaining_chunks(&upload_id, &parts, &progress.received_bytes);
+        match client.upload_chunks(tokio_stream::iter(chunks)).await {
+            Ok(response) => progress = response.into_inner(),
+            Err(e) if attempt < MAX_RESUME_ATTEMPTS => {
+                eprintln!("Upload {} interrupted ({}), resuming", upload_id, e.message());
+                progress = client
+                    .get_upload_status(UploadStatusRequest { upload_id: upload_id.clone() })
+                    .await?
+                    .into_inner();
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if progress.complete {
+        Ok(upload_id)
+    } else {
+        Err(format!("Upload {} did not complete", upload_id).into())
+    }
+}
+
+/// Moves a request's categorized expenses, and optionally a server key, into a chunked upload
+pub async fn upload_request_payloads(
+    client: &mut NbfcServiceClient<InterceptedService<Channel, BearerToken>>,
+    request: &mut EncryptedFinancialRequest,
+    server_key: Option<&[u8]>,
+) -> Result<String, Box<dyn Error>> {
+    let expenses: Vec<Vec<u8>> = request.categorized_expenses.iter().map(Message::encode_to_vec).collect();
+    let upload_id = upload_payloads(client, &request.key_id, server_key, &expenses).await?;
+    request.categorized_expenses.clear();
+    request.upload_id = upload_id.clone();
+    Ok(upload_id)
+}
+
+fn remaining_chunks(upload_id: &str, parts: &[(UploadPart, &[u8])], received: &[u64]) -> Vec<UploadChunk> {
+    let mut chunks = Vec::new();
+    for (part, (_, data)) in parts.iter().enumerate() {
+        let start = received.get(part).copied().unwrap_or(0) as usize;
+        for offset in (start..data.len()).step_by(MAX_CHUNK_BYTES) {
+            let end = (offset + MAX_CHUNK_BYTES).min(data.len());
+            chunks.push(UploadChunk {
+                upload_id: upload_id.to_string(),
+                part: part as u32,
+                offset: offset as u64,
+                data: data[offset..end].to_vec(),
+            });
+        }
+    }
+    chunks
+}
+
+impl From<UploadProgress> for UploadStatusResponse {
+    fn from(progress: UploadProgress) -> Self {
+        UploadStatusResponse {
+            upload_id: progress.upload_id,
+            received_bytes: progress.received_bytes,
+            complete: progress.complete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> (UploadStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("credit-uploads-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        (UploadStore::open(&dir).unwrap(), dir)
+    }
+
+    fn chunk(upload_id: &str, offset: u64, data: &[u8]) -> UploadChunk {
+        UploadChunk {
+            upload_id: upload_id.to_string(),
+            part: 0,
+            offset,
+            data: data.to_vec(),
+        }
+    }
+
+    fn part_files(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn parts_are_spooled_to_disk_and_assembled() {
+        let (store, dir) = temp_store("assemble");
+        let payload = b"encrypted expense".to_vec();
+        let upload_id = store
+            .begin("applicant", "key", vec![describe_part(PayloadKind::Expense, 0, &payload)])
+            .unwrap()
+            .upload_id;
+        assert_eq!(part_files(&dir), 1);
+
+        store.append("applicant", &chunk(&upload_id, 0, &payload[..9])).unwrap();
+        let progress = store.append("applicant", &chunk(&upload_id, 9, &payload[9..])).unwrap();
+        assert!(progress.complete);
+
+        let assembled = store.take("applicant", &upload_id, "key").unwrap();
+        assert_eq!(assembled.expenses, vec![payload]);
+        assert_eq!(part_files(&dir), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resent_chunks_must_match_the_bytes_received() {
+        let (store, dir) = temp_store("resend");
+        let payload = b"0123456789".to_vec();
+        let upload_id = store
+            .begin("applicant", "key", vec![describe_part(PayloadKind::Expense, 0, &payload)])
+            .unwrap()
+            .upload_id;
+        store.append("applicant", &chunk(&upload_id, 0, &payload[..6])).unwrap();
+
+        let progress = store.append("applicant", &chunk(&upload_id, 2, &payload[2..6])).unwrap();
+        assert_eq!(progress.received_bytes, vec![6]);
+        assert!(matches!(
+            store.append("applicant", &chunk(&upload_id, 2, b"xxxx")),
+            Err(UploadError::InvalidChunk(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_part_is_discarded() {
+        let (store, dir) = temp_store("corrupt");
+        let payload = b"0123456789".to_vec();
+        let upload_id = store
+            .begin("applicant", "key", vec![describe_part(PayloadKind::Expense, 0, &payload)])
+            .unwrap()
+            .upload_id;
+
+        assert_eq!(
+            store.append("applicant", &chunk(&upload_id, 0, b"9876543210")).unwrap_err(),
+            UploadError::HashMismatch { part: 0 }
+        );
+        assert_eq!(store.status("applicant", &upload_id).unwrap().received_bytes, vec![0]);
+        assert!(store.append("applicant", &chunk(&upload_id, 0, &payload)).unwrap().complete);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_busy_upload_does_not_block_others() {
+        let (store, dir) = temp_store("busy");
+        let manifest = || vec![describe_part(PayloadKind::Expense, 0, b"expense")];
+        let busy = store.begin("applicant", "key", manifest()).unwrap().upload_id;
+        let other = store.begin("applicant", "key", manifest()).unwrap().upload_id;
+
+        let busy = store.get("applicant", &busy).unwrap();
+        let _writing = busy.parts.lock().unwrap();
+        assert!(store.append("applicant", &chunk(&other, 0, b"expense")).unwrap().complete);
+        assert!(store.take("applicant", &other, "key").is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn each_owner_holds_a_limited_number_of_uploads() {
+        let (store, dir) = temp_store("limit");
+        let manifest = || vec![describe_part(PayloadKind::Expense, 0, b"expense")];
+        let mut upload_ids = Vec::new();
+        for _ in 0..MAX_UPLOADS_PER_OWNER {
+            upload_ids.push(store.begin("applicant", "key", manifest()).unwrap().upload_id);
+        }
+        assert_eq!(
+            store.begin("applicant", "key", manifest()).unwrap_err(),
+            UploadError::TooManyUploads { limit: MAX_UPLOADS_PER_OWNER }
+        );
+        assert!(store.begin("other", "key", manifest()).is_ok());
+
+        store.append("applicant", &chunk(&upload_ids[0], 0, b"expense")).unwrap();
+        store.take("applicant", &upload_ids[0], "key").unwrap();
+        assert!(store.begin("applicant", "key", manifest()).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opening_removes_parts_from_a_previous_run() {
+        let (store, dir) = temp_store("stale");
+        store.begin("applicant", "key", vec![describe_part(PayloadKind::Expense, 0, b"expense")]).unwrap();
+        fs::write(dir.join("notes.txt"), b"keep").unwrap();
+        std::mem::forget(store);
+
+        UploadStore::open(&dir).unwrap();
+        assert_eq!(part_files(&dir), 1);
+        assert!(dir.join("notes.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_owners_cannot_touch_an_upload() {
+        let (store, dir) = temp_store("owner");
+        let upload_id = store
+            .begin("applicant", "key", vec![describe_part(PayloadKind::Expense, 0, b"expense")])
+            .unwrap()
+            .upload_id;
+        assert_eq!(store.append("other", &chunk(&upload_id, 0, b"expense")).unwrap_err(), UploadError::NotOwner);
+        assert_eq!(store.status("other", &upload_id).unwrap_err(), UploadError::NotOwner);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
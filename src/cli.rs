@@ -0,0 +1,851 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tfhe::shortint::Ciphertext;
+
+use crate::auth::{self, BearerToken, Role};
+use crate::bank_service::credit_evaluation::bank_service_client::BankServiceClient;
+use crate::bank_service::credit_evaluation::CreditProofRequest;
+use crate::config::AppConfig;
+use crate::decryption_service::credit_evaluation::decryption_service_client::DecryptionServiceClient;
+use crate::decryption_service::credit_evaluation::{CiphertextKind, DecisionInputRequest};
+use crate::fhe_utils::{self, CategorizedExpense, ExpenseCategory};
+use crate::key_store::{self, KeyId, KeyStore};
+use crate::nbfc_service::credit_evaluation::nbfc_service_client::NbfcServiceClient;
+use crate::nbfc_service::credit_evaluation::{
+    CategorizedExpense as CategorizedExpenseMessage, CreditProofResponse, EncryptedFinancialRequest,
+    ExpenseCategory as ProtoExpenseCategory, JobState, JobStatusRequest, LoanTerms, PublishProofBundleRequest,
+    ReasonCode, SharedProofBundle,
+};
+use crate::tls::{self, TlsPaths};
+use crate::{bank_service, chunked_upload, decryption_service, error, fhe_params, nbfc_service, proof_jobs, signing};
+use crate::threshold_decryption;
+use crate::{zk_ciphertext_link, zk_salary_circuit};
+
+/// Privacy-preserving credit evaluation with FHE and zero-knowledge proofs
+#[derive(Debug, Parser)]
+#[command(name = "privacy-preserving-credit", version)]
+pub struct Cli {
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run every service and the end-to-end demonstration (the default)
+    Demo,
+    /// Run one service until interrupted
+    Serve {
+        #[arg(value_enum)]
+        service: ServiceKind,
+    },
+    /// Generate a new FHE key set and make it the active key
+    Keygen(KeygenArgs),
+    /// Issue a bearer token with the token issuer's signing key
+    IssueToken(IssueTokenArgs),
+    /// Encrypt an applicant file into an EncryptedFinancialRequest payload
+    Encrypt(EncryptArgs),
+    /// Run the applicant flow against running NBFC and Bank services
+    Apply(ApplyArgs),
+    /// Check on or cancel a proof job submitted with `apply --job`
+    Job {
+        #[command(subcommand)]
+        action: JobAction,
+    },
+    /// Prove offline that a salary meets a threshold
+    Prove(ProveArgs),
+    /// Verify an offline proof file
+    Verify(VerifyArgs),
+    /// Decode a protobuf payload
+    Inspect(InspectArgs),
+    /// Report every FHE parameter set's security, ciphertext size and latency
+    ParameterReport,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ServiceKind {
+    Nbfc,
+    Bank,
+    Decryption,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum JobAction {
+    /// Show a proof job's state and progress
+    Status { job_id: String },
+    /// Cancel a queued or running proof job
+    Cancel { job_id: String },
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// Directory of the key store
+    #[arg(long, default_value = key_store::DEFAULT_KEY_STORE_DIR)]
+    pub key_store: PathBuf,
+    /// Also generate development TLS certificates for every party and the NBFC's signing key
+    #[arg(long)]
+    pub dev_ca: bool,
+    /// Split the client key between the Bank and the NBFC and delete the full key
+    #[arg(long)]
+    pub split: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct IssueTokenArgs {
+    /// Institution the token names; must match the holder's certificate identity
+    #[arg(long)]
+    pub institution: String,
+    /// Role the token grants: applicant, nbfc, bank or auditor
+    #[arg(long)]
+    pub role: Role,
+    /// User an applicant token acts for; only that user's budget and bundles are reachable
+    #[arg(long)]
+    pub user_id: Option<String>,
+    /// Seconds until the token expires
+    #[arg(long, default_value_t = auth::DEFAULT_TOKEN_TTL.as_secs())]
+    pub ttl_secs: u64,
+    /// Where to write the token, e.g. certs/bank.token; printed when omitted
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct EncryptArgs {
+    /// Applicant JSON file with salary, tenure, expenses and loan terms
+    #[arg(long)]
+    pub input: PathBuf,
+    /// Where to write the encoded EncryptedFinancialRequest
+    #[arg(long)]
+    pub output: PathBuf,
+    /// Directory of the key store
+    #[arg(long, default_value = key_store::DEFAULT_KEY_STORE_DIR)]
+    pub key_store: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    /// Applicant JSON file with salary, tenure, expenses and loan terms
+    #[arg(long)]
+    pub input: PathBuf,
+    /// Directory of the key store
+    #[arg(long, default_value = key_store::DEFAULT_KEY_STORE_DIR)]
+    pub key_store: PathBuf,
+    /// Also share the published proof bundle with this institution; repeatable
+    #[arg(long)]
+    pub share_with: Vec<String>,
+    /// Upload the server key and categorized expenses in resumable chunks before applying
+    #[arg(long)]
+    pub chunked: bool,
+    /// Submit the application as a proof job and follow its progress
+    #[arg(long)]
+    pub job: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ProveArgs {
+    /// Salary to prove over; it never leaves this machine
+    #[arg(long)]
+    pub salary: u64,
+    /// Threshold to prove the salary exceeds, defaults to the configured policy threshold
+    #[arg(long)]
+    pub threshold: Option<u64>,
+    /// Where to write the proof file
+    #[arg(long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Proof file written by `prove`
+    pub proof: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// Message type the payload encodes
+    #[arg(long, value_enum)]
+    pub message: MessageKind,
+    /// File holding the encoded payload
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MessageKind {
+    FinancialRequest,
+    CreditProofResponse,
+    CreditProofRequest,
+    ProofBundle,
+}
+
+/// Applicant data read by `encrypt` and `apply`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApplicantInput {
+    /// Identifier hashed into the privacy budget and proof bundle owner
+    pub user_id: String,
+    pub salary: u64,
+    pub tenure_years: u64,
+    pub expenses: Vec<CategorizedExpense>,
+    #[serde(default)]
+    pub loan_terms: LoanTermsInput,
+    /// Defaults to the configured policy threshold
+    pub threshold: Option<u64>,
+    /// Defaults to the configured policy ratio
+    pub max_expense_ratio: Option<u64>,
+}
+
+impl ApplicantInput {
+    /// Applicant used by the demonstration workflow
+    pub fn demo() -> Self {
+        Self {
+            user_id: "demo-applicant".to_string(),
+            salary: 6000,
+            tenure_years: 3,
+            expenses: vec![
+                CategorizedExpense { category: ExpenseCategory::Rent, amount: 1200 },
+                CategorizedExpense { category: ExpenseCategory::Emi, amount: 800 },
+                CategorizedExpense { category: ExpenseCategory::Utilities, amount: 350 },
+                CategorizedExpense { category: ExpenseCategory::Discretionary, amount: 450 },
+                CategorizedExpense { category: ExpenseCategory::Discretionary, amount: 200 },
+            ],
+            loan_terms: LoanTermsInput::default(),
+            threshold: None,
+            max_expense_ratio: None,
+        }
+    }
+
+    /// Reads an applicant JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read applicant file {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("Invalid applicant file {}: {}", path.display(), e).into())
+    }
+
+    /// Hash of the user ID, the only identifier services see
+    pub fn user_id_hash(&self) -> Vec<u8> {
+        Sha256::digest(self.user_id.as_bytes()).to_vec()
+    }
+}
+
+/// Loan terms in an applicant file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoanTermsInput {
+    pub foir_percent: u64,
+    pub annual_interest_percent: f64,
+    pub tenure_months: u32,
+    pub requested_emi: u64,
+}
+
+impl Default for LoanTermsInput {
+    fn default() -> Self {
+        Self {
+            foir_percent: 50,
+            annual_interest_percent: 10.5,
+            tenure_months: 36,
+            requested_emi: 1,
+        }
+    }
+}
+
+/// An encrypted application and the key it was encrypted under
+pub struct EncryptedApplication {
+    pub key_id: KeyId,
+    pub request: EncryptedFinancialRequest,
+}
+
+/// Encrypts an applicant's data under the active key, with the salary commitment, link proof and threshold proof
+///
+/// Only the key holder's public key is used, so the applicant needs no secret material.
+pub fn encrypt_application(
+    input: &ApplicantInput,
+    config: &AppConfig,
+    key_store: &KeyStore,
+) -> Result<EncryptedApplication, Box<dyn Error>> {
+    // Step 1: Use the key holder's active public key
+    let key_id = key_store.active_key_id()?.ok_or("Key store has no active key")?;
+    let parameter_set = key_store.metadata(&key_id)?.parameter_set;
+    let public_key = fhe_utils::deserialize_public_key(&key_store.load_public_key_bytes(&key_id)?)?;
+
+    // Step 2: Encrypt with the public key alone, every amount as radix blocks
+    let encrypted_tenure =
+        fhe_utils::encrypt_radix_with_public_key(input.tenure_years, fhe_utils::TENURE_BITS, &public_key, parameter_set)?;
+    let encrypted_categorized_expenses =
+        fhe_utils::encrypt_categorized_expenses_with_public_key(input.expenses.clone(), &public_key, parameter_set)?;
+
+    // Step 3: Commit to the salary, prove the salary blocks encrypt the committed value
+    // and prove the committed value exceeds the threshold; the blinding never leaves here.
+    // The threshold proof is bound to a fresh nonce so the Bank can spot a replay.
+    let threshold = input.threshold.unwrap_or(config.policy.threshold);
+    let nonce = zk_salary_circuit::generate_nonce();
+    let salary_blinding = zk_salary_circuit::random_blinding();
+    let salary_commitment = zk_salary_circuit::commit_salary(input.salary, &salary_blinding);
+    let salary_encryption = zk_ciphertext_link::encrypt_salary_for_link(input.salary, &public_key, parameter_set)?;
+    let ciphertext_link_proof = zk_ciphertext_link::prove_ciphertext_link(
+        &salary_encryption,
+        input.salary,
+        &salary_blinding,
+        &public_key,
+        parameter_set,
+    )?;
+    // Without a proof the NBFC reports that the threshold is not met
+    let salary_threshold_proof =
+        zk_salary_circuit::generate_committed_salary_threshold_proof(input.salary, threshold, &salary_blinding, &nonce)?
+            .unwrap_or_default();
+
+    // Step 4: Serialize everything into the request
+    let categorized_expenses = encrypted_categorized_expenses
+        .iter()
+        .map(|e| {
+            Ok(CategorizedExpenseMessage {
+                category: to_proto_category(e.category) as i32,
+                encrypted_amount_blocks: serialize_blocks(&e.blocks)?,
+            })
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let request = EncryptedFinancialRequest {
+        // Lets the NBFC reject mismatched parameters
+        encryption_context: fhe_params::encode_parameter_context(&fhe_params::ParameterContext { parameter_set })?,
+        threshold,
+        user_id_hash: input.user_id_hash(),
+        max_expense_ratio: input.max_expense_ratio.unwrap_or(config.policy.max_expense_ratio),
+        categorized_expenses,
+        encrypted_tenure_blocks: serialize_blocks(&encrypted_tenure)?,
+        key_id: key_id.clone(),
+        loan_terms: Some(LoanTerms {
+            foir_percent: input.loan_terms.foir_percent,
+            annual_interest_percent: input.loan_terms.annual_interest_percent,
+            tenure_months: input.loan_terms.tenure_months,
+            requested_emi: input.loan_terms.requested_emi,
+        }),
+        encrypted_salary_blocks: serialize_blocks(&salary_encryption.blocks)?,
+        salary_commitment: zk_salary_circuit::commitment_to_bytes(&salary_commitment),
+        ciphertext_link_proof,
+        salary_threshold_proof,
+        nonce: nonce.to_vec(),
+        upload_id: String::new(),
+    };
+
+    Ok(EncryptedApplication { key_id, request })
+}
+
+/// Credit score and loan offer decrypted from an NBFC response
+#[derive(Debug, Serialize)]
+pub struct ApplicantResult {
+    pub credit_score: u64,
+    pub max_emi: Option<u64>,
+    pub max_principal: Option<u64>,
+}
+
+/// Has the key holder decrypt the applicant-only parts of an NBFC response
+///
+/// The applicant holds no secret key; the key holder's decryption service releases
+/// these kinds to callers with the applicant role only.
+pub async fn decrypt_applicant_result(
+    response: &CreditProofResponse,
+    config: &AppConfig,
+) -> Result<ApplicantResult, Box<dyn Error>> {
+    let channel = tls::connect(&config.services.decryption_url(), &TlsPaths::from_env(tls::APPLICANT_IDENTITY)).await?;
+    let token = BearerToken(auth::token_from_env(tls::APPLICANT_IDENTITY)?);
+    let mut client = DecryptionServiceClient::with_interceptor(channel, token);
+    let decrypt = |kind: CiphertextKind, ciphertext_blocks: Vec<Vec<u8>>| {
+        let request = DecisionInputRequest {
+            kind: kind as i32,
+            ciphertext_blocks,
+            key_id: response.key_id.clone(),
+            decision_attestation: response.decision_attestation.clone(),
+        };
+        let mut client = client.clone();
+        async move { Ok::<_, Box<dyn Error>>(client.decrypt_decision_input(request).await?.into_inner().value) }
+    };
+
+    let mut result = ApplicantResult {
+        credit_score: decrypt(CiphertextKind::CreditScore, response.encrypted_credit_score_blocks.clone()).await?,
+        max_emi: None,
+        max_principal: None,
+    };
+    if let Some(loan_offer) = &response.loan_offer {
+        result.max_emi = Some(decrypt(CiphertextKind::MaxEmi, loan_offer.encrypted_max_emi_blocks.clone()).await?);
+        result.max_principal =
+            Some(decrypt(CiphertextKind::MaxPrincipal, loan_offer.encrypted_max_principal_blocks.clone()).await?);
+    }
+    Ok(result)
+}
+
+/// Runs a subcommand other than the demonstration
+pub async fn run(command: Command, json_output: bool, config: AppConfig) -> Result<(), Box<dyn Error>> {
+    let (value, text) = match command {
+        Command::Demo => return Err("The demonstration is run by main".into()),
+        Command::Serve { service } => return serve(service, config).await,
+        Command::Keygen(args) => keygen(&args, &config)?,
+        Command::IssueToken(args) => issue_token(&args)?,
+        Command::Encrypt(args) => encrypt(&args, &config)?,
+        Command::Apply(args) => apply(&args, &config).await?,
+        Command::Job { action } => job(action, &config).await?,
+        Command::Prove(args) => prove(&args, &config)?,
+        Command::Verify(args) => {
+            let (value, text) = verify(&args)?;
+            let valid = value["valid"].as_bool().unwrap_or(false);
+            print_output(json_output, &value, &text);
+            // Scripts can rely on the exit status alone
+            if !valid {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Inspect(args) => inspect(&args)?,
+        Command::ParameterReport => {
+            let reports = fhe_params::generate_parameter_report(&fhe_params::FheParameterSet::ALL)?;
+            let value = reports
+                .iter()
+                .map(|report| {
+                    json!({
+                        "parameter_set": report.parameter_set.to_string(),
+                        "security_bits": report.security_bits,
+                        "ciphertext_bytes": report.ciphertext_bytes,
+                        "encrypt_latency_us": report.encrypt_latency.as_micros() as u64,
+                        "add_latency_us": report.add_latency.as_micros() as u64,
+                        "bootstrap_latency_us": report.bootstrap_latency.as_micros() as u64,
+                    })
+                })
+                .collect();
+            (value, fhe_params::format_parameter_report(&reports))
+        }
+    };
+    print_output(json_output, &value, &text);
+    Ok(())
+}
+
+fn print_output(json_output: bool, value: &Value, text: &str) {
+    if json_output {
+        println!("{}", value);
+    } else {
+        print!("{}", text);
+    }
+}
+
+async fn serve(service: ServiceKind, config: AppConfig) -> Result<(), Box<dyn Error>> {
+    match service {
+        ServiceKind::Nbfc => nbfc_service::start_nbfc_server(config).await,
+        ServiceKind::Bank => bank_service::start_bank_server(config).await,
+        ServiceKind::Decryption => decryption_service::start_decryption_server(config).await,
+    }
+}
+
+fn keygen(args: &KeygenArgs, config: &AppConfig) -> Result<(Value, String), Box<dyn Error>> {
+    let passphrase = passphrase()?;
+    let key_store = KeyStore::open(&args.key_store, key_store::DEFAULT_ROTATION_INTERVAL)?
+        .with_parameter_set(config.fhe.parameter_set)?;
+    let key_id = key_store.generate_keys(&passphrase)?;
+    let mut text = format!("Generated key {} with parameter set {}\n", key_id, config.fhe.parameter_set);
+    let mut value = json!({
+        "key_id": key_id,
+        "parameter_set": config.fhe.parameter_set.to_string(),
+        "key_store": args.key_store,
+    });
+
+    // Only the two shares remain, so the key holder can no longer decrypt on its own
+    if args.split {
+        let shares = key_store.split_client_key(&key_id, &passphrase)?;
+        for (party, path) in &shares {
+            text.push_str(&format!("Wrote the {} key share to {}\n", party, path.display()));
+        }
+        text.push_str(&format!(
+            "Deleted the full client key; point {} and {} at the shares\n",
+            threshold_decryption::BANK_KEY_SHARE_ENV,
+            threshold_decryption::NBFC_KEY_SHARE_ENV
+        ));
+        value["key_shares"] = json!(shares
+            .iter()
+            .map(|(party, path)| json!({ "party": party, "path": path }))
+            .collect::<Vec<_>>());
+    }
+
+    if args.dev_ca {
+        let dir = tls::tls_dir();
+        tls::generate_dev_ca(&dir, &tls::DEV_IDENTITIES)?;
+        signing::ensure_signing_keys(&dir, &[tls::NBFC_IDENTITY, auth::TOKEN_ISSUER_IDENTITY])?;
+        text.push_str(&format!("Generated development certificates and signing keys in {}\n", dir.display()));
+        value["tls_dir"] = json!(dir);
+    }
+    Ok((value, text))
+}
+
+fn issue_token(args: &IssueTokenArgs) -> Result<(Value, String), Box<dyn Error>> {
+    let ttl = Duration::from_secs(args.ttl_secs);
+    let claims = match (&args.user_id, args.role) {
+        (Some(user_id), Role::Applicant) => auth::Claims::for_user(&args.institution, user_id, ttl),
+        (None, Role::Applicant) => return Err("An applicant token needs --user-id".into()),
+        (Some(_), role) => return Err(format!("A {} token does not act for a user", role).into()),
+        (None, role) => auth::Claims::new(&args.institution, role, Vec::new(), ttl),
+    };
+    let token = auth::issue_token_from_env(&claims)?;
+
+    let mut value = json!({
+        "institution": claims.institution,
+        "role": claims.role.to_string(),
+        "expires_at": claims.expires_at,
+    });
+    let text = match &args.output {
+        Some(path) => {
+            signing::write_private_file(path, token.as_bytes())?;
+            value["output"] = json!(path);
+            format!(
+                "Wrote a {} token for {} to {}, expiring at {}\n",
+                claims.role,
+                claims.institution,
+                path.display(),
+                claims.expires_at
+            )
+        }
+        None => {
+            value["token"] = json!(token);
+            format!("{}\n", token)
+        }
+    };
+    Ok((value, text))
+}
+
+fn encrypt(args: &EncryptArgs, config: &AppConfig) -> Result<(Value, String), Box<dyn Error>> {
+    let input = ApplicantInput::from_file(&args.input)?;
+    let key_store = KeyStore::open(&args.key_store, key_store::DEFAULT_ROTATION_INTERVAL)?;
+    let application = encrypt_application(&input, config, &key_store)?;
+    let payload = application.request.encode_to_vec();
+    fs::write(&args.output, &payload)?;
+
+    let text = format!(
+        "Wrote {} byte EncryptedFinancialRequest under key {} to {}\n",
+        payload.len(),
+        application.key_id,
+        args.output.display()
+    );
+    let value = json!({
+        "key_id": application.key_id,
+        "output": args.output,
+        "bytes": payload.len(),
+    });
+    Ok((value, text))
+}
+
+async fn apply(args: &ApplyArgs, config: &AppConfig) -> Result<(Value, String), Box<dyn Error>> {
+    let input = ApplicantInput::from_file(&args.input)?;
+    let key_store = KeyStore::open(&args.key_store, key_store::DEFAULT_ROTATION_INTERVAL)?;
+    let mut application = encrypt_application(&input, config, &key_store)?;
+    let threshold = application.request.threshold;
+
+    // Step 1: Have the NBFC evaluate the encrypted application
+    let applicant_tls = TlsPaths::from_env(tls::APPLICANT_IDENTITY);
+    let token = BearerToken(auth::token_from_env(tls::APPLICANT_IDENTITY)?);
+    let mut nbfc_client = NbfcServiceClient::with_interceptor(
+        tls::connect(&config.services.nbfc_url(), &applicant_tls).await?,
+        token.clone(),
+    );
+    let upload_id = if args.chunked {
+        let server_key = bincode::serialize(&key_store.load_server_key(&application.key_id)?)?;
+        Some(chunked_upload::upload_request_payloads(&mut nbfc_client, &mut application.request, Some(&server_key)).await?)
+    } else {
+        None
+    };
+    let proof_response = if args.job {
+        // Progress goes to stderr so --json output stays parseable
+        proof_jobs::submit_and_follow(&mut nbfc_client, application.request, |status| {
+            eprintln!(
+                "Job {}: {} ({}/{})",
+                status.job_id, status.stage, status.completed_steps, status.total_steps
+            );
+        })
+        .await
+        .map_err(with_reason)?
+    } else {
+        nbfc_client
+            .generate_credit_proof(tonic::Request::new(application.request))
+            .await
+            .map_err(with_reason)?
+            .into_inner()
+    };
+    let result = decrypt_applicant_result(&proof_response, config).await?;
+
+    // Step 2: Publish the proof; the Bank decides only on bundles it fetches from the registry
+    let mut consented_requesters = vec![tls::BANK_IDENTITY.to_string()];
+    consented_requesters.extend(args.share_with.iter().filter(|r| r.as_str() != tls::BANK_IDENTITY).cloned());
+    let published = nbfc_client
+        .publish_proof_bundle(tonic::Request::new(PublishProofBundleRequest {
+            bundle: Some(SharedProofBundle {
+                zkp_proof: proof_response.zkp_proof,
+                public_inputs: proof_response.public_inputs,
+                encrypted_avg_expense_blocks: proof_response.encrypted_avg_expense_blocks,
+                proof_id: Vec::new(),
+                expiration_timestamp: 0,
+                user_id_hash: input.user_id_hash(),
+                key_id: proof_response.key_id,
+                threshold,
+                salary_commitment: proof_response.salary_commitment,
+                encrypted_ratio_bucket: proof_response.encrypted_ratio_bucket,
+                encrypted_affordability_check: proof_response.encrypted_affordability_check,
+                nonce: proof_response.nonce,
+                decision_attestation: proof_response.decision_attestation,
+            }),
+            consented_requesters,
+        }))
+        .await
+        .map_err(with_reason)?
+        .into_inner();
+    let proof_id = hex::encode(&published.proof_id);
+
+    // Step 3: Ask the Bank for a decision on the published proof
+    let mut bank_client = BankServiceClient::with_interceptor(
+        tls::connect(&config.services.bank_url(), &applicant_tls).await?,
+        token,
+    );
+    let decision = bank_client
+        .verify_proof_and_decide(tonic::Request::new(CreditProofRequest {
+            proof_id: published.proof_id,
+        }))
+        .await
+        .map_err(with_reason)?
+        .into_inner();
+    let reason_code = ReasonCode::from_i32(decision.reason_code).unwrap_or(ReasonCode::Unspecified);
+
+    let mut text = format!("Key: {}\n", application.key_id);
+    if let Some(upload_id) = &upload_id {
+        text.push_str(&format!("Uploaded payloads in chunks as {}\n", upload_id));
+    }
+    text.push_str(&format!("Your credit score: {}\n", result.credit_score));
+    if let (Some(max_emi), Some(max_principal)) = (result.max_emi, result.max_principal) {
+        text.push_str(&format!("Your loan offer: maximum EMI {}, maximum principal {}\n", max_emi, max_principal));
+    }
+    text.push_str(&format!("Published proof bundle {}\n", proof_id));
+    text.push_str(&format!(
+        "Eligible: {}\nReason: {} ({:?})\nCredit Score: {}\n",
+        decision.eligible, decision.reason, reason_code, decision.credit_score
+    ));
+    let value = json!({
+        "key_id": application.key_id,
+        "applicant": result,
+        "upload_id": upload_id,
+        "proof_id": proof_id,
+        "decision": {
+            "eligible": decision.eligible,
+            "reason": decision.reason,
+            "reason_code": format!("{:?}", reason_code),
+            "credit_score": decision.credit_score,
+        },
+    });
+    Ok((value, text))
+}
+
+async fn job(action: JobAction, config: &AppConfig) -> Result<(Value, String), Box<dyn Error>> {
+    let mut nbfc_client = NbfcServiceClient::with_interceptor(
+        tls::connect(&config.services.nbfc_url(), &TlsPaths::from_env(tls::APPLICANT_IDENTITY)).await?,
+        BearerToken(auth::token_from_env(tls::APPLICANT_IDENTITY)?),
+    );
+    let status = match action {
+        JobAction::Status { job_id } => nbfc_client.get_job_status(JobStatusRequest { job_id }).await?,
+        JobAction::Cancel { job_id } => nbfc_client.cancel_job(JobStatusRequest { job_id }).await?,
+    }
+    .into_inner();
+
+    let state = JobState::from_i32(status.state).unwrap_or(JobState::Unspecified);
+    let mut text = format!(
+        "Job {}: {:?}, {} ({}/{} steps)\n",
+        status.job_id, state, status.stage, status.completed_steps, status.total_steps
+    );
+    if !status.error.is_empty() {
+        text.push_str(&format!("Error: {}\n", status.error));
+    }
+    let value = json!({
+        "job_id": status.job_id,
+        "state": format!("{:?}", state),
+        "stage": status.stage,
+        "completed_steps": status.completed_steps,
+        "total_steps": status.total_steps,
+        "succeeded": status.result.is_some(),
+        "error": status.error,
+    });
+    Ok((value, text))
+}
+
+/// Names the reason code a service attached to a failed call
+fn with_reason(status: tonic::Status) -> Box<dyn Error> {
+    match error::error_detail(&status).and_then(|detail| ReasonCode::from_i32(detail.reason)) {
+        Some(reason) => format!("{} ({:?})", status.message(), reason).into(),
+        None => status.into(),
+    }
+}
+
+/// Offline proof written by `prove` and read by `verify`
+#[derive(Debug, Serialize, Deserialize)]
+struct ProofFile {
+    threshold: u64,
+    /// Hex-encoded salary commitment the proof is bound to
+    salary_commitment: String,
+    /// Hex-encoded nonce the proof is bound to
+    nonce: String,
+    /// Hex-encoded plonky2 proof
+    proof: String,
+}
+
+fn prove(args: &ProveArgs, config: &AppConfig) -> Result<(Value, String), Box<dyn Error>> {
+    let threshold = args.threshold.unwrap_or(config.policy.threshold);
+    let blinding = zk_salary_circuit::random_blinding();
+    let commitment = zk_salary_circuit::commit_salary(args.salary, &blinding);
+    let nonce = zk_salary_circuit::generate_nonce();
+    let proof = zk_salary_circuit::generate_committed_salary_threshold_proof(args.salary, threshold, &blinding, &nonce)?
+        .ok_or_else(|| format!("Salary does not exceed the threshold {}", threshold))?;
+
+    let proof_file = ProofFile {
+        threshold,
+        salary_commitment: hex::encode(zk_salary_circuit::commitment_to_bytes(&commitment)),
+        nonce: hex::encode(nonce),
+        proof: hex::encode(&proof),
+    };
+    fs::write(&args.output, serde_json::to_string_pretty(&proof_file)?)?;
+
+    let text = format!(
+        "Wrote proof that salary > {} to {}\nSalary commitment: {}\n",
+        threshold,
+        args.output.display(),
+        proof_file.salary_commitment
+    );
+    let value = json!({
+        "threshold": threshold,
+        "salary_commitment": proof_file.salary_commitment,
+        "output": args.output,
+    });
+    Ok((value, text))
+}
+
+fn verify(args: &VerifyArgs) -> Result<(Value, String), Box<dyn Error>> {
+    let contents = fs::read_to_string(&args.proof)
+        .map_err(|e| format!("Failed to read proof file {}: {}", args.proof.display(), e))?;
+    let proof_file: ProofFile = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid proof file {}: {}", args.proof.display(), e))?;
+    let commitment = zk_salary_circuit::commitment_from_bytes(&hex::decode(&proof_file.salary_commitment)?)?;
+    let nonce = zk_salary_circuit::nonce_from_bytes(&hex::decode(&proof_file.nonce)?)?;
+    let valid = zk_salary_circuit::verify_committed_salary_threshold_proof(
+        &hex::decode(&proof_file.proof)?,
+        proof_file.threshold,
+        &commitment,
+        &nonce,
+    )?;
+
+    let text = if valid {
+        format!("Valid: the committed salary exceeds {}\n", proof_file.threshold)
+    } else {
+        "Invalid proof\n".to_string()
+    };
+    let value = json!({
+        "valid": valid,
+        "threshold": proof_file.threshold,
+        "salary_commitment": proof_file.salary_commitment,
+    });
+    Ok((value, text))
+}
+
+fn inspect(args: &InspectArgs) -> Result<(Value, String), Box<dyn Error>> {
+    let payload = fs::read(&args.file)
+        .map_err(|e| format!("Failed to read payload {}: {}", args.file.display(), e))?;
+    let value = match args.message {
+        MessageKind::FinancialRequest => {
+            let m = EncryptedFinancialRequest::decode(payload.as_slice())?;
+            let parameter_set = fhe_params::decode_parameter_context(&m.encryption_context)
+                .map(|context| context.parameter_set.to_string())
+                .ok();
+            json!({
+                "key_id": m.key_id,
+                "parameter_set": parameter_set,
+                "threshold": m.threshold,
+                "max_expense_ratio": m.max_expense_ratio,
+                "user_id_hash": hex::encode(&m.user_id_hash),
+                "categorized_expenses": m.categorized_expenses
+                    .iter()
+                    .map(|e| format!("{:?}", ProtoExpenseCategory::from_i32(e.category).unwrap_or(ProtoExpenseCategory::Unspecified)))
+                    .collect::<Vec<_>>(),
+                "encrypted_tenure_blocks": m.encrypted_tenure_blocks.len(),
+                "encrypted_salary_blocks": m.encrypted_salary_blocks.len(),
+                "loan_terms": m.loan_terms.map(|t| json!({
+                    "foir_percent": t.foir_percent,
+                    "annual_interest_percent": t.annual_interest_percent,
+                    "tenure_months": t.tenure_months,
+                    "requested_emi": t.requested_emi,
+                })),
+                "salary_commitment": hex::encode(&m.salary_commitment),
+                "ciphertext_link_proof": bytes_summary(&m.ciphertext_link_proof),
+            })
+        }
+        MessageKind::CreditProofResponse => {
+            let m = CreditProofResponse::decode(payload.as_slice())?;
+            json!({
+                "key_id": m.key_id,
+                "zkp_proof": bytes_summary(&m.zkp_proof),
+                "salary_commitment": hex::encode(&m.salary_commitment),
+                "encrypted_avg_expense_blocks": m.encrypted_avg_expense_blocks.len(),
+                "encrypted_max_expense_blocks": m.encrypted_max_expense_blocks.len(),
+                "encrypted_min_expense_blocks": m.encrypted_min_expense_blocks.len(),
+                "encrypted_expense_variance_blocks": m.encrypted_expense_variance_blocks.len(),
+                "encrypted_expense_trend": m.encrypted_expense_trend.len(),
+                "encrypted_ratio_bucket": bytes_summary(&m.encrypted_ratio_bucket),
+                "encrypted_weighted_expense_blocks": m.encrypted_weighted_expense_blocks.len(),
+                "encrypted_credit_score_blocks": m.encrypted_credit_score_blocks.len(),
+                "encrypted_affordability_check": bytes_summary(&m.encrypted_affordability_check),
+                "has_loan_offer": m.loan_offer.is_some(),
+            })
+        }
+        MessageKind::CreditProofRequest => {
+            let m = CreditProofRequest::decode(payload.as_slice())?;
+            json!({
+                "proof_id": hex::encode(&m.proof_id),
+            })
+        }
+        MessageKind::ProofBundle => {
+            let m = SharedProofBundle::decode(payload.as_slice())?;
+            json!({
+                "proof_id": hex::encode(&m.proof_id),
+                "key_id": m.key_id,
+                "threshold": m.threshold,
+                "expiration_timestamp": m.expiration_timestamp,
+                "user_id_hash": hex::encode(&m.user_id_hash),
+                "zkp_proof": bytes_summary(&m.zkp_proof),
+                "salary_commitment": hex::encode(&m.salary_commitment),
+            })
+        }
+    };
+    let text = format!("{}\n", serde_json::to_string_pretty(&value)?);
+    Ok((value, text))
+}
+
+// Ciphertexts and proofs are too large to print, so only their size and digest are shown
+fn bytes_summary(bytes: &[u8]) -> Value {
+    json!({
+        "bytes": bytes.len(),
+        "sha256": hex::encode(Sha256::digest(bytes)),
+    })
+}
+
+fn serialize_blocks(blocks: &[Ciphertext]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    Ok(blocks.iter().map(bincode::serialize).collect::<Result<Vec<_>, _>>()?)
+}
+
+fn passphrase() -> Result<String, Box<dyn Error>> {
+    std::env::var(key_store::KEY_PASSPHRASE_ENV)
+        .map_err(|_| format!("{} is not set", key_store::KEY_PASSPHRASE_ENV).into())
+}
+
+fn to_proto_category(category: ExpenseCategory) -> ProtoExpenseCategory {
+    match category {
+        ExpenseCategory::Rent => ProtoExpenseCategory::Rent,
+        ExpenseCategory::Emi => ProtoExpenseCategory::Emi,
+        ExpenseCategory::Utilities => ProtoExpenseCategory::Utilities,
+        ExpenseCategory::Discretionary => ProtoExpenseCategory::Discretionary,
+    }
+}
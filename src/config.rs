@@ -0,0 +1,446 @@
+use serde::{Deserialize, Deserializer};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::credit_scoring::{self, ScoringModelKind};
+use crate::fhe_params::{self, FheParameterSet, ParameterPolicy};
+use crate::fhe_utils::{self, CategoryWeights, DpNoise};
+use crate::privacy_budget;
+
+/// Configuration file read when `CREDIT_CONFIG` is not set
+pub const DEFAULT_CONFIG_PATH: &str = "config/credit.toml";
+
+/// Environment variable pointing at the configuration file
+pub const CONFIG_PATH_ENV: &str = "CREDIT_CONFIG";
+
+/// Service addresses
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServicesConfig {
+    pub nbfc_addr: String,
+    pub bank_addr: String,
+    pub decryption_addr: String,
+}
+
+impl Default for ServicesConfig {
+    fn default() -> Self {
+        Self {
+            nbfc_addr: "[::1]:50051".to_string(),
+            bank_addr: "[::1]:50052".to_string(),
+            decryption_addr: "[::1]:50053".to_string(),
+        }
+    }
+}
+
+impl ServicesConfig {
+    /// URL clients use to reach the NBFC
+    pub fn nbfc_url(&self) -> String {
+        format!("https://{}", self.nbfc_addr)
+    }
+
+    /// URL clients use to reach the Bank
+    pub fn bank_url(&self) -> String {
+        format!("https://{}", self.bank_addr)
+    }
+
+    /// URL clients use to reach the key holder's decryption service
+    pub fn decryption_url(&self) -> String {
+        format!("https://{}", self.decryption_addr)
+    }
+}
+
+/// FHE parameter choice and the parameter sets services accept
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FheConfig {
+    #[serde(deserialize_with = "parse_parameter_set")]
+    pub parameter_set: FheParameterSet,
+    #[serde(deserialize_with = "parse_parameter_sets")]
+    pub accepted_parameter_sets: Vec<FheParameterSet>,
+    pub min_security_bits: u32,
+    /// Worker threads for homomorphic evaluation on the NBFC
+    pub threads: usize,
+}
+
+impl Default for FheConfig {
+    fn default() -> Self {
+        let policy = ParameterPolicy::default();
+        Self {
+            parameter_set: FheParameterSet::default(),
+            accepted_parameter_sets: policy.accepted,
+            min_security_bits: policy.min_security_bits,
+            threads: fhe_utils::DEFAULT_FHE_THREADS,
+        }
+    }
+}
+
+impl FheConfig {
+    /// Parameter policy services enforce on incoming encryption contexts
+    pub fn parameter_policy(&self) -> ParameterPolicy {
+        ParameterPolicy {
+            accepted: self.accepted_parameter_sets.clone(),
+            min_security_bits: self.min_security_bits,
+        }
+    }
+}
+
+/// Lending policy the Bank enforces and applicants apply under
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Lowest salary threshold the Bank accepts a proof for
+    pub threshold: u64,
+    /// Highest expense/income ratio, in percent, the Bank accepts
+    pub max_expense_ratio: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 5000,
+            max_expense_ratio: 50,
+        }
+    }
+}
+
+/// Differential privacy noise and budget
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// Each expense is clipped to this before averaging
+    pub expense_clip: u64,
+    /// Public offset the average expense noise is shifted by; the decryptor subtracts it
+    pub noise_offset: u64,
+    pub total_epsilon: f64,
+    pub noisy_average_epsilon: f64,
+    /// Split evenly across the expense maximum, minimum, variance and trend
+    pub statistics_epsilon: f64,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            expense_clip: privacy_budget::DEFAULT_EXPENSE_CLIP,
+            noise_offset: privacy_budget::DEFAULT_NOISE_OFFSET,
+            total_epsilon: privacy_budget::DEFAULT_TOTAL_EPSILON,
+            noisy_average_epsilon: privacy_budget::NOISY_AVERAGE_EPSILON,
+            statistics_epsilon: privacy_budget::EXPENSE_STATISTICS_EPSILON,
+        }
+    }
+}
+
+impl PrivacyConfig {
+    /// Laplace noise calibrated to `noisy_average_epsilon` for an average of `count` clipped expenses
+    pub fn average_noise(&self, count: usize) -> DpNoise {
+        DpNoise::laplace_for_average(self.expense_clip, count, self.noisy_average_epsilon, self.noise_offset)
+    }
+
+    /// Epsilon one application spends on everything released about its expenses
+    pub fn application_epsilon(&self) -> f64 {
+        self.noisy_average_epsilon + self.statistics_epsilon
+    }
+
+    /// Laplace noise for the maximum or minimum of clipped expenses, which one expense moves by up to the clip
+    pub fn extremum_noise(&self) -> DpNoise {
+        DpNoise::laplace_for_sensitivity(self.expense_clip as f64, self.per_statistic_epsilon(), self.noise_offset)
+    }
+
+    /// Laplace noise for the variance of `count` clipped expenses, which one expense moves by up to clip² / count
+    pub fn variance_noise(&self, count: usize) -> DpNoise {
+        let clip = self.expense_clip as f64;
+        DpNoise::laplace_for_sensitivity(clip * clip / count.max(1) as f64, self.per_statistic_epsilon(), self.noise_offset)
+    }
+
+    /// Laplace noise for each trend indicator; one expense moves two neighbouring indicators by up to 2 each
+    pub fn trend_noise(&self) -> DpNoise {
+        DpNoise::laplace_for_sensitivity(4.0, self.per_statistic_epsilon(), self.noise_offset)
+    }
+
+    fn per_statistic_epsilon(&self) -> f64 {
+        self.statistics_epsilon / 4.0
+    }
+}
+
+/// Scoring model and the Bank's decision scores
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringConfig {
+    /// Model the NBFC evaluates the credit score with
+    pub model: ScoringModelKind,
+    pub linear_model_path: String,
+    pub tree_model_path: String,
+    pub category_weights: CategoryWeights,
+    /// Score for expenses within half of the allowed ratio
+    pub low_ratio_score: u32,
+    /// Score for expenses within the allowed ratio
+    pub within_limit_score: u32,
+    /// Score for applicants who are not eligible
+    pub declined_score: u32,
+    /// Points deducted per average expense band
+    pub avg_expense_band_penalty: u32,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            model: ScoringModelKind::default(),
+            linear_model_path: credit_scoring::DEFAULT_LINEAR_MODEL_PATH.to_string(),
+            tree_model_path: credit_scoring::DEFAULT_TREE_MODEL_PATH.to_string(),
+            category_weights: CategoryWeights::default(),
+            low_ratio_score: 800,
+            within_limit_score: 740,
+            declined_score: 600,
+            avg_expense_band_penalty: 20,
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// File holding the selected scoring model
+    pub fn model_path(&self) -> &str {
+        match self.model {
+            ScoringModelKind::Linear => &self.linear_model_path,
+            ScoringModelKind::TreeEnsemble => &self.tree_model_path,
+        }
+    }
+}
+
+/// Proof job queue on the NBFC
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Jobs that may wait in the queue before submissions are refused
+    pub queue_capacity: usize,
+    /// Jobs evaluated and proved at the same time
+    pub workers: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 16,
+            workers: 2,
+        }
+    }
+}
+
+/// Typed configuration for every service
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub services: ServicesConfig,
+    pub fhe: FheConfig,
+    pub policy: PolicyConfig,
+    pub privacy: PrivacyConfig,
+    pub scoring: ScoringConfig,
+    pub jobs: JobsConfig,
+}
+
+impl AppConfig {
+    /// Loads the configuration file, applies environment overrides and validates the result
+    ///
+    /// The file named by `CREDIT_CONFIG` must exist; without it, a missing
+    /// `config/credit.toml` means every setting keeps its default.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut config = match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => Self::from_file(path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::from_file(DEFAULT_CONFIG_PATH)?,
+            Err(_) => Self::default(),
+        };
+        config.apply_env_overrides()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses a TOML configuration file without overrides or validation
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config {}: {}", path.display(), e).into())
+    }
+
+    /// Replaces settings with any `CREDIT_*` environment variables that are set
+    pub fn apply_env_overrides(&mut self) -> Result<(), Box<dyn Error>> {
+        override_from_env("CREDIT_NBFC_ADDR", &mut self.services.nbfc_addr)?;
+        override_from_env("CREDIT_BANK_ADDR", &mut self.services.bank_addr)?;
+        override_from_env("CREDIT_DECRYPTION_ADDR", &mut self.services.decryption_addr)?;
+        override_from_env(fhe_params::PARAMETER_SET_ENV, &mut self.fhe.parameter_set)?;
+        override_from_env("CREDIT_MIN_SECURITY_BITS", &mut self.fhe.min_security_bits)?;
+        override_from_env("CREDIT_FHE_THREADS", &mut self.fhe.threads)?;
+        override_from_env("CREDIT_THRESHOLD", &mut self.policy.threshold)?;
+        override_from_env("CREDIT_MAX_EXPENSE_RATIO", &mut self.policy.max_expense_ratio)?;
+        override_from_env("CREDIT_EXPENSE_CLIP", &mut self.privacy.expense_clip)?;
+        override_from_env("CREDIT_NOISE_OFFSET", &mut self.privacy.noise_offset)?;
+        override_from_env("CREDIT_TOTAL_EPSILON", &mut self.privacy.total_epsilon)?;
+        override_from_env("CREDIT_NOISY_AVERAGE_EPSILON", &mut self.privacy.noisy_average_epsilon)?;
+        override_from_env("CREDIT_STATISTICS_EPSILON", &mut self.privacy.statistics_epsilon)?;
+        override_from_env("CREDIT_SCORING_MODEL", &mut self.scoring.model)?;
+        override_from_env("CREDIT_LINEAR_MODEL_PATH", &mut self.scoring.linear_model_path)?;
+        override_from_env("CREDIT_TREE_MODEL_PATH", &mut self.scoring.tree_model_path)?;
+        override_from_env("CREDIT_JOB_QUEUE_CAPACITY", &mut self.jobs.queue_capacity)?;
+        override_from_env("CREDIT_JOB_WORKERS", &mut self.jobs.workers)?;
+        Ok(())
+    }
+
+    /// Checks every setting, reporting all problems at once
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut problems = Vec::new();
+
+        for (name, addr) in [
+            ("services.nbfc_addr", &self.services.nbfc_addr),
+            ("services.bank_addr", &self.services.bank_addr),
+            ("services.decryption_addr", &self.services.decryption_addr),
+        ] {
+            if addr.parse::<SocketAddr>().is_err() {
+                problems.push(format!("{} must be a socket address like [::1]:50051, got '{}'", name, addr));
+            }
+        }
+
+        if self.fhe.accepted_parameter_sets.is_empty() {
+            problems.push("fhe.accepted_parameter_sets must list at least one parameter set".to_string());
+        } else if !self.fhe.accepted_parameter_sets.contains(&self.fhe.parameter_set) {
+            problems.push(format!(
+                "fhe.parameter_set {} is not in fhe.accepted_parameter_sets",
+                self.fhe.parameter_set
+            ));
+        }
+        let mut sets = vec![self.fhe.parameter_set];
+        sets.extend(self.fhe.accepted_parameter_sets.iter().filter(|set| **set != self.fhe.parameter_set));
+        for set in sets.iter().filter(|set| !set.supports_compact_public_key()) {
+            problems.push(format!("fhe parameter set {} does not support compact public key encryption", set));
+        }
+        if self.fhe.parameter_set.estimated_security_bits() < self.fhe.min_security_bits {
+            problems.push(format!(
+                "fhe.parameter_set {} offers {} bits of security, below fhe.min_security_bits {}",
+                self.fhe.parameter_set,
+                self.fhe.parameter_set.estimated_security_bits(),
+                self.fhe.min_security_bits
+            ));
+        }
+        if self.fhe.threads == 0 {
+            problems.push("fhe.threads must be positive".to_string());
+        }
+
+        if self.policy.threshold == 0 {
+            problems.push("policy.threshold must be positive".to_string());
+        }
+        if !(1..=100).contains(&self.policy.max_expense_ratio) {
+            problems.push(format!(
+                "policy.max_expense_ratio must be between 1 and 100 percent, got {}",
+                self.policy.max_expense_ratio
+            ));
+        }
+
+        if !(self.privacy.total_epsilon > 0.0 && self.privacy.total_epsilon.is_finite()) {
+            problems.push(format!("privacy.total_epsilon must be positive, got {}", self.privacy.total_epsilon));
+        }
+        if !(self.privacy.noisy_average_epsilon > 0.0 && self.privacy.noisy_average_epsilon <= self.privacy.total_epsilon) {
+            problems.push(format!(
+                "privacy.noisy_average_epsilon must be positive and at most privacy.total_epsilon, got {}",
+                self.privacy.noisy_average_epsilon
+            ));
+        }
+        if !(self.privacy.statistics_epsilon > 0.0 && self.privacy.application_epsilon() <= self.privacy.total_epsilon) {
+            problems.push(format!(
+                "privacy.statistics_epsilon must be positive and, with privacy.noisy_average_epsilon, at most privacy.total_epsilon, got {}",
+                self.privacy.statistics_epsilon
+            ));
+        }
+        if self.privacy.expense_clip == 0 {
+            problems.push("privacy.expense_clip must be positive".to_string());
+        }
+
+        let model_path_key = match self.scoring.model {
+            ScoringModelKind::Linear => "scoring.linear_model_path",
+            ScoringModelKind::TreeEnsemble => "scoring.tree_model_path",
+        };
+        if !Path::new(self.scoring.model_path()).exists() {
+            problems.push(format!(
+                "{} {} does not exist for scoring.model {}",
+                model_path_key,
+                self.scoring.model_path(),
+                self.scoring.model
+            ));
+        }
+        let scores = &self.scoring;
+        if !(scores.declined_score <= scores.within_limit_score && scores.within_limit_score <= scores.low_ratio_score) {
+            problems.push(
+                "scoring scores must satisfy declined_score <= within_limit_score <= low_ratio_score".to_string(),
+            );
+        }
+
+        if self.jobs.queue_capacity == 0 {
+            problems.push("jobs.queue_capacity must be positive".to_string());
+        }
+        if self.jobs.workers == 0 {
+            problems.push("jobs.workers must be positive".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid configuration:\n  {}", problems.join("\n  ")).into())
+        }
+    }
+}
+
+fn override_from_env<T>(name: &str, target: &mut T) -> Result<(), Box<dyn Error>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        *target = value
+            .parse()
+            .map_err(|e| format!("Invalid value '{}' for {}: {}", value, name, e))?;
+    }
+    Ok(())
+}
+
+fn parse_parameter_set<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FheParameterSet, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map_err(serde::de::Error::custom)
+}
+
+fn parse_parameter_sets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<FheParameterSet>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| name.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        AppConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn parameter_sets_without_compact_public_keys_are_rejected() {
+        let mut config = AppConfig::default();
+        config.fhe.parameter_set = FheParameterSet::Message2Carry2;
+        config.fhe.accepted_parameter_sets = vec![FheParameterSet::Message2Carry2];
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("message_2_carry_2 does not support compact public key encryption"));
+
+        let mut config = AppConfig::default();
+        config.fhe.accepted_parameter_sets.push(FheParameterSet::Message3Carry3);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn security_floor_above_the_estimate_is_rejected() {
+        let mut config = AppConfig::default();
+        config.fhe.min_security_bits = config.fhe.parameter_set.estimated_security_bits() + 1;
+        assert!(config.validate().unwrap_err().to_string().contains("fhe.min_security_bits"));
+    }
+}
@@ -0,0 +1,613 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use tfhe::shortint::prelude::*;
+
+use crate::fhe_utils::{self, EncryptedAccumulator, EncryptedCategorizedExpense, ExpenseCategory, MAX_ACCUMULATOR_BLOCKS};
+
+/// Default location of the lender's linear scoring model
+pub const DEFAULT_LINEAR_MODEL_PATH: &str = "models/linear_scoring_model.json";
+
+/// Default location of the lender's decision-tree ensemble
+pub const DEFAULT_TREE_MODEL_PATH: &str = "models/tree_ensemble.json";
+
+/// Linear credit-scoring model with plaintext weights over encrypted features
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinearScoringModel {
+    pub intercept: u64,
+    pub salary_weight: i8,
+    pub avg_expense_weight: i8,
+    pub emi_load_weight: i8,
+    pub tenure_weight: i8,
+    /// Each feature is divided by its unit, rounding down, before it is weighted
+    #[serde(default)]
+    pub feature_units: FeatureUnits,
+    /// Optional logistic approximation applied to the linear score
+    #[serde(default)]
+    pub logistic: Option<LogisticApproximation>,
+}
+
+/// Amounts that make up one unit of each feature in a linear model
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FeatureUnits {
+    pub salary: u64,
+    pub avg_expense: u64,
+    pub emi_load: u64,
+    pub tenure: u64,
+}
+
+impl Default for FeatureUnits {
+    fn default() -> Self {
+        Self {
+            salary: 1,
+            avg_expense: 1,
+            emi_load: 1,
+            tenure: 1,
+        }
+    }
+}
+
+impl FeatureUnits {
+    fn get(&self, feature: Feature) -> u64 {
+        match feature {
+            Feature::Salary => self.salary,
+            Feature::AvgExpense => self.avg_expense,
+            Feature::EmiLoad => self.emi_load,
+            Feature::Tenure => self.tenure,
+        }
+    }
+}
+
+/// Sigmoid approximation of the linear score, rounded to an integer in `0..=output_max`
+///
+/// The rounded sigmoid is a step function of the score, so it is evaluated as the number
+/// of steps the encrypted score reaches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogisticApproximation {
+    pub midpoint: f64,
+    pub steepness: f64,
+    pub output_max: u64,
+}
+
+impl Default for LinearScoringModel {
+    fn default() -> Self {
+        Self {
+            intercept: 1,
+            salary_weight: 2,
+            avg_expense_weight: -1,
+            emi_load_weight: -1,
+            tenure_weight: 1,
+            feature_units: FeatureUnits {
+                salary: 1000,
+                avg_expense: 1000,
+                emi_load: 1000,
+                tenure: 1,
+            },
+            logistic: None,
+        }
+    }
+}
+
+/// Applicant feature a decision-tree node can split on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Salary,
+    AvgExpense,
+    EmiLoad,
+    Tenure,
+}
+
+/// Node of a decision tree; a split sends `feature <= threshold` to `left`, otherwise to `right`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TreeNode {
+    Split {
+        feature: Feature,
+        threshold: u64,
+        left: usize,
+        right: usize,
+    },
+    Leaf {
+        value: u64,
+    },
+}
+
+/// Decision tree stored as a flat node list rooted at index 0
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecisionTree {
+    pub nodes: Vec<TreeNode>,
+}
+
+/// Ensemble of decision trees whose leaf values are summed, as in gradient boosting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeEnsembleModel {
+    #[serde(default)]
+    pub base_score: u64,
+    pub trees: Vec<DecisionTree>,
+}
+
+/// Kind of scoring model the NBFC evaluates, as named in configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringModelKind {
+    #[default]
+    Linear,
+    TreeEnsemble,
+}
+
+impl fmt::Display for ScoringModelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScoringModelKind::Linear => "linear",
+            ScoringModelKind::TreeEnsemble => "tree_ensemble",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ScoringModelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "linear" => Ok(ScoringModelKind::Linear),
+            "tree_ensemble" => Ok(ScoringModelKind::TreeEnsemble),
+            _ => Err(format!("Unknown scoring model '{}', expected linear or tree_ensemble", s)),
+        }
+    }
+}
+
+/// Scoring model loaded for evaluation
+#[derive(Debug, Clone)]
+pub enum ScoringModel {
+    Linear(LinearScoringModel),
+    TreeEnsemble(TreeEnsembleModel),
+}
+
+impl Default for ScoringModel {
+    fn default() -> Self {
+        ScoringModel::Linear(LinearScoringModel::default())
+    }
+}
+
+impl ScoringModel {
+    /// Loads a model of the given kind from its JSON file
+    pub fn load<P: AsRef<Path>>(kind: ScoringModelKind, path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(match kind {
+            ScoringModelKind::Linear => ScoringModel::Linear(load_linear_model(path)?),
+            ScoringModelKind::TreeEnsemble => ScoringModel::TreeEnsemble(load_tree_ensemble(path)?),
+        })
+    }
+
+    /// Evaluates the model over encrypted features, returning an encrypted score as radix blocks
+    pub fn evaluate_encrypted(&self, features: &EncryptedFeatures, pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+        match self {
+            ScoringModel::Linear(model) => evaluate_encrypted_linear_score(features, model, pk),
+            ScoringModel::TreeEnsemble(model) => evaluate_encrypted_tree_ensemble(features, model, pk),
+        }
+    }
+
+    /// Evaluates the model over plaintext features
+    pub fn evaluate_plain(&self, features: &PlainFeatures) -> u64 {
+        match self {
+            ScoringModel::Linear(model) => evaluate_plain_linear_score(features, model),
+            ScoringModel::TreeEnsemble(model) => evaluate_plain_tree_ensemble(features, model),
+        }
+    }
+}
+
+/// Plaintext applicant features, used to check encrypted evaluation
+#[derive(Debug, Clone, Copy)]
+pub struct PlainFeatures {
+    pub salary: u64,
+    pub avg_expense: u64,
+    pub emi_load: u64,
+    pub tenure: u64,
+}
+
+impl PlainFeatures {
+    fn get(&self, feature: Feature) -> u64 {
+        match feature {
+            Feature::Salary => self.salary,
+            Feature::AvgExpense => self.avg_expense,
+            Feature::EmiLoad => self.emi_load,
+            Feature::Tenure => self.tenure,
+        }
+    }
+}
+
+/// Encrypted applicant features consumed by the scoring models, as little-endian radix blocks
+#[derive(Clone)]
+pub struct EncryptedFeatures {
+    pub salary: Vec<Ciphertext>,
+    pub avg_expense: Vec<Ciphertext>,
+    pub emi_load: Vec<Ciphertext>,
+    pub tenure: Vec<Ciphertext>,
+}
+
+/// Loads a linear scoring model from a JSON file, rejecting zero units and decreasing sigmoids
+pub fn load_linear_model<P: AsRef<Path>>(path: P) -> Result<LinearScoringModel, Box<dyn Error>> {
+    let contents = fs::read_to_string(path.as_ref())
+        .map_err(|e| format!("Failed to read model {}: {}", path.as_ref().display(), e))?;
+    let model: LinearScoringModel = serde_json::from_str(&contents)?;
+
+    let units = model.feature_units;
+    if [units.salary, units.avg_expense, units.emi_load, units.tenure].contains(&0) {
+        return Err("Feature units must be positive".into());
+    }
+    if let Some(logistic) = &model.logistic {
+        if logistic.steepness.is_nan() || logistic.steepness <= 0.0 || logistic.output_max == 0 {
+            return Err("Logistic approximation needs a positive steepness and output_max".into());
+        }
+    }
+    Ok(model)
+}
+
+impl EncryptedFeatures {
+    fn get(&self, feature: Feature) -> &[Ciphertext] {
+        match feature {
+            Feature::Salary => &self.salary,
+            Feature::AvgExpense => &self.avg_expense,
+            Feature::EmiLoad => &self.emi_load,
+            Feature::Tenure => &self.tenure,
+        }
+    }
+}
+
+/// Loads a decision-tree ensemble from a JSON export, rejecting malformed trees
+pub fn load_tree_ensemble<P: AsRef<Path>>(path: P) -> Result<TreeEnsembleModel, Box<dyn Error>> {
+    let contents = fs::read_to_string(path.as_ref())
+        .map_err(|e| format!("Failed to read model {}: {}", path.as_ref().display(), e))?;
+    let model: TreeEnsembleModel = serde_json::from_str(&contents)?;
+
+    if model.trees.is_empty() {
+        return Err("Tree ensemble contains no trees".into());
+    }
+    for (tree_index, tree) in model.trees.iter().enumerate() {
+        validate_tree(tree).map_err(|e| format!("Invalid tree {}: {}", tree_index, e))?;
+    }
+    Ok(model)
+}
+
+/// Sums the encrypted expenses in the EMI category to form the EMI load feature
+pub fn compute_encrypted_emi_load(
+    encrypted_expenses: &[EncryptedCategorizedExpense],
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let emis: Vec<Vec<Ciphertext>> = encrypted_expenses
+        .iter()
+        .filter(|expense| expense.category == ExpenseCategory::Emi)
+        .map(|expense| expense.blocks.clone())
+        .collect();
+
+    // An applicant without EMIs has no EMI load
+    if emis.is_empty() {
+        return Ok(fhe_utils::trivial_radix(0, 1, pk));
+    }
+    fhe_utils::sum_encrypted_expenses(&emis, pk)
+}
+
+/// Evaluates the linear model over encrypted features, returning an encrypted score as radix blocks
+pub fn evaluate_encrypted_linear_score(
+    features: &EncryptedFeatures,
+    model: &LinearScoringModel,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    // Sum the intercept and positive terms and the negative terms separately, then
+    // subtract with saturation so the unsigned plaintext space never wraps
+    let mut positive = EncryptedAccumulator::from_radix(&fhe_utils::trivial_radix(model.intercept, 1, pk), MAX_ACCUMULATOR_BLOCKS)?;
+    let mut negative = EncryptedAccumulator::from_radix(&fhe_utils::trivial_radix(0, 1, pk), MAX_ACCUMULATOR_BLOCKS)?;
+    for (feature, weight) in linear_terms(model).into_iter().filter(|(_, weight)| *weight != 0) {
+        let units = fhe_utils::radix_scalar_div(features.get(feature), model.feature_units.get(feature), pk)?;
+        let term = fhe_utils::radix_scalar_mul(&units, weight.unsigned_abs() as u64, pk)?;
+        if weight > 0 {
+            positive.add_radix(&term, pk)?;
+        } else {
+            negative.add_radix(&term, pk)?;
+        }
+    }
+    let score = fhe_utils::radix_saturating_sub(&positive.finish(pk)?, &negative.finish(pk)?, pk)?;
+
+    match &model.logistic {
+        Some(logistic) => {
+            let mut level = EncryptedAccumulator::from_radix(&fhe_utils::trivial_radix(0, 1, pk), MAX_ACCUMULATOR_BLOCKS)?;
+            for step in logistic_steps(logistic) {
+                let reached = fhe_utils::radix_scalar_greater_or_equal(&score, step, pk)?;
+                level.add_radix(std::slice::from_ref(&reached), pk)?;
+            }
+            level.finish(pk)
+        }
+        None => Ok(score),
+    }
+}
+
+/// Evaluates the linear model over plaintext features
+pub fn evaluate_plain_linear_score(features: &PlainFeatures, model: &LinearScoringModel) -> u64 {
+    let (mut positive, mut negative) = (model.intercept, 0u64);
+    for (feature, weight) in linear_terms(model) {
+        let term = features.get(feature) / model.feature_units.get(feature) * weight.unsigned_abs() as u64;
+        if weight > 0 {
+            positive += term;
+        } else {
+            negative += term;
+        }
+    }
+    let score = positive.saturating_sub(negative);
+
+    match &model.logistic {
+        Some(logistic) => logistic_approximation(score, logistic),
+        None => score,
+    }
+}
+
+fn linear_terms(model: &LinearScoringModel) -> [(Feature, i8); 4] {
+    [
+        (Feature::Salary, model.salary_weight),
+        (Feature::AvgExpense, model.avg_expense_weight),
+        (Feature::EmiLoad, model.emi_load_weight),
+        (Feature::Tenure, model.tenure_weight),
+    ]
+}
+
+/// Decrypts an encrypted credit score (to be done only by the applicant or an authorized decryptor)
+pub fn decrypt_credit_score(encrypted_score: &[Ciphertext], sk: &ClientKey) -> Result<u64, Box<dyn Error>> {
+    fhe_utils::decrypt_radix(encrypted_score, sk)
+}
+
+fn logistic_approximation(x: u64, logistic: &LogisticApproximation) -> u64 {
+    let z = logistic.steepness * (x as f64 - logistic.midpoint);
+    let sigmoid = 1.0 / (1.0 + (-z).exp());
+    (sigmoid * logistic.output_max as f64).round() as u64
+}
+
+/// Smallest score at which the rounded sigmoid reaches each level from 1 to `output_max`
+fn logistic_steps(logistic: &LogisticApproximation) -> Vec<u64> {
+    let levels = logistic.output_max as f64;
+    (1..=logistic.output_max)
+        .map(|level| {
+            // Rounding reaches the level once the sigmoid reaches (level - 0.5) / output_max
+            let p = (level as f64 - 0.5) / levels;
+            let estimate = logistic.midpoint + (p / (1.0 - p)).ln() / logistic.steepness;
+            let mut step = estimate.ceil().max(0.0) as u64;
+            // Correct the estimate for floating-point error
+            while step > 0 && logistic_approximation(step - 1, logistic) >= level {
+                step -= 1;
+            }
+            while logistic_approximation(step, logistic) < level {
+                step += 1;
+            }
+            step
+        })
+        .collect()
+}
+
+/// Evaluates a tree ensemble over encrypted features, returning an encrypted score as radix blocks
+///
+/// Every split is evaluated with a homomorphic comparison so the path taken stays hidden.
+/// Each leaf's path indicator is the AND of the comparisons leading to it, and multiplying
+/// that indicator by the leaf value gives the leaf's contribution.
+pub fn evaluate_encrypted_tree_ensemble(
+    features: &EncryptedFeatures,
+    model: &TreeEnsembleModel,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let mut score = EncryptedAccumulator::from_radix(&fhe_utils::trivial_radix(model.base_score, 1, pk), MAX_ACCUMULATOR_BLOCKS)?;
+
+    for tree in &model.trees {
+        // A tree that is a single leaf contributes a constant
+        if let TreeNode::Leaf { value } = &tree.nodes[0] {
+            score.add_radix(&fhe_utils::trivial_radix(*value, 1, pk), pk)?;
+            continue;
+        }
+
+        let mut contributions = Vec::new();
+        collect_leaf_contributions(tree, 0, None, features, pk, &mut contributions)?;
+        for contribution in contributions {
+            score.add_radix(&contribution, pk)?;
+        }
+    }
+    score.finish(pk)
+}
+
+/// Evaluates a tree ensemble over plaintext features
+pub fn evaluate_plain_tree_ensemble(features: &PlainFeatures, model: &TreeEnsembleModel) -> u64 {
+    let mut score = model.base_score;
+    for tree in &model.trees {
+        let mut index = 0;
+        loop {
+            match &tree.nodes[index] {
+                TreeNode::Split { feature, threshold, left, right } => {
+                    index = if features.get(*feature) <= *threshold { *left } else { *right };
+                }
+                TreeNode::Leaf { value } => {
+                    score += value;
+                    break;
+                }
+            }
+        }
+    }
+    score
+}
+
+fn collect_leaf_contributions(
+    tree: &DecisionTree,
+    index: usize,
+    path: Option<Ciphertext>,
+    features: &EncryptedFeatures,
+    pk: &ServerKey,
+    contributions: &mut Vec<Vec<Ciphertext>>,
+) -> Result<(), Box<dyn Error>> {
+    match &tree.nodes[index] {
+        TreeNode::Split { feature, threshold, left, right } => {
+            // "value > threshold" is "value >= threshold + 1"; nothing exceeds u64::MAX
+            let value = features.get(*feature);
+            let goes_right = match threshold.checked_add(1) {
+                Some(bound) => fhe_utils::radix_scalar_greater_or_equal(value, bound, pk)?,
+                None => pk.create_trivial(0),
+            };
+            let goes_left = pk.apply_lookup_table(&goes_right, &pk.generate_lookup_table(|right| 1 - right.min(1)));
+
+            let left_path = extend_path(path.as_ref(), goes_left, pk);
+            let right_path = extend_path(path.as_ref(), goes_right, pk);
+
+            collect_leaf_contributions(tree, *left, Some(left_path), features, pk, contributions)?;
+            collect_leaf_contributions(tree, *right, Some(right_path), features, pk, contributions)
+        }
+        TreeNode::Leaf { value } => {
+            let path = path.ok_or("A tree consisting of a single leaf has no encrypted path")?;
+            contributions.push(fhe_utils::radix_scalar_mul(std::slice::from_ref(&path), *value, pk)?);
+            Ok(())
+        }
+    }
+}
+
+fn extend_path(path: Option<&Ciphertext>, branch: Ciphertext, pk: &ServerKey) -> Ciphertext {
+    match path {
+        Some(path) => pk.bitand(path, &branch),
+        None => branch,
+    }
+}
+
+fn validate_tree(tree: &DecisionTree) -> Result<(), Box<dyn Error>> {
+    if tree.nodes.is_empty() {
+        return Err("tree has no nodes".into());
+    }
+    for (index, node) in tree.nodes.iter().enumerate() {
+        if let TreeNode::Split { left, right, .. } = node {
+            // Children must come after their parent, which also rules out cycles
+            for child in [*left, *right] {
+                if child <= index || child >= tree.nodes.len() {
+                    return Err(format!("node {} has invalid child {}", index, child).into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe_params::FheParameterSet;
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+
+    // The example applicant: 6000 salary, 450 average expense, one 800 EMI, 3 years' tenure
+    const EXAMPLE_APPLICANT: PlainFeatures = PlainFeatures {
+        salary: 6000,
+        avg_expense: 450,
+        emi_load: 800,
+        tenure: 3,
+    };
+
+    fn keys() -> &'static (ClientKey, ServerKey) {
+        static KEYS: OnceLock<(ClientKey, ServerKey)> = OnceLock::new();
+        KEYS.get_or_init(|| {
+            let client_key = ClientKey::new(FheParameterSet::Message2Carry2.parameters());
+            let server_key = ServerKey::new(&client_key);
+            (client_key, server_key)
+        })
+    }
+
+    fn model_path(relative: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+    }
+
+    fn encrypt_features(features: &PlainFeatures, client_key: &ClientKey) -> EncryptedFeatures {
+        EncryptedFeatures {
+            salary: fhe_utils::encrypt_radix(features.salary, fhe_utils::SALARY_BITS, client_key).unwrap(),
+            avg_expense: fhe_utils::encrypt_radix(features.avg_expense, fhe_utils::EXPENSE_BITS, client_key).unwrap(),
+            emi_load: fhe_utils::encrypt_radix(features.emi_load, fhe_utils::EXPENSE_BITS, client_key).unwrap(),
+            tenure: fhe_utils::encrypt_radix(features.tenure, fhe_utils::TENURE_BITS, client_key).unwrap(),
+        }
+    }
+
+    fn assert_encrypted_matches_plain(model: &ScoringModel, features: &PlainFeatures) {
+        let (client_key, server_key) = keys();
+        let encrypted_score = model
+            .evaluate_encrypted(&encrypt_features(features, client_key), server_key)
+            .unwrap();
+        assert_eq!(
+            decrypt_credit_score(&encrypted_score, client_key).unwrap(),
+            model.evaluate_plain(features),
+            "features {:?}",
+            features
+        );
+    }
+
+    #[test]
+    fn tree_ensemble_file_loads() {
+        let model = load_tree_ensemble(model_path(DEFAULT_TREE_MODEL_PATH)).unwrap();
+        assert_eq!(model.trees.len(), 2);
+        assert_eq!(evaluate_plain_tree_ensemble(&EXAMPLE_APPLICANT, &model), 3);
+    }
+
+    #[test]
+    fn encrypted_tree_ensemble_matches_plaintext() {
+        let model = ScoringModel::load(ScoringModelKind::TreeEnsemble, model_path(DEFAULT_TREE_MODEL_PATH)).unwrap();
+        // Reach every leaf, including both sides of each threshold
+        let applicants = [
+            EXAMPLE_APPLICANT,
+            PlainFeatures { salary: 4000, avg_expense: 450, emi_load: 0, tenure: 1 },
+            PlainFeatures { salary: 4001, avg_expense: 450, emi_load: 1000, tenure: 2 },
+            PlainFeatures { salary: 6000, avg_expense: 900, emi_load: 1001, tenure: 0 },
+        ];
+        for features in &applicants {
+            assert_encrypted_matches_plain(&model, features);
+        }
+    }
+
+    #[test]
+    fn encrypted_linear_score_matches_plaintext() {
+        let model = ScoringModel::load(ScoringModelKind::Linear, model_path(DEFAULT_LINEAR_MODEL_PATH)).unwrap();
+        assert_eq!(model.evaluate_plain(&EXAMPLE_APPLICANT), 3);
+        assert_encrypted_matches_plain(&model, &EXAMPLE_APPLICANT);
+        // Expenses above the positive terms saturate at zero before the logistic
+        assert_encrypted_matches_plain(
+            &model,
+            &PlainFeatures { salary: 2000, avg_expense: 9000, emi_load: 3000, tenure: 0 },
+        );
+    }
+
+    #[test]
+    fn linear_score_without_logistic_saturates_at_zero() {
+        let model = ScoringModel::Linear(LinearScoringModel::default());
+        let features = PlainFeatures { salary: 1000, avg_expense: 4000, emi_load: 0, tenure: 0 };
+        assert_eq!(model.evaluate_plain(&features), 0);
+        assert_encrypted_matches_plain(&model, &features);
+    }
+
+    #[test]
+    fn logistic_steps_match_rounded_sigmoid() {
+        let logistic = LogisticApproximation {
+            midpoint: 10.0,
+            steepness: 0.5,
+            output_max: 3,
+        };
+        let steps = logistic_steps(&logistic);
+        for score in 0..40 {
+            let reached = steps.iter().filter(|&&step| score >= step).count() as u64;
+            assert_eq!(reached, logistic_approximation(score, &logistic), "score {}", score);
+        }
+    }
+
+    #[test]
+    fn tree_with_backward_child_is_rejected() {
+        let tree: DecisionTree = serde_json::from_str(
+            r#"{ "nodes": [{ "feature": "salary", "threshold": 1, "left": 0, "right": 1 }, { "value": 1 }] }"#,
+        )
+        .unwrap();
+        assert!(validate_tree(&tree).is_err());
+    }
+
+    #[test]
+    fn scoring_model_kind_parses_config_names() {
+        assert_eq!("tree_ensemble".parse::<ScoringModelKind>().unwrap(), ScoringModelKind::TreeEnsemble);
+        assert_eq!(ScoringModelKind::Linear.to_string(), "linear");
+        assert!("forest".parse::<ScoringModelKind>().is_err());
+    }
+}
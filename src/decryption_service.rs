@@ -0,0 +1,303 @@
+use tonic::{transport::Server, Request, Response, Status};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tfhe::shortint::Ciphertext;
+use ed25519_dalek::VerifyingKey;
+
+// Generate the server code from our proto definition
+pub mod credit_evaluation {
+    tonic::include_proto!("credit_evaluation");
+}
+
+use credit_evaluation::decryption_service_server::{DecryptionService, DecryptionServiceServer};
+use credit_evaluation::{CiphertextKind, DecisionInputRequest, DecisionInputResponse};
+
+use crate::attestation::DecisionAttestation;
+use crate::fhe_utils;
+use crate::privacy_budget;
+use crate::key_store::{self, KeyStore};
+use crate::tls::{self, TlsPaths};
+use crate::config::AppConfig;
+use crate::auth::{self, AuthAuditLog, AuthInterceptor, Authorizer, Role};
+use crate::error::CreditError;
+use crate::signing::SigningKeyPaths;
+
+/// Address clients use to reach the decryption service
+pub const DEFAULT_DECRYPTION_SERVICE_ADDR: &str = "https://[::1]:50053";
+
+/// Default append-only log of every decryption request
+pub const DEFAULT_AUDIT_LOG_PATH: &str = "logs/decryption_audit.jsonl";
+
+/// Default upper bounds of the average expense bands
+pub const DEFAULT_AVG_EXPENSE_BAND_BOUNDS: [u64; 2] = [1000, 2500];
+
+/// Which ciphertext kinds the key holder agrees to decrypt for each role, and how coarsely
+#[derive(Debug, Clone)]
+pub struct DecryptionPolicy {
+    pub allowed_kinds: HashMap<Role, HashSet<CiphertextKind>>,
+    /// An average expense is released only as the number of these bounds it reaches
+    pub avg_expense_band_bounds: Vec<u64>,
+    /// Public offset the NBFC shifted the noise of the average and the other expense statistics by
+    pub avg_expense_noise_offset: u64,
+}
+
+impl Default for DecryptionPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_kinds: [
+                (
+                    Role::Bank,
+                    [
+                        CiphertextKind::RatioBucket,
+                        CiphertextKind::AffordabilityCheck,
+                        CiphertextKind::AvgExpense,
+                        CiphertextKind::MaxExpense,
+                        CiphertextKind::MinExpense,
+                        CiphertextKind::ExpenseVariance,
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+                (
+                    Role::Applicant,
+                    [
+                        CiphertextKind::CreditScore,
+                        CiphertextKind::MaxEmi,
+                        CiphertextKind::MaxPrincipal,
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            avg_expense_band_bounds: DEFAULT_AVG_EXPENSE_BAND_BOUNDS.to_vec(),
+            avg_expense_noise_offset: privacy_budget::DEFAULT_NOISE_OFFSET,
+        }
+    }
+}
+
+impl DecryptionPolicy {
+    /// Rejects kinds the key holder has not agreed to decrypt for the caller's role
+    pub fn check(&self, kind: CiphertextKind, role: Role) -> Result<(), String> {
+        let allowed = self.allowed_kinds.get(&role).map_or(false, |kinds| kinds.contains(&kind));
+        if kind == CiphertextKind::Unspecified || !allowed {
+            return Err(format!("Decryption of {:?} ciphertexts is not permitted for role {}", kind, role));
+        }
+        Ok(())
+    }
+
+    /// Derives the released decision input from a decrypted value
+    pub fn derive(&self, kind: CiphertextKind, plaintext: u64) -> u64 {
+        match kind {
+            CiphertextKind::RatioBucket => plaintext.min(fhe_utils::RATIO_BUCKET_EXCEEDED),
+            CiphertextKind::AffordabilityCheck => plaintext.min(1),
+            CiphertextKind::AvgExpense => {
+                let average = plaintext.saturating_sub(self.avg_expense_noise_offset);
+                self.avg_expense_band_bounds.iter().filter(|bound| average >= **bound).count() as u64
+            }
+            CiphertextKind::MaxExpense | CiphertextKind::MinExpense | CiphertextKind::ExpenseVariance => {
+                plaintext.saturating_sub(self.avg_expense_noise_offset)
+            }
+            // The applicant learns their own score and offer in full
+            CiphertextKind::CreditScore | CiphertextKind::MaxEmi | CiphertextKind::MaxPrincipal => plaintext,
+            CiphertextKind::ExpenseTrend | CiphertextKind::Unspecified => 0,
+        }
+    }
+}
+
+/// One entry in the decryption audit log
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: u64,
+    requester: &'a str,
+    key_id: &'a str,
+    kind: String,
+    outcome: &'a str,
+}
+
+/// Append-only JSON-lines log of decryption requests
+#[derive(Debug)]
+pub struct DecryptionAuditLog {
+    file: Mutex<File>,
+}
+
+impl DecryptionAuditLog {
+    /// Opens the log for appending, creating it and its directory if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    fn record(&self, requester: &str, key_id: &str, kind: CiphertextKind, outcome: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let record = AuditRecord {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            requester,
+            key_id,
+            kind: format!("{:?}", kind),
+            outcome,
+        };
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+// Implementation of the key holder's decryption service
+#[derive(Debug)]
+pub struct DecryptionServiceImpl {
+    key_store: Arc<KeyStore>,
+    policy: DecryptionPolicy,
+    audit_log: DecryptionAuditLog,
+    authorizer: Arc<Authorizer>,
+    /// Key the NBFC signs application outputs with; only attested ciphertexts are decrypted
+    nbfc_verifying_key: VerifyingKey,
+}
+
+#[tonic::async_trait]
+impl DecryptionService for DecryptionServiceImpl {
+    async fn decrypt_decision_input(
+        &self,
+        request: Request<DecisionInputRequest>,
+    ) -> Result<Response<DecisionInputResponse>, Status> {
+        // The requester recorded in the audit log is the authenticated institution
+        let principal = self.authorizer.authorize(&request, "DecryptionService/DecryptDecisionInput")?;
+        let requester = principal.institution;
+        let req = request.into_inner();
+        let kind = CiphertextKind::from_i32(req.kind).unwrap_or(CiphertextKind::Unspecified);
+
+        // Step 1: Enforce the policy, recording refusals as well as decryptions
+        if req.key_id.is_empty() {
+            self.audit(&requester, &req.key_id, kind, "denied: missing key ID")?;
+            return Err(CreditError::InvalidArgument("Missing FHE key ID".into()).into());
+        }
+        if let Err(e) = self.policy.check(kind, principal.role) {
+            self.audit(&requester, &req.key_id, kind, "denied: policy")?;
+            return Err(CreditError::PermissionDenied(e).into());
+        }
+        // Only a ciphertext the NBFC attests as this kind of output under this key is decrypted
+        let attested = DecisionAttestation::open(&req.decision_attestation, &self.nbfc_verifying_key).and_then(|statement| {
+            if statement.key_id != req.key_id {
+                return Err(CreditError::KeyMismatch {
+                    key_id: req.key_id.clone(),
+                    message: "NBFC attestation names a different key".into(),
+                });
+            }
+            statement.check_ciphertext(kind, &req.ciphertext_blocks)
+        });
+        if let Err(e) = attested {
+            self.audit(&requester, &req.key_id, kind, "denied: attestation")?;
+            return Err(e.into());
+        }
+
+        // Step 2: Decrypt under the key the ciphertext is tagged with
+        let blocks = req.ciphertext_blocks
+            .iter()
+            .map(|block| bincode::deserialize(block))
+            .collect::<Result<Vec<Ciphertext>, _>>()
+            .map_err(|e| CreditError::deserialization("ciphertext", e))?;
+        if blocks.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing ciphertext blocks".into()).into());
+        }
+        let plaintext = self.decrypt(&req.key_id, &blocks)
+            .map_err(|e| CreditError::internal("decrypt", e))?;
+
+        // Step 3: Release only the derived decision input
+        self.audit(&requester, &req.key_id, kind, "decrypted")?;
+        let response = DecisionInputResponse {
+            value: self.policy.derive(kind, plaintext),
+        };
+
+        Ok(Response::new(response))
+    }
+}
+
+impl DecryptionServiceImpl {
+    fn decrypt(&self, key_id: &str, blocks: &[Ciphertext]) -> Result<u64, Box<dyn std::error::Error>> {
+        let passphrase = std::env::var(key_store::KEY_PASSPHRASE_ENV)
+            .map_err(|_| format!("{} is not set", key_store::KEY_PASSPHRASE_ENV))?;
+        let client_key = self.key_store.load_client_key(key_id, &passphrase)?;
+        fhe_utils::decrypt_radix(blocks, &client_key)
+    }
+
+    // A decryption that cannot be audited must not happen
+    fn audit(&self, requester: &str, key_id: &str, kind: CiphertextKind, outcome: &str) -> Result<(), Status> {
+        self.audit_log
+            .record(requester, key_id, kind, outcome)
+            .map_err(|e| Status::internal(format!("Failed to write audit log: {}", e)))
+    }
+}
+
+// Start the decryption server
+pub async fn start_decryption_server(config: AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = config.services.decryption_addr.parse()?;
+    let authorizer = Arc::new(Authorizer::new(
+        auth::default_access_policy(),
+        AuthAuditLog::open(auth::DEFAULT_AUTH_AUDIT_LOG_PATH)?,
+    ));
+    let service = DecryptionServiceImpl {
+        key_store: Arc::new(KeyStore::open(key_store::DEFAULT_KEY_STORE_DIR, key_store::DEFAULT_ROTATION_INTERVAL)?),
+        policy: DecryptionPolicy {
+            avg_expense_noise_offset: config.privacy.noise_offset,
+            ..Default::default()
+        },
+        audit_log: DecryptionAuditLog::open(DEFAULT_AUDIT_LOG_PATH)?,
+        authorizer: authorizer.clone(),
+        nbfc_verifying_key: SigningKeyPaths::from_env(tls::NBFC_IDENTITY).load_verifying_key()?,
+    };
+
+    println!("Decryption Server listening on {}", addr);
+
+    Server::builder()
+        .tls_config(tls::server_tls_config(&TlsPaths::from_env(tls::KEY_HOLDER_IDENTITY))?)?
+        .add_service(DecryptionServiceServer::with_interceptor(service, AuthInterceptor::from_env(authorizer)?))
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_may_only_decrypt_their_kinds() {
+        let policy = DecryptionPolicy::default();
+        assert!(policy.check(CiphertextKind::RatioBucket, Role::Bank).is_ok());
+        assert!(policy.check(CiphertextKind::CreditScore, Role::Bank).is_err());
+        assert!(policy.check(CiphertextKind::MaxEmi, Role::Applicant).is_ok());
+        assert!(policy.check(CiphertextKind::AvgExpense, Role::Applicant).is_err());
+        assert!(policy.check(CiphertextKind::Unspecified, Role::Bank).is_err());
+        assert!(policy.check(CiphertextKind::MaxExpense, Role::Bank).is_ok());
+        assert!(policy.check(CiphertextKind::ExpenseTrend, Role::Bank).is_err());
+    }
+
+    #[test]
+    fn average_expense_band_removes_the_noise_offset() {
+        let policy = DecryptionPolicy::default();
+        let offset = policy.avg_expense_noise_offset;
+        assert_eq!(policy.derive(CiphertextKind::AvgExpense, offset + 999), 0);
+        assert_eq!(policy.derive(CiphertextKind::AvgExpense, offset + 1000), 1);
+        assert_eq!(policy.derive(CiphertextKind::AvgExpense, offset + 2500), 2);
+        // Noise that pulled the value below the offset releases the lowest band
+        assert_eq!(policy.derive(CiphertextKind::AvgExpense, offset / 2), 0);
+    }
+
+    #[test]
+    fn expense_statistics_remove_the_noise_offset() {
+        let policy = DecryptionPolicy::default();
+        let offset = policy.avg_expense_noise_offset;
+        assert_eq!(policy.derive(CiphertextKind::MaxExpense, offset + 1200), 1200);
+        assert_eq!(policy.derive(CiphertextKind::ExpenseVariance, offset / 2), 0);
+    }
+}
@@ -0,0 +1,212 @@
+use prost::Message;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use tonic::codegen::Bytes;
+use tonic::{Code, Status};
+
+use crate::nbfc_service::credit_evaluation::{ErrorDetail, ReasonCode};
+
+/// Domain reported in every error detail
+pub const ERROR_DOMAIN: &str = "credit_evaluation";
+
+/// Failures the services report, each with a gRPC code and a machine-readable reason
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CreditError {
+    /// A field could not be decoded
+    Deserialization { field: &'static str, message: String },
+    /// A request was malformed in some other way
+    InvalidArgument(String),
+    /// A proof or commitment opening did not verify
+    ProofInvalid(String),
+    /// The salary is below the requested threshold
+    ThresholdNotMet { threshold: u64 },
+    /// Data was encrypted under a different key or parameter set than expected
+    KeyMismatch { key_id: String, message: String },
+    /// A proof bundle or other time-limited item has expired
+    Expired(String),
+    /// A proof was presented again after the Bank already decided on it
+    Replay,
+    NotFound(String),
+    /// The applicant's privacy budget cannot cover the query
+    BudgetExhausted(String),
+    /// The caller may not act on the named user or item
+    PermissionDenied(String),
+    /// The item or service is not in a state that allows the request, e.g. a revoked bundle
+    FailedPrecondition(String),
+    /// The proof job queue is full; retry later
+    QueueFull,
+    /// The caller already holds as many chunked uploads as it may
+    TooManyUploads { limit: usize },
+    /// The caller cancelled the proof job
+    Cancelled,
+    /// The service is shutting down
+    Unavailable(String),
+    /// Uploaded bytes did not match their hash and must be sent again
+    DataLoss(String),
+    Internal(String),
+}
+
+impl CreditError {
+    /// Failure to decode the named field
+    pub fn deserialization(field: &'static str, error: impl Display) -> Self {
+        CreditError::Deserialization {
+            field,
+            message: error.to_string(),
+        }
+    }
+
+    /// Unexpected failure while doing `action`
+    pub fn internal(action: &str, error: impl Display) -> Self {
+        CreditError::Internal(format!("Failed to {}: {}", action, error))
+    }
+
+    /// gRPC status code the error is reported with
+    pub fn code(&self) -> Code {
+        match self {
+            CreditError::Deserialization { .. } | CreditError::InvalidArgument(_) | CreditError::ProofInvalid(_) => {
+                Code::InvalidArgument
+            }
+            CreditError::ThresholdNotMet { .. }
+            | CreditError::KeyMismatch { .. }
+            | CreditError::Expired(_)
+            | CreditError::FailedPrecondition(_) => Code::FailedPrecondition,
+            CreditError::Replay => Code::AlreadyExists,
+            CreditError::NotFound(_) => Code::NotFound,
+            CreditError::BudgetExhausted(_) => Code::ResourceExhausted,
+            CreditError::PermissionDenied(_) => Code::PermissionDenied,
+            CreditError::QueueFull | CreditError::TooManyUploads { .. } => Code::ResourceExhausted,
+            CreditError::Cancelled => Code::Cancelled,
+            CreditError::Unavailable(_) => Code::Unavailable,
+            CreditError::DataLoss(_) => Code::DataLoss,
+            CreditError::Internal(_) => Code::Internal,
+        }
+    }
+
+    /// Reason code carried in the status details
+    pub fn reason_code(&self) -> ReasonCode {
+        match self {
+            CreditError::Deserialization { .. } => ReasonCode::DeserializationFailed,
+            CreditError::InvalidArgument(_) => ReasonCode::InvalidArgument,
+            CreditError::ProofInvalid(_) => ReasonCode::ProofInvalid,
+            CreditError::ThresholdNotMet { .. } => ReasonCode::ThresholdNotMet,
+            CreditError::KeyMismatch { .. } => ReasonCode::KeyMismatch,
+            CreditError::Expired(_) => ReasonCode::Expired,
+            CreditError::Replay => ReasonCode::Replay,
+            CreditError::NotFound(_) => ReasonCode::NotFound,
+            CreditError::BudgetExhausted(_) => ReasonCode::BudgetExhausted,
+            CreditError::PermissionDenied(_) => ReasonCode::PermissionDenied,
+            CreditError::FailedPrecondition(_) => ReasonCode::FailedPrecondition,
+            CreditError::QueueFull => ReasonCode::QueueFull,
+            CreditError::TooManyUploads { .. } => ReasonCode::TooManyUploads,
+            CreditError::Cancelled => ReasonCode::Cancelled,
+            CreditError::Unavailable(_) => ReasonCode::Unavailable,
+            CreditError::DataLoss(_) => ReasonCode::DataLoss,
+            CreditError::Internal(_) => ReasonCode::Internal,
+        }
+    }
+
+    /// Structured detail attached to the gRPC status
+    pub fn detail(&self) -> ErrorDetail {
+        let mut metadata = HashMap::new();
+        match self {
+            CreditError::Deserialization { field, .. } => {
+                metadata.insert("field".to_string(), field.to_string());
+            }
+            CreditError::ThresholdNotMet { threshold } => {
+                metadata.insert("threshold".to_string(), threshold.to_string());
+            }
+            CreditError::KeyMismatch { key_id, .. } => {
+                metadata.insert("key_id".to_string(), key_id.clone());
+            }
+            CreditError::TooManyUploads { limit } => {
+                metadata.insert("limit".to_string(), limit.to_string());
+            }
+            _ => {}
+        }
+        ErrorDetail {
+            reason: self.reason_code() as i32,
+            domain: ERROR_DOMAIN.to_string(),
+            metadata,
+        }
+    }
+}
+
+impl Display for CreditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreditError::Deserialization { field, message } => write!(f, "Failed to deserialize {}: {}", field, message),
+            CreditError::InvalidArgument(message)
+            | CreditError::ProofInvalid(message)
+            | CreditError::Expired(message)
+            | CreditError::NotFound(message)
+            | CreditError::BudgetExhausted(message)
+            | CreditError::PermissionDenied(message)
+            | CreditError::FailedPrecondition(message)
+            | CreditError::Unavailable(message)
+            | CreditError::DataLoss(message)
+            | CreditError::Internal(message) => write!(f, "{}", message),
+            CreditError::ThresholdNotMet { threshold } => write!(f, "Salary does not meet the threshold of {}", threshold),
+            CreditError::KeyMismatch { message, .. } => write!(f, "{}", message),
+            CreditError::Replay => write!(f, "Proof has already been presented"),
+            CreditError::QueueFull => write!(f, "Proof job queue is full, retry later"),
+            CreditError::Cancelled => write!(f, "Proof job was cancelled"),
+            CreditError::TooManyUploads { limit } => {
+                write!(f, "At most {} uploads may be in progress at once; finish or abandon one first", limit)
+            }
+        }
+    }
+}
+
+impl Error for CreditError {}
+
+impl From<CreditError> for Status {
+    fn from(error: CreditError) -> Self {
+        let details = Bytes::from(error.detail().encode_to_vec());
+        Status::with_details(error.code(), error.to_string(), details)
+    }
+}
+
+/// Decodes the structured detail of a status returned by one of the services
+pub fn error_detail(status: &Status) -> Option<ErrorDetail> {
+    ErrorDetail::decode(status.details())
+        .ok()
+        .filter(|detail| detail.domain == ERROR_DOMAIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_carries_code_message_and_detail() {
+        let status = Status::from(CreditError::BudgetExhausted("epsilon 0.5 requested, 0.1 remaining".into()));
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.message(), "epsilon 0.5 requested, 0.1 remaining");
+        let detail = error_detail(&status).unwrap();
+        assert_eq!(detail.reason, ReasonCode::BudgetExhausted as i32);
+        assert_eq!(detail.domain, ERROR_DOMAIN);
+    }
+
+    #[test]
+    fn detail_metadata_names_the_failing_field() {
+        let status = Status::from(CreditError::deserialization("salary commitment", "truncated"));
+        let detail = error_detail(&status).unwrap();
+        assert_eq!(detail.reason, ReasonCode::DeserializationFailed as i32);
+        assert_eq!(detail.metadata.get("field").map(String::as_str), Some("salary commitment"));
+    }
+
+    #[test]
+    fn upload_limit_is_reported_in_the_detail() {
+        let status = Status::from(CreditError::TooManyUploads { limit: 4 });
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        let detail = error_detail(&status).unwrap();
+        assert_eq!(detail.reason, ReasonCode::TooManyUploads as i32);
+        assert_eq!(detail.metadata.get("limit").map(String::as_str), Some("4"));
+    }
+
+    #[test]
+    fn status_from_elsewhere_has_no_detail() {
+        assert_eq!(error_detail(&Status::permission_denied("denied")), None);
+    }
+}
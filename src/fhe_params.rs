@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tfhe::shortint::parameters::*;
+use tfhe::shortint::prelude::*;
+
+/// Environment variable selecting the parameter set
+pub const PARAMETER_SET_ENV: &str = "CREDIT_FHE_PARAMS";
+
+/// Security level, in bits, services require unless configured otherwise
+pub const DEFAULT_MIN_SECURITY_BITS: u32 = 128;
+
+/// Lattice-estimator fits for a 64-bit modulus and binary secrets, as used by concrete-optimizer
+///
+/// A level is met when `log2(noise std dev) >= slope * dimension + bias`, noise normalised to the torus.
+const SECURITY_CURVES: [(u32, f64, f64); 9] = [
+    (80, -0.040_426_331_193_645_89, 1.660_978_864_143_672_2),
+    (96, -0.034_147_803_608_670_81, 2.017_310_258_660_345),
+    (112, -0.029_670_137_081_135_885, 2.162_463_714_083_856),
+    (128, -0.026_374_888_765_705_498, 2.012_143_923_330_495),
+    (144, -0.023_491_767_566_219_68, 2.052_324_148_327_739),
+    (160, -0.021_171_049_703_359_75, 2.042_549_046_059_733),
+    (176, -0.019_300_079_212_413_3, 2.087_735_163_694_296),
+    (192, -0.017_705_566_601_733_65, 2.067_357_025_815_574),
+    (256, -0.013_267_593_379_700_003, 2.033_939_720_069_285),
+];
+
+/// Smallest LWE dimension the curves were fitted for
+const MIN_CURVE_DIMENSION: usize = 450;
+
+/// Iterations averaged when measuring per-operation latency
+const LATENCY_SAMPLES: u32 = 5;
+
+/// Supported tfhe shortint parameter sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FheParameterSet {
+    Message1Carry1,
+    Message2Carry2,
+    Message3Carry3,
+    Message4Carry4,
+    /// Message 2 / carry 2 sized for compact public key encryption
+    Message2Carry2CompactPk,
+}
+
+impl Default for FheParameterSet {
+    fn default() -> Self {
+        FheParameterSet::Message2Carry2CompactPk
+    }
+}
+
+impl FheParameterSet {
+    /// Every supported parameter set, smallest first
+    pub const ALL: [FheParameterSet; 5] = [
+        FheParameterSet::Message1Carry1,
+        FheParameterSet::Message2Carry2,
+        FheParameterSet::Message2Carry2CompactPk,
+        FheParameterSet::Message3Carry3,
+        FheParameterSet::Message4Carry4,
+    ];
+
+    /// Returns the tfhe parameters for this set
+    pub fn parameters(&self) -> ShortintParameterSet {
+        match self {
+            FheParameterSet::Message1Carry1 => PARAM_MESSAGE_1_CARRY_1.into(),
+            FheParameterSet::Message2Carry2 => PARAM_MESSAGE_2_CARRY_2.into(),
+            FheParameterSet::Message3Carry3 => PARAM_MESSAGE_3_CARRY_3.into(),
+            FheParameterSet::Message4Carry4 => PARAM_MESSAGE_4_CARRY_4.into(),
+            FheParameterSet::Message2Carry2CompactPk => PARAM_MESSAGE_2_CARRY_2_COMPACT_PK.into(),
+        }
+    }
+
+    /// Bits of plaintext message per ciphertext
+    pub fn message_bits(&self) -> u32 {
+        match self {
+            FheParameterSet::Message1Carry1 => 1,
+            FheParameterSet::Message2Carry2 | FheParameterSet::Message2Carry2CompactPk => 2,
+            FheParameterSet::Message3Carry3 => 3,
+            FheParameterSet::Message4Carry4 => 4,
+        }
+    }
+
+    /// Bits of carry space per ciphertext
+    pub fn carry_bits(&self) -> u32 {
+        // All supported sets use symmetric message and carry sizes
+        self.message_bits()
+    }
+
+    /// Estimated security level in bits
+    ///
+    /// The weaker of the LWE key and the GLWE (bootstrapping) key, each rounded down to a curve level.
+    pub fn estimated_security_bits(&self) -> u32 {
+        let params = self.parameters();
+        let lwe = lwe_security_bits(params.lwe_dimension().0, params.lwe_modular_std_dev().0);
+        let glwe = lwe_security_bits(
+            params.glwe_dimension().0 * params.polynomial_size().0,
+            params.glwe_modular_std_dev().0,
+        );
+        lwe.min(glwe)
+    }
+
+    /// Whether applicants can encrypt under a compact public key with this set
+    pub fn supports_compact_public_key(&self) -> bool {
+        matches!(self, FheParameterSet::Message2Carry2CompactPk)
+    }
+
+    /// Selects the smallest set matching the requested sizes and security target
+    pub fn select(
+        message_bits: u32,
+        carry_bits: u32,
+        security_bits: u32,
+        compact_public_key: bool,
+    ) -> Result<FheParameterSet, Box<dyn Error>> {
+        FheParameterSet::ALL
+            .iter()
+            .copied()
+            .find(|set| {
+                set.message_bits() == message_bits
+                    && set.carry_bits() == carry_bits
+                    && set.estimated_security_bits() >= security_bits
+                    && (!compact_public_key || set.supports_compact_public_key())
+            })
+            .ok_or_else(|| {
+                format!(
+                    "No parameter set offers message {} bits, carry {} bits and {} bits of security{}",
+                    message_bits,
+                    carry_bits,
+                    security_bits,
+                    if compact_public_key { " with compact public keys" } else { "" }
+                )
+                .into()
+            })
+    }
+}
+
+impl fmt::Display for FheParameterSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FheParameterSet::Message1Carry1 => "message_1_carry_1",
+            FheParameterSet::Message2Carry2 => "message_2_carry_2",
+            FheParameterSet::Message3Carry3 => "message_3_carry_3",
+            FheParameterSet::Message4Carry4 => "message_4_carry_4",
+            FheParameterSet::Message2Carry2CompactPk => "message_2_carry_2_compact_pk",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FheParameterSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FheParameterSet::ALL
+            .iter()
+            .copied()
+            .find(|set| set.to_string() == s.trim().to_ascii_lowercase())
+            .ok_or_else(|| {
+                let known: Vec<String> = FheParameterSet::ALL.iter().map(|set| set.to_string()).collect();
+                format!("Unknown FHE parameter set '{}', expected one of: {}", s, known.join(", "))
+            })
+    }
+}
+
+/// Parameter information carried in the `encryption_context` field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParameterContext {
+    pub parameter_set: FheParameterSet,
+}
+
+/// Serializes a parameter context for the `encryption_context` field
+pub fn encode_parameter_context(context: &ParameterContext) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(bincode::serialize(context)?)
+}
+
+/// Deserializes a parameter context from the `encryption_context` field
+pub fn decode_parameter_context(data: &[u8]) -> Result<ParameterContext, Box<dyn Error>> {
+    bincode::deserialize(data).map_err(|e| format!("Malformed parameter context: {}", e).into())
+}
+
+/// Parameter sets a service is willing to evaluate under
+#[derive(Debug, Clone)]
+pub struct ParameterPolicy {
+    pub accepted: Vec<FheParameterSet>,
+    pub min_security_bits: u32,
+}
+
+impl Default for ParameterPolicy {
+    fn default() -> Self {
+        Self {
+            accepted: vec![FheParameterSet::default()],
+            min_security_bits: DEFAULT_MIN_SECURITY_BITS,
+        }
+    }
+}
+
+impl ParameterPolicy {
+    /// Rejects a context whose parameter set this service does not accept
+    pub fn check(&self, context: &ParameterContext) -> Result<(), String> {
+        let set = context.parameter_set;
+        if set.estimated_security_bits() < self.min_security_bits {
+            return Err(format!(
+                "Parameter set {} offers {} bits of security, below the required {}",
+                set,
+                set.estimated_security_bits(),
+                self.min_security_bits
+            ));
+        }
+        if !self.accepted.contains(&set) {
+            let accepted: Vec<String> = self.accepted.iter().map(|set| set.to_string()).collect();
+            return Err(format!(
+                "Parameter set {} is not accepted, use one of: {}",
+                set,
+                accepted.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Measured characteristics of a parameter set
+#[derive(Debug, Clone)]
+pub struct ParameterReport {
+    pub parameter_set: FheParameterSet,
+    pub security_bits: u32,
+    pub ciphertext_bytes: u64,
+    pub encrypt_latency: Duration,
+    pub add_latency: Duration,
+    pub bootstrap_latency: Duration,
+}
+
+/// Generates keys for each set and measures ciphertext size and per-operation latency
+pub fn generate_parameter_report(sets: &[FheParameterSet]) -> Result<Vec<ParameterReport>, Box<dyn Error>> {
+    let mut reports = Vec::with_capacity(sets.len());
+    for &set in sets {
+        let client_key = ClientKey::new(set.parameters());
+        let server_key = ServerKey::new(&client_key);
+
+        let ct = client_key.encrypt(1);
+        let ciphertext_bytes = bincode::serialized_size(&ct)?;
+
+        let encrypt_latency = average_latency(|| {
+            client_key.encrypt(1);
+        });
+        let add_latency = average_latency(|| {
+            server_key.unchecked_add(&ct, &ct);
+        });
+        let lut = server_key.generate_lookup_table(|x| x);
+        let bootstrap_latency = average_latency(|| {
+            server_key.apply_lookup_table(&ct, &lut);
+        });
+
+        reports.push(ParameterReport {
+            parameter_set: set,
+            security_bits: set.estimated_security_bits(),
+            ciphertext_bytes,
+            encrypt_latency,
+            add_latency,
+            bootstrap_latency,
+        });
+    }
+    Ok(reports)
+}
+
+/// Formats parameter reports as a plain-text table
+pub fn format_parameter_report(reports: &[ParameterReport]) -> String {
+    let mut table = format!(
+        "{:<30} {:>8} {:>10} {:>12} {:>12} {:>12}\n",
+        "parameter set", "security", "ct bytes", "encrypt", "add", "bootstrap"
+    );
+    for report in reports {
+        table.push_str(&format!(
+            "{:<30} {:>8} {:>10} {:>12?} {:>12?} {:>12?}\n",
+            report.parameter_set.to_string(),
+            report.security_bits,
+            report.ciphertext_bytes,
+            report.encrypt_latency,
+            report.add_latency,
+            report.bootstrap_latency
+        ));
+    }
+    table
+}
+
+/// Highest curve level an LWE instance of this dimension and noise meets, 0 if none
+fn lwe_security_bits(dimension: usize, std_dev: f64) -> u32 {
+    if dimension < MIN_CURVE_DIMENSION || std_dev <= 0.0 {
+        return 0;
+    }
+    let log_std_dev = std_dev.log2();
+    SECURITY_CURVES
+        .iter()
+        .filter(|(_, slope, bias)| log_std_dev >= slope * dimension as f64 + bias)
+        .map(|(bits, _, _)| *bits)
+        .max()
+        .unwrap_or(0)
+}
+
+fn average_latency<F: FnMut()>(mut operation: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..LATENCY_SAMPLES {
+        operation();
+    }
+    start.elapsed() / LATENCY_SAMPLES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_grows_with_noise_and_dimension() {
+        assert_eq!(lwe_security_bits(742, 2f64.powf(-17.1)), 128);
+        assert_eq!(lwe_security_bits(742, 2f64.powf(-20.0)), 96);
+        assert_eq!(lwe_security_bits(1024, 2f64.powf(-17.1)), 176);
+        assert_eq!(lwe_security_bits(400, 0.1), 0);
+        assert_eq!(lwe_security_bits(742, 0.0), 0);
+    }
+
+    #[test]
+    fn predefined_sets_meet_the_default_policy() {
+        for set in FheParameterSet::ALL {
+            assert!(set.estimated_security_bits() >= DEFAULT_MIN_SECURITY_BITS, "{}", set);
+        }
+    }
+
+    #[test]
+    fn policy_rejects_sets_below_its_security_floor() {
+        let set = FheParameterSet::default();
+        let context = ParameterContext { parameter_set: set };
+        assert!(ParameterPolicy::default().check(&context).is_ok());
+
+        let strict = ParameterPolicy { accepted: vec![set], min_security_bits: set.estimated_security_bits() + 1 };
+        assert!(strict.check(&context).is_err());
+    }
+
+    #[test]
+    fn select_requires_compact_public_keys_when_asked() {
+        assert_eq!(
+            FheParameterSet::select(2, 2, DEFAULT_MIN_SECURITY_BITS, true).unwrap(),
+            FheParameterSet::Message2Carry2CompactPk
+        );
+        assert!(FheParameterSet::select(3, 3, DEFAULT_MIN_SECURITY_BITS, true).is_err());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for set in FheParameterSet::ALL {
+            assert_eq!(set.to_string().parse::<FheParameterSet>().unwrap(), set);
+        }
+        assert!("message_9_carry_9".parse::<FheParameterSet>().is_err());
+    }
+}
@@ -1,77 +1,1348 @@
-use tfhe::shortint::prelude::*;
-use rand::Rng;
-use std::error::Error;
-use std::vec::Vec;
-use std::convert::TryInto;
-
-/// Struct to hold encrypted financial data
-pub struct EncryptedFinancialData {
-    pub encrypted_salary: Ciphertext,
-    pub encrypted_expenses: Vec<Ciphertext>,
-    pub sk: ClientKey,
-    pub pk: ServerKey,
-}
-
-/// Encrypts a salary value using FHE
-pub fn encrypt_salary(salary: u64) -> Result<(Ciphertext, ClientKey, ServerKey), Box<dyn Error>> {
-    // Set up the parameters with predefined parameters
-    let params = PARAM_MESSAGE_2_CARRY_2;
-    let client_key = ClientKey::new(params);
-    let server_key = ServerKey::new(&client_key);
-    
-    // Encrypt the salary
-    let encrypted_salary = client_key.encrypt(salary);
-    
-    Ok((encrypted_salary, client_key, server_key))
-}
-
-/// Encrypts multiple expense values using FHE
-pub fn encrypt_expenses(expenses: Vec<u64>, client_key: &ClientKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
-    let mut encrypted_expenses = Vec::with_capacity(expenses.len());
-    for expense in expenses {
-        let encrypted_expense = client_key.encrypt(expense);
-        encrypted_expenses.push(encrypted_expense);
-    }
-    Ok(encrypted_expenses)
-}
-
-/// Computes the average of encrypted expenses
-pub fn compute_encrypted_average_expense(encrypted_expenses: &[Ciphertext], sk: &ClientKey, pk: &ServerKey) -> Result<Ciphertext, Box<dyn Error>> {
-    if encrypted_expenses.is_empty() {
-        return Err("Cannot compute average of empty expenses".into());
-    }
-    
-    let mut sum = encrypted_expenses[0].clone();
-    // Sum up all expenses
-    for i in 1..encrypted_expenses.len() {
-        sum = pk.unchecked_add(&sum, &encrypted_expenses[i]);
-    }
-    
-    let count = encrypted_expenses.len() as u8; // Convert to u8 for the division
-    let avg = pk.unchecked_scalar_div(&sum, count);
-    
-    // Add differential privacy noise
-    let noise_level = 5; // Adjusted to a fixed level due to integer domain
-    let mut rng = rand::thread_rng();
-    let noise: u8 = rng.gen_range(0..=noise_level);
-    let noisy_avg = pk.unchecked_add(&avg, &sk.encrypt(noise.into())); // Convert noise to u64
-    
-    Ok(noisy_avg)
-}
-
-/// Checks if encrypted salary is greater than a threshold without decrypting
-pub fn is_salary_greater_than_threshold(
-    encrypted_salary: &Ciphertext,
-    threshold: u64,
-    sk: &ClientKey,
-) -> Result<bool, Box<dyn Error>> {
-    let decrypted_salary = sk.decrypt(encrypted_salary);
-    Ok(decrypted_salary > threshold)
-}
-
-/// Decrypts the average expense (to be done only by the authorized entity)
-pub fn decrypt_average_expense(encrypted_avg: &Ciphertext, sk: &ClientKey) -> Result<u64, Box<dyn Error>> {
-    let decrypted_avg = sk.decrypt(encrypted_avg);
-    Ok(decrypted_avg)
-}
-
+use tfhe::shortint::prelude::*;
+use tfhe::shortint::CompactPublicKey;
+use rand::Rng;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::error::Error;
+use std::vec::Vec;
+use std::convert::TryInto;
+use serde::Deserialize;
+
+use crate::fhe_params::FheParameterSet;
+
+/// Struct to hold encrypted financial data as little-endian radix blocks
+pub struct EncryptedFinancialData {
+    pub encrypted_salary: Vec<Ciphertext>,
+    pub encrypted_expenses: Vec<Vec<Ciphertext>>,
+    pub sk: ClientKey,
+    pub pk: ServerKey,
+}
+
+/// Expense categories that underwriting treats differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpenseCategory {
+    Rent,
+    Emi,
+    Utilities,
+    Discretionary,
+}
+
+/// A plaintext expense tagged with its category
+#[derive(Debug, Clone, Deserialize)]
+pub struct CategorizedExpense {
+    pub category: ExpenseCategory,
+    pub amount: u64,
+}
+
+/// An encrypted expense tagged with its category
+#[derive(Clone)]
+pub struct EncryptedCategorizedExpense {
+    pub category: ExpenseCategory,
+    /// Amount as little-endian radix blocks
+    pub blocks: Vec<Ciphertext>,
+}
+
+/// Plaintext per-category weights configured by the lender
+#[derive(Debug, Clone, Deserialize)]
+pub struct CategoryWeights {
+    pub rent: u8,
+    pub emi: u8,
+    pub utilities: u8,
+    pub discretionary: u8,
+}
+
+impl Default for CategoryWeights {
+    fn default() -> Self {
+        // Existing EMIs and rent are committed outflows, so they weigh more than discretionary spend
+        Self {
+            rent: 2,
+            emi: 3,
+            utilities: 1,
+            discretionary: 1,
+        }
+    }
+}
+
+impl CategoryWeights {
+    /// Returns the weight configured for a category
+    pub fn weight(&self, category: ExpenseCategory) -> u8 {
+        match category {
+            ExpenseCategory::Rent => self.rent,
+            ExpenseCategory::Emi => self.emi,
+            ExpenseCategory::Utilities => self.utilities,
+            ExpenseCategory::Discretionary => self.discretionary,
+        }
+    }
+}
+
+/// Encrypts a salary value using FHE
+pub fn encrypt_salary(salary: u64) -> Result<(Vec<Ciphertext>, ClientKey, ServerKey), Box<dyn Error>> {
+    encrypt_salary_with_parameters(salary, FheParameterSet::Message2Carry2)
+}
+
+/// Encrypts a salary value using FHE under the given parameter set
+pub fn encrypt_salary_with_parameters(salary: u64, parameter_set: FheParameterSet) -> Result<(Vec<Ciphertext>, ClientKey, ServerKey), Box<dyn Error>> {
+    let params = parameter_set.parameters();
+    let client_key = ClientKey::new(params);
+    let server_key = ServerKey::new(&client_key);
+    
+    // Encrypt the salary
+    let encrypted_salary = encrypt_radix(salary, SALARY_BITS, &client_key)?;
+    
+    Ok((encrypted_salary, client_key, server_key))
+}
+
+/// Generates a key pair plus a compact public key that applicants can encrypt under
+pub fn generate_keys_with_public_key() -> Result<(ClientKey, ServerKey, CompactPublicKey), Box<dyn Error>> {
+    generate_keys_with_public_key_for(FheParameterSet::Message2Carry2CompactPk)
+}
+
+/// Generates a key pair plus a compact public key under the given parameter set
+pub fn generate_keys_with_public_key_for(parameter_set: FheParameterSet) -> Result<(ClientKey, ServerKey, CompactPublicKey), Box<dyn Error>> {
+    // Compact public keys require a parameter set sized for public key encryption
+    if !parameter_set.supports_compact_public_key() {
+        return Err(format!("Parameter set {} does not support compact public keys", parameter_set).into());
+    }
+    let params = parameter_set.parameters();
+    let client_key = ClientKey::new(params);
+    let server_key = ServerKey::new(&client_key);
+    let public_key = CompactPublicKey::new(&client_key);
+    
+    Ok((client_key, server_key, public_key))
+}
+
+/// Serializes a compact public key so the key holder can publish it
+pub fn serialize_public_key(public_key: &CompactPublicKey) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(bincode::serialize(public_key)?)
+}
+
+/// Deserializes a published compact public key
+pub fn deserialize_public_key(data: &[u8]) -> Result<CompactPublicKey, Box<dyn Error>> {
+    Ok(bincode::deserialize(data)?)
+}
+
+/// Salary width, in bits, covered by the radix salary blocks
+pub const SALARY_BITS: u32 = 32;
+
+/// Expense width, in bits, covered by each expense's radix blocks
+pub const EXPENSE_BITS: u32 = 32;
+
+/// Tenure width, in bits, covered by the radix tenure blocks
+pub const TENURE_BITS: u32 = 8;
+
+/// Splits a value of at most `bits` bits into little-endian digits of `message_bits` bits each
+pub fn radix_digits(value: u64, bits: u32, message_bits: u32) -> Result<Vec<u64>, Box<dyn Error>> {
+    if bits < u64::BITS && value >> bits != 0 {
+        return Err(format!("Value {} does not fit in {} bits", value, bits).into());
+    }
+    let num_blocks = (bits + message_bits - 1) / message_bits;
+    Ok((0..num_blocks)
+        .map(|i| (value >> (i * message_bits)) % (1u64 << message_bits))
+        .collect())
+}
+
+/// Encrypts a value of at most `bits` bits as little-endian radix blocks, one digit per ciphertext
+pub fn encrypt_radix(value: u64, bits: u32, client_key: &ClientKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let message_bits = client_key.parameters.message_modulus().0.trailing_zeros();
+    let digits = radix_digits(value, bits, message_bits)?;
+    Ok(digits.into_iter().map(|digit| client_key.encrypt(digit)).collect())
+}
+
+/// Encrypts a value of at most `bits` bits as little-endian radix blocks using only the key holder's public key
+pub fn encrypt_radix_with_public_key(
+    value: u64,
+    bits: u32,
+    public_key: &CompactPublicKey,
+    parameter_set: FheParameterSet,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let digits = radix_digits(value, bits, parameter_set.message_bits())?;
+    Ok(digits.into_iter().map(|digit| public_key.encrypt(digit)).collect())
+}
+
+/// Encrypts multiple expense values as radix blocks using FHE
+pub fn encrypt_expenses(expenses: Vec<u64>, client_key: &ClientKey) -> Result<Vec<Vec<Ciphertext>>, Box<dyn Error>> {
+    let mut encrypted_expenses = Vec::with_capacity(expenses.len());
+    for expense in expenses {
+        let encrypted_expense = encrypt_radix(expense, EXPENSE_BITS, client_key)?;
+        encrypted_expenses.push(encrypted_expense);
+    }
+    Ok(encrypted_expenses)
+}
+
+/// Encrypts categorized expense values, keeping each category in the clear
+pub fn encrypt_categorized_expenses(expenses: Vec<CategorizedExpense>, client_key: &ClientKey) -> Result<Vec<EncryptedCategorizedExpense>, Box<dyn Error>> {
+    expenses
+        .into_iter()
+        .map(|expense| {
+            Ok(EncryptedCategorizedExpense {
+                category: expense.category,
+                blocks: encrypt_radix(expense.amount, EXPENSE_BITS, client_key)?,
+            })
+        })
+        .collect()
+}
+
+/// Encrypts categorized expense values using only the key holder's public key
+pub fn encrypt_categorized_expenses_with_public_key(
+    expenses: Vec<CategorizedExpense>,
+    public_key: &CompactPublicKey,
+    parameter_set: FheParameterSet,
+) -> Result<Vec<EncryptedCategorizedExpense>, Box<dyn Error>> {
+    expenses
+        .into_iter()
+        .map(|expense| {
+            Ok(EncryptedCategorizedExpense {
+                category: expense.category,
+                blocks: encrypt_radix_with_public_key(expense.amount, EXPENSE_BITS, public_key, parameter_set)?,
+            })
+        })
+        .collect()
+}
+
+/// Default number of worker threads for batched FHE operations
+pub const DEFAULT_FHE_THREADS: usize = 4;
+
+/// Builds a bounded thread pool for batched FHE operations
+pub fn build_fhe_thread_pool(num_threads: usize) -> Result<ThreadPool, Box<dyn Error>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads.max(1))
+        .thread_name(|i| format!("fhe-worker-{}", i))
+        .build()?;
+    Ok(pool)
+}
+
+/// Encrypts multiple expense values as radix blocks in parallel on the given pool
+pub fn encrypt_expenses_parallel(expenses: &[u64], client_key: &ClientKey, pool: &ThreadPool) -> Result<Vec<Vec<Ciphertext>>, Box<dyn Error>> {
+    let encrypted_expenses = pool.install(|| {
+        expenses
+            .par_iter()
+            .map(|&expense| encrypt_radix(expense, EXPENSE_BITS, client_key).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+    Ok(encrypted_expenses)
+}
+
+/// Encrypts many applicants' expenses in parallel on the given pool, one batch per applicant
+pub fn encrypt_expense_batches_parallel(batches: &[Vec<u64>], client_key: &ClientKey, pool: &ThreadPool) -> Result<Vec<Vec<Vec<Ciphertext>>>, Box<dyn Error>> {
+    let encrypted_batches = pool.install(|| {
+        batches
+            .par_iter()
+            .map(|batch| {
+                batch
+                    .par_iter()
+                    .map(|&expense| encrypt_radix(expense, EXPENSE_BITS, client_key).map_err(|e| e.to_string()))
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+    Ok(encrypted_batches)
+}
+
+/// Encrypts many applicants' categorized expenses in parallel using only the key holder's public key
+pub fn encrypt_categorized_batches_parallel(
+    batches: &[Vec<CategorizedExpense>],
+    public_key: &CompactPublicKey,
+    parameter_set: FheParameterSet,
+    pool: &ThreadPool,
+) -> Result<Vec<Vec<EncryptedCategorizedExpense>>, Box<dyn Error>> {
+    let encrypted_batches = pool.install(|| {
+        batches
+            .par_iter()
+            .map(|batch| {
+                batch
+                    .par_iter()
+                    .map(|expense| {
+                        let blocks = encrypt_radix_with_public_key(expense.amount, EXPENSE_BITS, public_key, parameter_set)
+                            .map_err(|e| e.to_string())?;
+                        Ok(EncryptedCategorizedExpense { category: expense.category, blocks })
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+    Ok(encrypted_batches)
+}
+
+/// Sums clean radix values by pairwise tree reduction in parallel, propagating carries at every level
+pub fn tree_reduce_sum_parallel(
+    radix_values: &[Vec<Ciphertext>],
+    pk: &ServerKey,
+    pool: &ThreadPool,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if radix_values.is_empty() {
+        return Err("Cannot sum empty values".into());
+    }
+    
+    let mut level = radix_values.to_vec();
+    while level.len() > 1 {
+        level = pool.install(|| {
+            level
+                .par_chunks(2)
+                .map(|pair| match pair {
+                    [lhs, rhs] => add_radix_values(lhs, rhs, pk).map_err(|e| e.to_string()),
+                    [single] => Ok(single.clone()),
+                    _ => unreachable!("chunks of two hold one or two values"),
+                })
+                .collect::<Result<Vec<_>, String>>()
+        })?;
+    }
+    Ok(level.remove(0))
+}
+
+/// Adds two clean radix values, returning clean blocks
+fn add_radix_values(lhs: &[Ciphertext], rhs: &[Ciphertext], pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let mut accumulator = EncryptedAccumulator::from_radix(lhs, MAX_ACCUMULATOR_BLOCKS)?;
+    accumulator.add_radix(rhs, pk)?;
+    accumulator.finish(pk)
+}
+
+/// Computes the weighted sum of categorized encrypted expenses using plaintext per-category weights
+///
+/// Expenses are summed per category first, so each weight is applied once, and the
+/// result is returned as clean radix blocks.
+pub fn compute_encrypted_weighted_expense_sum(
+    encrypted_expenses: &[EncryptedCategorizedExpense],
+    weights: &CategoryWeights,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if encrypted_expenses.is_empty() {
+        return Err("Cannot sum empty expenses".into());
+    }
+    
+    let mut total = EncryptedAccumulator::from_radix(&[pk.create_trivial(0)], MAX_ACCUMULATOR_BLOCKS)?;
+    for category in [ExpenseCategory::Rent, ExpenseCategory::Emi, ExpenseCategory::Utilities, ExpenseCategory::Discretionary] {
+        let weight = weights.weight(category) as u64;
+        let in_category: Vec<Vec<Ciphertext>> = encrypted_expenses
+            .iter()
+            .filter(|expense| expense.category == category)
+            .map(|expense| expense.blocks.clone())
+            .collect();
+        if weight == 0 || in_category.is_empty() {
+            continue;
+        }
+        let category_total = sum_encrypted_expenses(&in_category, pk)?;
+        total.add_radix(&radix_scalar_mul(&category_total, weight, pk)?, pk)?;
+    }
+    total.finish(pk)
+}
+
+/// Differential privacy noise added to a released encrypted statistic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DpNoise {
+    /// Release the statistic without noise
+    None,
+    /// Add rounded Laplace noise with the given scale, shifted up by a public offset
+    ///
+    /// Encrypted values cannot go negative, so the noise is added as `offset + noise`, floored
+    /// at zero, and whoever decrypts subtracts the offset.
+    Laplace { scale: f64, offset: u64 },
+}
+
+impl DpNoise {
+    /// Laplace noise for an average of `count` values each clipped to `clip`
+    ///
+    /// One value moves the average by at most `clip / count`, so the scale is that over epsilon.
+    pub fn laplace_for_average(clip: u64, count: usize, epsilon: f64, offset: u64) -> Self {
+        Self::laplace_for_sensitivity(clip as f64 / count.max(1) as f64, epsilon, offset)
+    }
+
+    /// Laplace noise for a statistic that one value moves by at most `sensitivity`
+    pub fn laplace_for_sensitivity(sensitivity: f64, epsilon: f64, offset: u64) -> Self {
+        DpNoise::Laplace {
+            scale: sensitivity / epsilon,
+            offset,
+        }
+    }
+}
+
+/// Trend indicator when an expense fell from the previous month
+pub const TREND_FALLING: u64 = 0;
+/// Trend indicator when an expense matched the previous month
+pub const TREND_FLAT: u64 = 1;
+/// Trend indicator when an expense rose from the previous month
+pub const TREND_RISING: u64 = 2;
+
+/// Draws Laplace noise with the given scale
+pub fn sample_laplace(scale: f64) -> f64 {
+    // u must lie in the open interval (-0.5, 0.5); u = -0.5 would give ln(0) and infinite noise
+    let mut rng = rand::thread_rng();
+    let u: f64 = loop {
+        let u = rng.gen_range(-0.5..0.5);
+        if u > -0.5 {
+            break u;
+        }
+    };
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+/// Computes the average of encrypted expenses with the given differential privacy noise
+pub fn compute_encrypted_average_expense_with_noise(
+    encrypted_expenses: &[Vec<Ciphertext>],
+    noise: DpNoise,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let total = sum_encrypted_expenses(encrypted_expenses, pk)?;
+    compute_encrypted_average_of_total(&total, encrypted_expenses.len(), noise, pk)
+}
+
+/// Divides an already summed expense total by the expense count and adds noise
+///
+/// Lets a caller that needs the total anyway reuse it instead of summing twice.
+pub fn compute_encrypted_average_of_total(
+    encrypted_total: &[Ciphertext],
+    count: usize,
+    noise: DpNoise,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if count == 0 {
+        return Err("Cannot compute average of empty expenses".into());
+    }
+    
+    let average = radix_scalar_div(encrypted_total, count as u64, pk)?;
+    add_dp_noise(&average, noise, pk)
+}
+
+/// Computes the maximum of clean radix expenses with the given differential privacy noise
+pub fn compute_encrypted_max_expense(
+    encrypted_expenses: &[Vec<Ciphertext>],
+    noise: DpNoise,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let (first, rest) = encrypted_expenses
+        .split_first()
+        .ok_or("Cannot compute maximum of empty expenses")?;
+    let mut max = first.clone();
+    for expense in rest {
+        // max(a, b) = a + max(b - a, 0), which fits in the wider of the two
+        let num_blocks = max.len().max(expense.len());
+        let excess = radix_saturating_sub(expense, &max, pk)?;
+        max = add_radix_values(&max, &excess, pk)?;
+        max.truncate(num_blocks);
+    }
+    add_dp_noise(&max, noise, pk)
+}
+
+/// Computes the minimum of clean radix expenses with the given differential privacy noise
+pub fn compute_encrypted_min_expense(
+    encrypted_expenses: &[Vec<Ciphertext>],
+    noise: DpNoise,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let (first, rest) = encrypted_expenses
+        .split_first()
+        .ok_or("Cannot compute minimum of empty expenses")?;
+    let mut min = first.clone();
+    for expense in rest {
+        // min(a, b) = a - max(a - b, 0)
+        let shortfall = radix_saturating_sub(&min, expense, pk)?;
+        min = radix_saturating_sub(&min, &shortfall, pk)?;
+    }
+    add_dp_noise(&min, noise, pk)
+}
+
+/// Computes an encrypted month-over-month trend indicator for each month after the first
+///
+/// Each indicator is `TREND_FALLING`, `TREND_FLAT` or `TREND_RISING` before noise is added.
+pub fn compute_encrypted_expense_trend(
+    monthly_expenses: &[Vec<Ciphertext>],
+    noise: DpNoise,
+    pk: &ServerKey,
+) -> Result<Vec<Vec<Ciphertext>>, Box<dyn Error>> {
+    if monthly_expenses.len() < 2 {
+        return Err("Need at least two months of expenses to compute a trend".into());
+    }
+    
+    // Both comparisons hold when flat, so "previous <= current" + 1 - "current <= previous" is the indicator
+    let indicator = pk.generate_lookup_table_bivariate(|not_falling, not_rising| (not_falling.min(1) + 1).saturating_sub(not_rising.min(1)));
+    monthly_expenses
+        .windows(2)
+        .map(|pair| {
+            let not_falling = radix_less_or_equal(&pair[0], &pair[1], pk)?;
+            let not_rising = radix_less_or_equal(&pair[1], &pair[0], pk)?;
+            let trend = pk.apply_lookup_table_bivariate(&not_falling, &not_rising, &indicator);
+            add_dp_noise(&[trend], noise, pk)
+        })
+        .collect()
+}
+
+/// Computes the approximate encrypted variance of expenses already clipped to `clip`
+///
+/// Squared deviations are taken from the mean rounded down, and their mean is rounded
+/// down too. Clipping bounds every deviation by `clip`, which keeps the squares small.
+pub fn compute_encrypted_expense_variance(
+    clipped_expenses: &[Vec<Ciphertext>],
+    clip: u64,
+    noise: DpNoise,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if clipped_expenses.is_empty() {
+        return Err("Cannot compute variance of empty expenses".into());
+    }
+    
+    let count = clipped_expenses.len() as u64;
+    let mean = radix_scalar_div(&sum_encrypted_expenses(clipped_expenses, pk)?, count, pk)?;
+    let deviation_blocks = scalar_digits(clip, pk.message_modulus.0 as u64).len().max(1);
+    let squared_deviations: Vec<Vec<Ciphertext>> = clipped_expenses
+        .par_iter()
+        .map(|expense| {
+            // One of the two differences is zero, so their sum is |expense - mean|
+            let above = radix_saturating_sub(expense, &mean, pk).map_err(|e| e.to_string())?;
+            let below = radix_saturating_sub(&mean, expense, pk).map_err(|e| e.to_string())?;
+            let mut deviation = add_radix_values(&above, &below, pk).map_err(|e| e.to_string())?;
+            // The deviation is at most the clip, so the blocks above it are zero
+            deviation.truncate(deviation_blocks);
+            radix_mul(&deviation, &deviation, pk).map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    
+    let variance = radix_scalar_div(&sum_encrypted_expenses(&squared_deviations, pk)?, count, pk)?;
+    add_dp_noise(&variance, noise, pk)
+}
+
+/// Adds differential privacy noise to an encrypted radix statistic
+///
+/// The noise is a trivial encryption: the evaluator picks it and cannot decrypt the result,
+/// while the party that decrypts never learns it.
+fn add_dp_noise(blocks: &[Ciphertext], noise: DpNoise, pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    match noise {
+        DpNoise::None => Ok(blocks.to_vec()),
+        DpNoise::Laplace { scale, offset } => {
+            let shifted = (offset as f64 + sample_laplace(scale)).round().max(0.0) as u64;
+            add_radix_values(blocks, &trivial_radix(shifted, 1, pk), pk)
+        }
+    }
+}
+
+/// Maximum number of blocks an accumulator may grow to before reporting overflow
+pub const MAX_ACCUMULATOR_BLOCKS: usize = 32;
+
+/// Sums encrypted values across radix blocks, propagating carries before the carry space is exhausted
+///
+/// Blocks are little-endian digits in base `message_modulus`. The accumulator tracks each
+/// block's degree (its worst-case plaintext value), so overflow is reported as an error
+/// instead of silently wrapping.
+pub struct EncryptedAccumulator {
+    blocks: Vec<Ciphertext>,
+    max_blocks: usize,
+}
+
+impl EncryptedAccumulator {
+    /// Starts an accumulator holding a single encrypted value
+    pub fn new(initial: &Ciphertext, max_blocks: usize) -> Self {
+        Self {
+            blocks: vec![initial.clone()],
+            max_blocks: max_blocks.max(1),
+        }
+    }
+    
+    /// Adds an encrypted value, cleaning carries first if the lowest block cannot absorb it
+    pub fn add(&mut self, value: &Ciphertext, pk: &ServerKey) -> Result<(), Box<dyn Error>> {
+        if !can_add(&self.blocks[0], value, pk) {
+            self.propagate_carries(pk)?;
+        }
+        self.blocks[0] = checked_add(&self.blocks[0], value, pk)?;
+        Ok(())
+    }
+    
+    /// Starts an accumulator holding a little-endian radix value
+    pub fn from_radix(blocks: &[Ciphertext], max_blocks: usize) -> Result<Self, Box<dyn Error>> {
+        if blocks.is_empty() {
+            return Err("Cannot accumulate an empty radix value".into());
+        }
+        if blocks.len() > max_blocks {
+            return Err(format!("Radix value has more than {} blocks", max_blocks).into());
+        }
+        Ok(Self {
+            blocks: blocks.to_vec(),
+            max_blocks,
+        })
+    }
+    
+    /// Adds a little-endian radix value block by block, growing the accumulator as needed
+    pub fn add_radix(&mut self, blocks: &[Ciphertext], pk: &ServerKey) -> Result<(), Box<dyn Error>> {
+        self.add_radix_at(blocks, 0, pk)
+    }
+    
+    /// Adds a little-endian radix value shifted up by `offset` blocks
+    pub fn add_radix_at(&mut self, blocks: &[Ciphertext], offset: usize, pk: &ServerKey) -> Result<(), Box<dyn Error>> {
+        if offset + blocks.len() > self.max_blocks {
+            return Err(format!("Radix value has more than {} blocks", self.max_blocks).into());
+        }
+        while self.blocks.len() < offset + blocks.len() {
+            self.blocks.push(pk.create_trivial(0));
+        }
+        if self.blocks[offset..].iter().zip(blocks).any(|(acc, block)| !can_add(acc, block, pk)) {
+            self.propagate_carries(pk)?;
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            self.blocks[offset + i] = checked_add(&self.blocks[offset + i], block, pk)?;
+        }
+        Ok(())
+    }
+    
+    /// Propagates all pending carries and returns the clean blocks, least significant first
+    pub fn finish(mut self, pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+        self.propagate_carries(pk)?;
+        Ok(self.blocks)
+    }
+    
+    /// Propagates carries in rounds until every block is clean
+    ///
+    /// Each round splits every dirty block into its message and carry before adding the
+    /// carries one block up, so a carry always lands on a block that has room for it.
+    fn propagate_carries(&mut self, pk: &ServerKey) -> Result<(), Box<dyn Error>> {
+        let message_modulus = pk.message_modulus.0 as u64;
+        loop {
+            let dirty: Vec<usize> = (0..self.blocks.len())
+                .filter(|&i| self.blocks[i].degree.get() as u64 >= message_modulus)
+                .collect();
+            if dirty.is_empty() {
+                return Ok(());
+            }
+            
+            // Extracting the carry and message bootstraps the block, which also resets its noise
+            let split: Vec<(usize, Ciphertext, Ciphertext)> = dirty
+                .par_iter()
+                .map(|&i| (i, pk.message_extract(&self.blocks[i]), pk.carry_extract(&self.blocks[i])))
+                .collect();
+            let mut carries = Vec::with_capacity(split.len());
+            for (i, message, carry) in split {
+                self.blocks[i] = message;
+                carries.push((i + 1, carry));
+            }
+            
+            for (i, carry) in carries {
+                if i < self.blocks.len() {
+                    self.blocks[i] = checked_add(&self.blocks[i], &carry, pk)?;
+                } else if self.blocks.len() < self.max_blocks {
+                    self.blocks.push(carry);
+                } else {
+                    return Err(format!("Encrypted sum overflows {} blocks", self.max_blocks).into());
+                }
+            }
+        }
+    }
+}
+
+/// Largest plaintext value a single ciphertext can hold, carries included
+fn max_plaintext_value(pk: &ServerKey) -> u64 {
+    (pk.message_modulus.0 * pk.carry_modulus.0) as u64 - 1
+}
+
+fn can_add(lhs: &Ciphertext, rhs: &Ciphertext, pk: &ServerKey) -> bool {
+    let bound = lhs.degree.get() as u64 + rhs.degree.get() as u64;
+    bound <= max_plaintext_value(pk) && pk.is_add_possible(lhs, rhs).is_ok()
+}
+
+/// Adds two ciphertexts, bootstrapping when noise is too high and failing if the sum may overflow
+fn checked_add(lhs: &Ciphertext, rhs: &Ciphertext, pk: &ServerKey) -> Result<Ciphertext, Box<dyn Error>> {
+    let bound = lhs.degree.get() as u64 + rhs.degree.get() as u64;
+    if bound > max_plaintext_value(pk) {
+        return Err(format!(
+            "Encrypted addition may overflow: worst case {} exceeds {}",
+            bound,
+            max_plaintext_value(pk)
+        ).into());
+    }
+    
+    if pk.is_add_possible(lhs, rhs).is_ok() {
+        return Ok(pk.unchecked_add(lhs, rhs));
+    }
+    
+    // The degrees fit, so the noise is what blocks the addition; refresh both operands
+    let lhs = refresh(lhs, pk);
+    let rhs = refresh(rhs, pk);
+    if pk.is_add_possible(&lhs, &rhs).is_err() {
+        return Err("Encrypted addition is not possible even after bootstrapping".into());
+    }
+    Ok(pk.unchecked_add(&lhs, &rhs))
+}
+
+/// Bootstraps a ciphertext with an identity lookup table, resetting its noise but keeping its degree
+fn refresh(ct: &Ciphertext, pk: &ServerKey) -> Ciphertext {
+    let lut = pk.generate_lookup_table(|x| x);
+    let mut refreshed = pk.apply_lookup_table(ct, &lut);
+    refreshed.degree = ct.degree;
+    refreshed
+}
+
+/// Computes encrypted "value >= scalar" for a clean little-endian radix value
+pub fn radix_scalar_greater_or_equal(blocks: &[Ciphertext], scalar: u64, pk: &ServerKey) -> Result<Ciphertext, Box<dyn Error>> {
+    if blocks.is_empty() {
+        return Err("Cannot compare an empty radix value".into());
+    }
+    let message_modulus = pk.message_modulus.0 as u64;
+    
+    let mut digits = Vec::with_capacity(blocks.len());
+    let mut rest = scalar;
+    for _ in blocks {
+        digits.push((rest % message_modulus) as u8);
+        rest /= message_modulus;
+    }
+    if rest > 0 {
+        // The scalar is beyond anything the blocks can represent
+        return Ok(pk.unchecked_scalar_mul(&blocks[0], 0));
+    }
+    
+    // Walk from the least significant block: a higher block decides unless it is equal
+    let mut greater_or_equal = pk.scalar_greater_or_equal(&blocks[0], digits[0]);
+    for (block, digit) in blocks.iter().zip(digits).skip(1) {
+        let greater = pk.scalar_greater(block, digit);
+        let equal = pk.scalar_equal(block, digit);
+        let carried = pk.bitand(&equal, &greater_or_equal);
+        // The two cases are mutually exclusive, so their sum is still a boolean
+        greater_or_equal = checked_add(&greater, &carried, pk)?;
+    }
+    Ok(greater_or_equal)
+}
+
+/// Encrypts a plaintext as trivial little-endian radix blocks, padded to at least `num_blocks`
+pub fn trivial_radix(value: u64, num_blocks: usize, pk: &ServerKey) -> Vec<Ciphertext> {
+    let message_modulus = pk.message_modulus.0 as u64;
+    let mut digits = scalar_digits(value, message_modulus);
+    digits.resize(digits.len().max(num_blocks).max(1), 0);
+    digits.into_iter().map(|digit| pk.create_trivial(digit)).collect()
+}
+
+/// Splits a plaintext into its little-endian digits in base `message_modulus`
+fn scalar_digits(scalar: u64, message_modulus: u64) -> Vec<u64> {
+    let mut digits = Vec::new();
+    let mut rest = scalar;
+    while rest > 0 {
+        digits.push(rest % message_modulus);
+        rest /= message_modulus;
+    }
+    digits
+}
+
+/// Multiplies a clean radix value by a plaintext scalar, returning clean blocks
+///
+/// Every block-digit product is split into a low and a high digit with two lookup
+/// tables, and the partial products are summed with carry propagation.
+pub fn radix_scalar_mul(blocks: &[Ciphertext], scalar: u64, pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if blocks.is_empty() {
+        return Err("Cannot multiply an empty radix value".into());
+    }
+    let message_modulus = pk.message_modulus.0 as u64;
+    let digits = scalar_digits(scalar, message_modulus);
+    
+    let mut partials = Vec::new();
+    for i in 0..blocks.len() {
+        for (j, &digit) in digits.iter().enumerate().filter(|(_, &digit)| digit != 0) {
+            partials.push((i, i + j, digit, false));
+            if digit * (message_modulus - 1) >= message_modulus {
+                partials.push((i, i + j + 1, digit, true));
+            }
+        }
+    }
+    let products: Vec<(usize, Ciphertext)> = partials
+        .par_iter()
+        .map(|&(i, position, digit, high)| {
+            let product = match (digit, high) {
+                (1, false) => blocks[i].clone(),
+                (_, false) => pk.apply_lookup_table(&blocks[i], &pk.generate_lookup_table(move |x| x * digit % message_modulus)),
+                (_, true) => pk.apply_lookup_table(&blocks[i], &pk.generate_lookup_table(move |x| x * digit / message_modulus)),
+            };
+            (position, product)
+        })
+        .collect();
+    
+    // The product of an n-block value and a d-digit scalar fits in n + d blocks
+    let mut accumulator = EncryptedAccumulator::from_radix(&[pk.create_trivial(0)], blocks.len() + digits.len().max(1))?;
+    for (position, product) in &products {
+        accumulator.add_radix_at(std::slice::from_ref(product), *position, pk)?;
+    }
+    accumulator.finish(pk)
+}
+
+/// Multiplies two clean radix values, returning clean blocks
+///
+/// Every pair of blocks is multiplied with two bivariate lookup tables, one for the low
+/// and one for the high digit of the product, and the partial products are summed.
+pub fn radix_mul(lhs: &[Ciphertext], rhs: &[Ciphertext], pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if lhs.is_empty() || rhs.is_empty() {
+        return Err("Cannot multiply an empty radix value".into());
+    }
+    let message_modulus = pk.message_modulus.0 as u64;
+    let low = pk.generate_lookup_table_bivariate(move |l, r| l * r % message_modulus);
+    let high = pk.generate_lookup_table_bivariate(move |l, r| l * r / message_modulus);
+    
+    let pairs: Vec<(usize, usize)> = (0..lhs.len())
+        .flat_map(|i| (0..rhs.len()).map(move |j| (i, j)))
+        .collect();
+    let products: Vec<(usize, Ciphertext, Ciphertext)> = pairs
+        .par_iter()
+        .map(|&(i, j)| {
+            (
+                i + j,
+                pk.apply_lookup_table_bivariate(&lhs[i], &rhs[j], &low),
+                pk.apply_lookup_table_bivariate(&lhs[i], &rhs[j], &high),
+            )
+        })
+        .collect();
+    
+    // The product of an n-block and a k-block value fits in n + k blocks
+    let mut accumulator = EncryptedAccumulator::from_radix(&[pk.create_trivial(0)], lhs.len() + rhs.len())?;
+    for (position, low, high) in &products {
+        accumulator.add_radix_at(std::slice::from_ref(low), *position, pk)?;
+        accumulator.add_radix_at(std::slice::from_ref(high), position + 1, pk)?;
+    }
+    accumulator.finish(pk)
+}
+
+/// Computes encrypted "lhs <= rhs" for clean little-endian radix values of any lengths
+pub fn radix_less_or_equal(lhs: &[Ciphertext], rhs: &[Ciphertext], pk: &ServerKey) -> Result<Ciphertext, Box<dyn Error>> {
+    let num_blocks = lhs.len().max(rhs.len());
+    if num_blocks == 0 {
+        return Err("Cannot compare empty radix values".into());
+    }
+    let zero = pk.create_trivial(0);
+    let block = |blocks: &[Ciphertext], i: usize| blocks.get(i).cloned().unwrap_or_else(|| zero.clone());
+    
+    // Compare the blocks independently: 0 when less, 1 when equal, 2 when greater
+    let compare = pk.generate_lookup_table_bivariate(|l, r| match l.cmp(&r) {
+        std::cmp::Ordering::Less => 0,
+        std::cmp::Ordering::Equal => 1,
+        std::cmp::Ordering::Greater => 2,
+    });
+    let comparisons: Vec<Ciphertext> = (0..num_blocks)
+        .into_par_iter()
+        .map(|i| pk.apply_lookup_table_bivariate(&block(lhs, i), &block(rhs, i), &compare))
+        .collect();
+    
+    // Walk up from the least significant block: a higher block decides unless it is equal
+    let mut less_or_equal = pk.apply_lookup_table(&comparisons[0], &pk.generate_lookup_table(|cmp| (cmp <= 1) as u64));
+    let decide = pk.generate_lookup_table_bivariate(|cmp, below| if cmp == 1 { below } else { (cmp == 0) as u64 });
+    for comparison in &comparisons[1..] {
+        less_or_equal = pk.apply_lookup_table_bivariate(comparison, &less_or_equal, &decide);
+    }
+    Ok(less_or_equal)
+}
+
+/// Computes encrypted "max(lhs - rhs, 0)" for clean little-endian radix values
+pub fn radix_saturating_sub(lhs: &[Ciphertext], rhs: &[Ciphertext], pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let num_blocks = lhs.len().max(rhs.len());
+    if num_blocks == 0 {
+        return Err("Cannot subtract empty radix values".into());
+    }
+    let message_modulus = pk.message_modulus.0 as u64;
+    let zero = pk.create_trivial(0);
+    let block = |blocks: &[Ciphertext], i: usize| blocks.get(i).cloned().unwrap_or_else(|| zero.clone());
+    
+    // Subtract the blocks independently, noting which ones borrow
+    let difference = pk.generate_lookup_table_bivariate(move |l, r| (l + message_modulus - r) % message_modulus);
+    let borrows = pk.generate_lookup_table_bivariate(|l, r| (l < r) as u64);
+    let blockwise: Vec<(Ciphertext, Ciphertext)> = (0..num_blocks)
+        .into_par_iter()
+        .map(|i| {
+            let (l, r) = (block(lhs, i), block(rhs, i));
+            (pk.apply_lookup_table_bivariate(&l, &r, &difference), pk.apply_lookup_table_bivariate(&l, &r, &borrows))
+        })
+        .collect();
+    
+    // Ripple the borrows up; a block that borrowed has a nonzero difference, so it cannot borrow twice
+    let minus_borrow = pk.generate_lookup_table_bivariate(move |d, b| (d + message_modulus - b) % message_modulus);
+    let borrows_again = pk.generate_lookup_table_bivariate(|d, b| (d < b) as u64);
+    let mut differences = Vec::with_capacity(num_blocks);
+    let mut borrow: Option<Ciphertext> = None;
+    for (block_difference, block_borrow) in blockwise {
+        match borrow {
+            None => {
+                differences.push(block_difference);
+                borrow = Some(block_borrow);
+            }
+            Some(incoming) => {
+                differences.push(pk.apply_lookup_table_bivariate(&block_difference, &incoming, &minus_borrow));
+                let again = pk.apply_lookup_table_bivariate(&block_difference, &incoming, &borrows_again);
+                borrow = Some(checked_add(&block_borrow, &again, pk)?);
+            }
+        }
+    }
+    
+    // A borrow out of the top block means lhs < rhs, so the result saturates at zero
+    let borrow = borrow.ok_or("Cannot subtract empty radix values")?;
+    let zero_if_borrowed = pk.generate_lookup_table_bivariate(|d, b| if b == 0 { d } else { 0 });
+    Ok(differences
+        .par_iter()
+        .map(|d| pk.apply_lookup_table_bivariate(d, &borrow, &zero_if_borrowed))
+        .collect())
+}
+
+/// Divides a clean radix value by a plaintext divisor, rounding down
+///
+/// Multiplies by a precomputed reciprocal and drops whole low blocks, which is exact for
+/// every value the blocks can hold.
+pub fn radix_scalar_div(blocks: &[Ciphertext], divisor: u64, pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if divisor == 0 {
+        return Err("Cannot divide by zero".into());
+    }
+    if blocks.is_empty() {
+        return Err("Cannot divide an empty radix value".into());
+    }
+    if divisor == 1 {
+        return Ok(blocks.to_vec());
+    }
+    
+    // floor(x * m / 2^s) = floor(x / d) for x < 2^n when m = ceil(2^s / d) and s >= n + log2(d)
+    let message_bits = pk.message_modulus.0.trailing_zeros();
+    let value_bits = blocks.len() as u32 * message_bits;
+    let divisor_bits = u64::BITS - (divisor - 1).leading_zeros();
+    let shift_blocks = (value_bits + divisor_bits + message_bits - 1) / message_bits;
+    let shift = shift_blocks * message_bits;
+    if shift >= 128 {
+        return Err(format!("Cannot divide a {}-bit value by {}", value_bits, divisor).into());
+    }
+    let reciprocal: u64 = ((1u128 << shift) + divisor as u128 - 1)
+        .checked_div(divisor as u128)
+        .and_then(|m| m.try_into().ok())
+        .ok_or_else(|| format!("Cannot divide a {}-bit value by {}", value_bits, divisor))?;
+    
+    let product = radix_scalar_mul(blocks, reciprocal, pk)?;
+    let mut quotient: Vec<Ciphertext> = product.into_iter().skip(shift_blocks as usize).collect();
+    // The quotient is never larger than the dividend, so the extra high blocks are zero
+    quotient.truncate(blocks.len());
+    if quotient.is_empty() {
+        quotient.push(pk.create_trivial(0));
+    }
+    Ok(quotient)
+}
+
+/// Ratio bucket when expenses are within half of the maximum expense ratio
+pub const RATIO_BUCKET_LOW: u64 = 0;
+/// Ratio bucket when expenses are within the maximum expense ratio
+pub const RATIO_BUCKET_WITHIN_LIMIT: u64 = 1;
+/// Ratio bucket when expenses exceed the maximum expense ratio
+pub const RATIO_BUCKET_EXCEEDED: u64 = 2;
+
+/// Computes an encrypted boolean "total expenses * 100 <= max_expense_ratio * salary" using only the server key
+///
+/// The salary and expense total are given as clean little-endian radix blocks.
+pub fn compute_encrypted_expense_ratio_check(
+    encrypted_salary: &[Ciphertext],
+    encrypted_total_expenses: &[Ciphertext],
+    max_expense_ratio: u64,
+    pk: &ServerKey,
+) -> Result<Ciphertext, Box<dyn Error>> {
+    encrypted_scaled_less_or_equal(encrypted_total_expenses, 100, encrypted_salary, max_expense_ratio, pk)
+}
+
+/// Computes an encrypted expense-to-income ratio bucket using only the server key
+///
+/// The salary and expense total are given as clean little-endian radix blocks.
+pub fn compute_encrypted_expense_ratio_bucket(
+    encrypted_salary: &[Ciphertext],
+    encrypted_total_expenses: &[Ciphertext],
+    max_expense_ratio: u64,
+    pk: &ServerKey,
+) -> Result<Ciphertext, Box<dyn Error>> {
+    let total = encrypted_total_expenses;
+    
+    // Each check is an encrypted 0/1, so their sum counts the limits that are met
+    let within_half = encrypted_scaled_less_or_equal(total, 200, encrypted_salary, max_expense_ratio, pk)?;
+    let within_limit = encrypted_scaled_less_or_equal(total, 100, encrypted_salary, max_expense_ratio, pk)?;
+    let limits_met = checked_add(&within_half, &within_limit, pk)?;
+    
+    let lut = pk.generate_lookup_table(|met| RATIO_BUCKET_EXCEEDED.saturating_sub(met));
+    Ok(pk.apply_lookup_table(&limits_met, &lut))
+}
+
+/// Sums radix-encrypted expenses homomorphically into clean radix blocks, carrying across as many blocks as the total needs
+pub fn sum_encrypted_expenses(encrypted_expenses: &[Vec<Ciphertext>], pk: &ServerKey) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let (first, rest) = encrypted_expenses
+        .split_first()
+        .ok_or("Cannot sum empty expenses")?;
+    
+    let mut accumulator = EncryptedAccumulator::from_radix(first, MAX_ACCUMULATOR_BLOCKS)?;
+    for expense in rest {
+        accumulator.add_radix(expense, pk)?;
+    }
+    accumulator.finish(pk)
+}
+
+/// Computes encrypted "lhs * lhs_scale <= rhs * rhs_scale" for radix values, reducing the scales by their gcd first
+fn encrypted_scaled_less_or_equal(
+    lhs: &[Ciphertext],
+    lhs_scale: u64,
+    rhs: &[Ciphertext],
+    rhs_scale: u64,
+    pk: &ServerKey,
+) -> Result<Ciphertext, Box<dyn Error>> {
+    // Smaller scalars mean fewer partial products
+    let divisor = gcd(lhs_scale, rhs_scale).max(1);
+    let scaled_lhs = radix_scalar_mul(lhs, lhs_scale / divisor, pk)?;
+    let scaled_rhs = radix_scalar_mul(rhs, rhs_scale / divisor, pk)?;
+    radix_less_or_equal(&scaled_lhs, &scaled_rhs, pk)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Computes the encrypted disposable income "max(salary - total expenses, 0)" as radix blocks
+pub fn compute_encrypted_disposable_income(
+    encrypted_salary: &[Ciphertext],
+    encrypted_total_expenses: &[Ciphertext],
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    radix_saturating_sub(encrypted_salary, encrypted_total_expenses, pk)
+}
+
+/// Computes an encrypted maximum EMI, "disposable income * foir_percent / 100", as radix blocks
+///
+/// FOIR is the lender's fixed-obligation-to-income ratio.
+pub fn compute_encrypted_max_emi(
+    encrypted_disposable_income: &[Ciphertext],
+    foir_percent: u64,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if foir_percent > 100 {
+        return Err(format!("FOIR must be at most 100%, got {}%", foir_percent).into());
+    }
+    let scaled = radix_scalar_mul(encrypted_disposable_income, foir_percent, pk)?;
+    radix_scalar_div(&scaled, 100, pk)
+}
+
+/// Fixed-point scale of the annuity factor applied to the encrypted EMI
+pub const ANNUITY_FACTOR_SCALE: u64 = 1000;
+
+/// Computes an encrypted maximum principal for a plaintext annual interest rate and tenure
+///
+/// The annuity factor is rounded down to a multiple of `1 / ANNUITY_FACTOR_SCALE`, so the
+/// principal never exceeds what the EMI can repay.
+pub fn compute_encrypted_max_principal(
+    encrypted_max_emi: &[Ciphertext],
+    annual_interest_percent: f64,
+    tenure_months: u32,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    if tenure_months == 0 {
+        return Err("Tenure must be at least one month".into());
+    }
+    if !(0.0..100.0).contains(&annual_interest_percent) {
+        return Err(format!("Interest rate {}% is out of range", annual_interest_percent).into());
+    }
+    
+    let scaled_factor = (annuity_factor(annual_interest_percent, tenure_months) * ANNUITY_FACTOR_SCALE as f64).floor() as u64;
+    let scaled = radix_scalar_mul(encrypted_max_emi, scaled_factor, pk)?;
+    radix_scalar_div(&scaled, ANNUITY_FACTOR_SCALE, pk)
+}
+
+/// Computes an encrypted eligibility bit "disposable income * foir_percent >= requested EMI * 100"
+///
+/// Comparing the scaled income avoids dividing by 100 first.
+pub fn compute_encrypted_affordability_check(
+    encrypted_disposable_income: &[Ciphertext],
+    foir_percent: u64,
+    requested_emi: u64,
+    pk: &ServerKey,
+) -> Result<Ciphertext, Box<dyn Error>> {
+    let required = requested_emi
+        .checked_mul(100)
+        .ok_or("Requested EMI is too large")?;
+    let scaled = radix_scalar_mul(encrypted_disposable_income, foir_percent, pk)?;
+    radix_scalar_greater_or_equal(&scaled, required, pk)
+}
+
+/// Decrypts little-endian radix blocks (to be done only by the applicant)
+pub fn decrypt_radix(encrypted_blocks: &[Ciphertext], sk: &ClientKey) -> Result<u64, Box<dyn Error>> {
+    let message_modulus = sk.parameters.message_modulus().0 as u64;
+    let mut value: u64 = 0;
+    for block in encrypted_blocks.iter().rev() {
+        value = value
+            .checked_mul(message_modulus)
+            .and_then(|v| v.checked_add(sk.decrypt(block)))
+            .ok_or("Decrypted value overflows u64")?;
+    }
+    Ok(value)
+}
+
+/// Computes an encrypted boolean "lower <= value < upper" for a clean radix value
+///
+/// An `upper` of `None` leaves the range open-ended.
+pub fn compute_encrypted_range_indicator(
+    encrypted_blocks: &[Ciphertext],
+    lower: u64,
+    upper: Option<u64>,
+    pk: &ServerKey,
+) -> Result<Ciphertext, Box<dyn Error>> {
+    let at_least_lower = radix_scalar_greater_or_equal(encrypted_blocks, lower, pk)?;
+    match upper {
+        None => Ok(at_least_lower),
+        Some(upper) => {
+            let at_least_upper = radix_scalar_greater_or_equal(encrypted_blocks, upper, pk)?;
+            let lut = pk.generate_lookup_table_bivariate(|lo, hi| lo & (1 - hi.min(1)));
+            Ok(pk.apply_lookup_table_bivariate(&at_least_lower, &at_least_upper, &lut))
+        }
+    }
+}
+
+/// Clips a clean radix value to at most `clip`, bounding any one value's contribution to a sum
+pub fn compute_encrypted_clipped_radix(
+    encrypted_blocks: &[Ciphertext],
+    clip: u64,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn Error>> {
+    let message_modulus = pk.message_modulus.0 as u64;
+    let exceeds = radix_scalar_greater_or_equal(encrypted_blocks, clip, pk)?;
+    
+    // Replace every block with the matching digit of the clip when the value reaches it
+    let mut rest = clip;
+    let clipped = encrypted_blocks
+        .iter()
+        .map(|block| {
+            let digit = rest % message_modulus;
+            rest /= message_modulus;
+            let lut = pk.generate_lookup_table_bivariate(move |value, exceeds| if exceeds == 1 { digit } else { value });
+            pk.apply_lookup_table_bivariate(block, &exceeds, &lut)
+        })
+        .collect();
+    Ok(clipped)
+}
+
+/// Present value of one unit of EMI paid monthly over the tenure
+fn annuity_factor(annual_interest_percent: f64, tenure_months: u32) -> f64 {
+    let monthly_rate = annual_interest_percent / 100.0 / 12.0;
+    if monthly_rate == 0.0 {
+        return tenure_months as f64;
+    }
+    (1.0 - (1.0 + monthly_rate).powi(-(tenure_months as i32))) / monthly_rate
+}
+
+/// Checks if encrypted salary is greater than a threshold without decrypting
+pub fn is_salary_greater_than_threshold(
+    encrypted_salary: &[Ciphertext],
+    threshold: u64,
+    sk: &ClientKey,
+) -> Result<bool, Box<dyn Error>> {
+    let decrypted_salary = decrypt_radix(encrypted_salary, sk)?;
+    Ok(decrypted_salary > threshold)
+}
+
+/// Decrypts the average expense (to be done only by the authorized entity)
+pub fn decrypt_average_expense(encrypted_avg: &[Ciphertext], sk: &ClientKey) -> Result<u64, Box<dyn Error>> {
+    decrypt_radix(encrypted_avg, sk)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    // One month of the example applicant's expenses, plus a second utilities bill
+    const EXAMPLE_SALARY: u64 = 6000;
+    const EXAMPLE_EXPENSES: [(ExpenseCategory, u64); 7] = [
+        (ExpenseCategory::Rent, 1200),
+        (ExpenseCategory::Emi, 800),
+        (ExpenseCategory::Utilities, 350),
+        (ExpenseCategory::Discretionary, 450),
+        (ExpenseCategory::Discretionary, 200),
+        (ExpenseCategory::Utilities, 150),
+        (ExpenseCategory::Emi, 0),
+    ];
+
+    fn keys() -> &'static (ClientKey, ServerKey) {
+        static KEYS: OnceLock<(ClientKey, ServerKey)> = OnceLock::new();
+        KEYS.get_or_init(|| {
+            let client_key = ClientKey::new(FheParameterSet::Message2Carry2.parameters());
+            let server_key = ServerKey::new(&client_key);
+            (client_key, server_key)
+        })
+    }
+
+    fn example_expenses() -> Vec<CategorizedExpense> {
+        EXAMPLE_EXPENSES
+            .iter()
+            .map(|&(category, amount)| CategorizedExpense { category, amount })
+            .collect()
+    }
+
+    fn example_total() -> u64 {
+        EXAMPLE_EXPENSES.iter().map(|(_, amount)| amount).sum()
+    }
+
+    #[test]
+    fn radix_encryption_rejects_oversized_values() {
+        let (client_key, _) = keys();
+        assert!(encrypt_radix(1 << EXPENSE_BITS, EXPENSE_BITS, client_key).is_err());
+    }
+
+    #[test]
+    fn sum_of_many_expenses_carries_into_higher_blocks() {
+        let (client_key, server_key) = keys();
+        let expenses = vec![1200, 800, 350, 450, 200, 150, 999, 4095];
+        let encrypted = encrypt_expenses(expenses.clone(), client_key).unwrap();
+
+        let total = sum_encrypted_expenses(&encrypted, server_key).unwrap();
+        assert_eq!(decrypt_radix(&total, client_key).unwrap(), expenses.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn weighted_sum_uses_default_weights() {
+        let (client_key, server_key) = keys();
+        let weights = CategoryWeights::default();
+        let expected: u64 = EXAMPLE_EXPENSES.iter().map(|&(category, amount)| weights.weight(category) as u64 * amount).sum();
+        let encrypted = encrypt_categorized_expenses(example_expenses(), client_key).unwrap();
+
+        let total = compute_encrypted_weighted_expense_sum(&encrypted, &weights, server_key).unwrap();
+        assert_eq!(decrypt_radix(&total, client_key).unwrap(), expected);
+    }
+
+    #[test]
+    fn tree_reduction_matches_serial_sum() {
+        let (client_key, server_key) = keys();
+        let pool = build_fhe_thread_pool(DEFAULT_FHE_THREADS).unwrap();
+        let expenses: Vec<u64> = (0..13).map(|i| 250 + i * 137).collect();
+        let encrypted = encrypt_expenses(expenses.clone(), client_key).unwrap();
+
+        let total = tree_reduce_sum_parallel(&encrypted, server_key, &pool).unwrap();
+        assert_eq!(decrypt_radix(&total, client_key).unwrap(), expenses.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn average_expense_matches_plaintext_mean() {
+        let (client_key, server_key) = keys();
+        let amounts: Vec<u64> = EXAMPLE_EXPENSES.iter().map(|(_, amount)| *amount).collect();
+        let encrypted = encrypt_expenses(amounts.clone(), client_key).unwrap();
+
+        let average = compute_encrypted_average_expense_with_noise(&encrypted, DpNoise::None, server_key).unwrap();
+        assert_eq!(decrypt_average_expense(&average, client_key).unwrap(), example_total() / amounts.len() as u64);
+    }
+
+    #[test]
+    fn noise_offset_is_removed_after_decryption() {
+        let (client_key, server_key) = keys();
+        let amounts: Vec<u64> = EXAMPLE_EXPENSES.iter().map(|(_, amount)| *amount).collect();
+        let encrypted = encrypt_expenses(amounts.clone(), client_key).unwrap();
+        // A zero scale isolates the offset
+        let noise = DpNoise::Laplace { scale: 0.0, offset: 1 << 20 };
+
+        let average = compute_encrypted_average_expense_with_noise(&encrypted, noise, server_key).unwrap();
+        let released = decrypt_average_expense(&average, client_key).unwrap() - (1 << 20);
+        assert_eq!(released, example_total() / amounts.len() as u64);
+    }
+
+    #[test]
+    fn laplace_scale_is_sensitivity_over_epsilon() {
+        let noise = DpNoise::laplace_for_average(6000, 3, 0.5, 1 << 20);
+        assert_eq!(noise, DpNoise::Laplace { scale: 4000.0, offset: 1 << 20 });
+        assert_eq!(DpNoise::laplace_for_sensitivity(4.0, 0.5, 0), DpNoise::Laplace { scale: 8.0, offset: 0 });
+    }
+
+    #[test]
+    fn max_and_min_match_plaintext() {
+        let (client_key, server_key) = keys();
+        let amounts = [350u64, 1200, 200, 800];
+        let encrypted: Vec<_> = amounts.iter().map(|&a| encrypt_radix(a, 16, client_key).unwrap()).collect();
+
+        let max = compute_encrypted_max_expense(&encrypted, DpNoise::None, server_key).unwrap();
+        assert_eq!(decrypt_radix(&max, client_key).unwrap(), 1200);
+        let min = compute_encrypted_min_expense(&encrypted, DpNoise::None, server_key).unwrap();
+        assert_eq!(decrypt_radix(&min, client_key).unwrap(), 200);
+        assert!(compute_encrypted_max_expense(&[], DpNoise::None, server_key).is_err());
+    }
+
+    #[test]
+    fn trend_compares_each_month_with_the_previous() {
+        let (client_key, server_key) = keys();
+        let amounts = [300u64, 500, 500, 100];
+        let encrypted: Vec<_> = amounts.iter().map(|&a| encrypt_radix(a, 16, client_key).unwrap()).collect();
+
+        let trend: Vec<u64> = compute_encrypted_expense_trend(&encrypted, DpNoise::None, server_key)
+            .unwrap()
+            .iter()
+            .map(|indicator| decrypt_radix(indicator, client_key).unwrap())
+            .collect();
+        assert_eq!(trend, vec![TREND_RISING, TREND_FLAT, TREND_FALLING]);
+        assert!(compute_encrypted_expense_trend(&encrypted[..1], DpNoise::None, server_key).is_err());
+    }
+
+    #[test]
+    fn variance_of_clipped_expenses_matches_plaintext() {
+        let (client_key, server_key) = keys();
+        // Mean 30, squared deviations 400, 100, 0 and 900
+        let amounts = [10u64, 20, 30, 60];
+        let encrypted: Vec<_> = amounts.iter().map(|&a| encrypt_radix(a, 8, client_key).unwrap()).collect();
+
+        let variance = compute_encrypted_expense_variance(&encrypted, 100, DpNoise::None, server_key).unwrap();
+        assert_eq!(decrypt_radix(&variance, client_key).unwrap(), 350);
+    }
+
+    #[test]
+    fn radix_arithmetic_matches_plaintext() {
+        let (client_key, server_key) = keys();
+        let (a, b) = (12345u64, 9876u64);
+        let (ea, eb) = (encrypt_radix(a, 16, client_key).unwrap(), encrypt_radix(b, 16, client_key).unwrap());
+
+        let product = radix_scalar_mul(&ea, 37, server_key).unwrap();
+        assert_eq!(decrypt_radix(&product, client_key).unwrap(), a * 37);
+        let product = radix_mul(&ea, &eb, server_key).unwrap();
+        assert_eq!(decrypt_radix(&product, client_key).unwrap(), a * b);
+        let quotient = radix_scalar_div(&ea, 7, server_key).unwrap();
+        assert_eq!(decrypt_radix(&quotient, client_key).unwrap(), a / 7);
+        let difference = radix_saturating_sub(&ea, &eb, server_key).unwrap();
+        assert_eq!(decrypt_radix(&difference, client_key).unwrap(), a - b);
+        let saturated = radix_saturating_sub(&eb, &ea, server_key).unwrap();
+        assert_eq!(decrypt_radix(&saturated, client_key).unwrap(), 0);
+        assert_eq!(client_key.decrypt(&radix_less_or_equal(&eb, &ea, server_key).unwrap()), 1);
+        assert_eq!(client_key.decrypt(&radix_less_or_equal(&ea, &eb, server_key).unwrap()), 0);
+        assert_eq!(client_key.decrypt(&radix_less_or_equal(&ea, &ea, server_key).unwrap()), 1);
+    }
+
+    #[test]
+    fn ratio_bucket_at_realistic_amounts() {
+        let (client_key, server_key) = keys();
+        let encrypted = encrypt_categorized_expenses(example_expenses(), client_key).unwrap();
+        let blocks: Vec<Vec<Ciphertext>> = encrypted.into_iter().map(|e| e.blocks).collect();
+        let total = sum_encrypted_expenses(&blocks, server_key).unwrap();
+        let salary = |value: u64| encrypt_radix(value, SALARY_BITS, client_key).unwrap();
+
+        // A total of 3150 is within half of a 50% limit on 13000, exactly at it on 6300 and over it on 6000
+        let low = compute_encrypted_expense_ratio_bucket(&salary(13000), &total, 50, server_key).unwrap();
+        assert_eq!(client_key.decrypt(&low), RATIO_BUCKET_LOW);
+        let within = compute_encrypted_expense_ratio_bucket(&salary(6300), &total, 50, server_key).unwrap();
+        assert_eq!(client_key.decrypt(&within), RATIO_BUCKET_WITHIN_LIMIT);
+        let exceeded = compute_encrypted_expense_ratio_bucket(&salary(EXAMPLE_SALARY), &total, 50, server_key).unwrap();
+        assert_eq!(client_key.decrypt(&exceeded), RATIO_BUCKET_EXCEEDED);
+    }
+
+    #[test]
+    fn loan_offer_matches_plaintext_at_realistic_amounts() {
+        let (client_key, server_key) = keys();
+        let (foir, rate, tenure) = (50, 10.5, 36);
+        let salary = encrypt_radix(EXAMPLE_SALARY, SALARY_BITS, client_key).unwrap();
+        let total = encrypt_radix(example_total(), EXPENSE_BITS, client_key).unwrap();
+
+        let disposable = compute_encrypted_disposable_income(&salary, &total, server_key).unwrap();
+        let expected_disposable = EXAMPLE_SALARY - example_total();
+        assert_eq!(decrypt_radix(&disposable, client_key).unwrap(), expected_disposable);
+
+        let max_emi = compute_encrypted_max_emi(&disposable, foir, server_key).unwrap();
+        let expected_emi = expected_disposable * foir / 100;
+        assert_eq!(decrypt_radix(&max_emi, client_key).unwrap(), expected_emi);
+
+        let principal = compute_encrypted_max_principal(&max_emi, rate, tenure, server_key).unwrap();
+        let factor = (annuity_factor(rate, tenure) * ANNUITY_FACTOR_SCALE as f64).floor() as u64;
+        assert_eq!(decrypt_radix(&principal, client_key).unwrap(), expected_emi * factor / ANNUITY_FACTOR_SCALE);
+
+        let affordable = compute_encrypted_affordability_check(&disposable, foir, expected_emi, server_key).unwrap();
+        assert_eq!(client_key.decrypt(&affordable), 1);
+        let unaffordable = compute_encrypted_affordability_check(&disposable, foir, expected_emi + 1, server_key).unwrap();
+        assert_eq!(client_key.decrypt(&unaffordable), 0);
+    }
+
+    #[test]
+    fn laplace_noise_is_always_finite() {
+        for _ in 0..100_000 {
+            assert!(sample_laplace(1.0).is_finite());
+        }
+    }
+
+    #[test]
+    fn comparing_an_empty_radix_value_fails() {
+        let (_, server_key) = keys();
+        assert!(radix_scalar_greater_or_equal(&[], 1, server_key).is_err());
+        assert!(radix_less_or_equal(&[], &[], server_key).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tfhe::shortint::prelude::*;
+use tfhe::shortint::CompactPublicKey;
+
+use crate::fhe_params::FheParameterSet;
+use crate::threshold_decryption::{self, SPLIT_PARTIES};
+
+/// Default directory holding persisted keys
+pub const DEFAULT_KEY_STORE_DIR: &str = "keys";
+
+/// Default interval after which the active key is rotated
+pub const DEFAULT_ROTATION_INTERVAL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often a running NBFC checks whether the active key is due for rotation
+pub const ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Environment variable holding the passphrase client keys are encrypted under
+pub const KEY_PASSPHRASE_ENV: &str = "CREDIT_KEY_PASSPHRASE";
+
+/// PBKDF2 iterations used to derive the at-rest encryption key from a passphrase
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+const ACTIVE_KEY_FILE: &str = "ACTIVE";
+
+/// Length of a key ID in hex digits
+const KEY_ID_LEN: usize = 32;
+
+/// Stable identifier for a generated key set
+pub type KeyId = String;
+
+/// Metadata recorded alongside every key set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    pub key_id: KeyId,
+    pub parameter_set: FheParameterSet,
+    pub created_at: u64,
+    pub retired_at: Option<u64>,
+}
+
+/// Client key encrypted under a passphrase-derived key
+#[derive(Serialize, Deserialize)]
+struct EncryptedClientKey {
+    salt: [u8; 16],
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+/// File-backed store for FHE key sets
+///
+/// Client keys are encrypted at rest with ChaCha20-Poly1305 under a PBKDF2-derived key.
+/// Server and public keys are stored as plain bincode since they hold no secret material.
+#[derive(Debug)]
+pub struct KeyStore {
+    dir: PathBuf,
+    rotation_interval: Duration,
+    parameter_set: FheParameterSet,
+}
+
+impl KeyStore {
+    /// Opens a key store rooted at `dir`, creating the directory if needed
+    pub fn open<P: AsRef<Path>>(dir: P, rotation_interval: Duration) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            rotation_interval,
+            parameter_set: FheParameterSet::default(),
+        })
+    }
+
+    /// Generates future key sets under the given parameter set
+    pub fn with_parameter_set(mut self, parameter_set: FheParameterSet) -> Result<Self, Box<dyn Error>> {
+        if !parameter_set.supports_compact_public_key() {
+            return Err(format!("Parameter set {} does not support compact public keys", parameter_set).into());
+        }
+        self.parameter_set = parameter_set;
+        Ok(self)
+    }
+
+    /// Generates a new key set, persists it and makes it the active key
+    pub fn generate_keys(&self, passphrase: &str) -> Result<KeyId, Box<dyn Error>> {
+        let client_key = ClientKey::new(self.parameter_set.parameters());
+        let server_key = ServerKey::new(&client_key);
+        let public_key = CompactPublicKey::new(&client_key);
+
+        let key_id = new_key_id();
+        fs::write(self.path(&key_id, "client.key")?, seal_client_key(&client_key, passphrase)?)?;
+        fs::write(self.path(&key_id, "server.key")?, bincode::serialize(&server_key)?)?;
+        fs::write(self.path(&key_id, "public.key")?, bincode::serialize(&public_key)?)?;
+
+        let metadata = KeyMetadata {
+            key_id: key_id.clone(),
+            parameter_set: self.parameter_set,
+            created_at: unix_now(),
+            retired_at: None,
+        };
+        self.write_metadata(&metadata)?;
+        fs::write(self.dir.join(ACTIVE_KEY_FILE), &key_id)?;
+
+        Ok(key_id)
+    }
+
+    /// Returns the ID of the active key set, if any
+    pub fn active_key_id(&self) -> Result<Option<KeyId>, Box<dyn Error>> {
+        let path = self.dir.join(ACTIVE_KEY_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?.trim().to_string()))
+    }
+
+    /// Returns the metadata recorded for a key set
+    pub fn metadata(&self, key_id: &str) -> Result<KeyMetadata, Box<dyn Error>> {
+        let contents = fs::read_to_string(self.path(key_id, "meta.json")?)
+            .map_err(|e| format!("Unknown key ID {}: {}", key_id, e))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Loads and decrypts a client key with the passphrase it was stored under
+    pub fn load_client_key(&self, key_id: &str, passphrase: &str) -> Result<ClientKey, Box<dyn Error>> {
+        let sealed = fs::read(self.path(key_id, "client.key")?)
+            .map_err(|e| format!("Unknown key ID {}: {}", key_id, e))?;
+        open_client_key(&sealed, passphrase)
+    }
+
+    /// Loads the server key the NBFC evaluates under
+    pub fn load_server_key(&self, key_id: &str) -> Result<ServerKey, Box<dyn Error>> {
+        let data = fs::read(self.path(key_id, "server.key")?)
+            .map_err(|e| format!("Unknown key ID {}: {}", key_id, e))?;
+        Ok(bincode::deserialize(&data)?)
+    }
+
+    /// Returns the SHA-256 of the stored server key, to check uploaded copies against
+    pub fn server_key_sha256(&self, key_id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = fs::read(self.path(key_id, "server.key")?)
+            .map_err(|e| format!("Unknown key ID {}: {}", key_id, e))?;
+        Ok(Sha256::digest(&data).to_vec())
+    }
+
+    /// Loads the serialized compact public key applicants encrypt under
+    pub fn load_public_key_bytes(&self, key_id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = fs::read(self.path(key_id, "public.key")?)
+            .map_err(|e| format!("Unknown key ID {}: {}", key_id, e))?;
+        Ok(data)
+    }
+
+    /// Splits a key set's client key between the Bank and the NBFC, then deletes the sealed full key
+    ///
+    /// Returns each party with the path of its share. Afterwards nothing can decrypt under the
+    /// key set without both parties.
+    pub fn split_client_key(&self, key_id: &str, passphrase: &str) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>> {
+        let client_key = self.load_client_key(key_id, passphrase)?;
+        let shares = threshold_decryption::split_client_key(client_key, &SPLIT_PARTIES)?;
+        let mut paths = Vec::with_capacity(shares.len());
+        for share in &shares {
+            let path = self.path(key_id, &format!("{}.share", share.party))?;
+            share.save(&path)?;
+            paths.push((share.party.clone(), path));
+        }
+        fs::remove_file(self.path(key_id, "client.key")?)?;
+        Ok(paths)
+    }
+
+    /// Rotates the active key if it is older than the rotation interval, returning the new key ID
+    ///
+    /// The retired key set stays on disk so ciphertexts tagged with it can still be processed.
+    pub fn rotate_if_due(&self, passphrase: &str) -> Result<Option<KeyId>, Box<dyn Error>> {
+        let active = match self.active_key_id()? {
+            Some(key_id) => self.metadata(&key_id)?,
+            None => return Ok(Some(self.generate_keys(passphrase)?)),
+        };
+
+        let age = unix_now().saturating_sub(active.created_at);
+        if age < self.rotation_interval.as_secs() {
+            return Ok(None);
+        }
+
+        let new_key_id = self.generate_keys(passphrase)?;
+        self.write_metadata(&KeyMetadata {
+            retired_at: Some(unix_now()),
+            ..active
+        })?;
+        Ok(Some(new_key_id))
+    }
+
+    fn write_metadata(&self, metadata: &KeyMetadata) -> Result<(), Box<dyn Error>> {
+        fs::write(self.path(&metadata.key_id, "meta.json")?, serde_json::to_string_pretty(metadata)?)?;
+        Ok(())
+    }
+
+    // Key IDs arrive in requests, so they are checked before they name a file
+    fn path(&self, key_id: &str, suffix: &str) -> Result<PathBuf, Box<dyn Error>> {
+        validate_key_id(key_id)?;
+        Ok(self.dir.join(format!("{}.{}", key_id, suffix)))
+    }
+}
+
+/// Checks a key ID has the form `new_key_id` produces: 32 lowercase hex digits
+pub fn validate_key_id(key_id: &str) -> Result<(), String> {
+    let well_formed = key_id.len() == KEY_ID_LEN
+        && key_id.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if !well_formed {
+        return Err(format!("Invalid key ID {:?}: expected {} lowercase hex digits", key_id, KEY_ID_LEN));
+    }
+    Ok(())
+}
+
+/// Periodically checks whether the active key is due for rotation
+///
+/// The first check runs one `check_every` after the call. Key generation blocks for seconds,
+/// so each check runs on the blocking thread pool.
+pub async fn run_rotation_schedule(store: Arc<KeyStore>, passphrase: String, check_every: Duration) {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + check_every, check_every);
+    loop {
+        interval.tick().await;
+        let store = store.clone();
+        let passphrase = passphrase.clone();
+        let rotated = tokio::task::spawn_blocking(move || store.rotate_if_due(&passphrase).map_err(|e| e.to_string()))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        match rotated {
+            Ok(Some(key_id)) => println!("Rotated FHE keys, active key is now {}", key_id),
+            Ok(None) => {}
+            Err(e) => eprintln!("Key rotation failed: {}", e),
+        }
+    }
+}
+
+fn new_key_id() -> KeyId {
+    let mut bytes = [0u8; KEY_ID_LEN / 2];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
+    Key::from(key)
+}
+
+fn seal_client_key(client_key: &ClientKey, passphrase: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+    let plaintext = bincode::serialize(client_key)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| "Failed to encrypt client key")?;
+
+    Ok(bincode::serialize(&EncryptedClientKey { salt, nonce, ciphertext })?)
+}
+
+fn open_client_key(sealed: &[u8], passphrase: &str) -> Result<ClientKey, Box<dyn Error>> {
+    let sealed: EncryptedClientKey = bincode::deserialize(sealed)?;
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &sealed.salt));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&sealed.nonce), sealed.ciphertext.as_slice())
+        .map_err(|_| "Failed to decrypt client key: wrong passphrase or corrupted key file")?;
+
+    Ok(bincode::deserialize(&plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str, rotation_interval: Duration) -> (PathBuf, KeyStore) {
+        let dir = std::env::temp_dir().join(format!("credit-key-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = KeyStore::open(&dir, rotation_interval).unwrap();
+        (dir, store)
+    }
+
+    #[test]
+    fn key_ids_are_lowercase_hex_only() {
+        assert!(validate_key_id(&new_key_id()).is_ok());
+        assert!(validate_key_id("").is_err());
+        assert!(validate_key_id(&"A".repeat(KEY_ID_LEN)).is_err());
+        assert!(validate_key_id(&format!("../{}", "0".repeat(KEY_ID_LEN - 3))).is_err());
+        assert!(validate_key_id(&format!("{}/", "0".repeat(KEY_ID_LEN - 1))).is_err());
+    }
+
+    #[test]
+    fn traversing_key_id_never_reaches_the_filesystem() {
+        let (dir, store) = temp_store("traversal", DEFAULT_ROTATION_INTERVAL);
+        fs::write(dir.join("secret.meta.json"), "{}").unwrap();
+
+        let error = store.metadata("../secret").unwrap_err().to_string();
+        assert!(error.contains("Invalid key ID"), "{}", error);
+        assert!(store.load_server_key("../../etc/passwd").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn due_key_is_rotated_and_retired() {
+        let (dir, store) = temp_store("rotation", Duration::ZERO);
+        let first = store.rotate_if_due("passphrase").unwrap().expect("an empty store gets a key");
+        let second = store.rotate_if_due("passphrase").unwrap().expect("a zero interval is always due");
+
+        assert_ne!(first, second);
+        assert_eq!(store.active_key_id().unwrap(), Some(second.clone()));
+        assert!(store.metadata(&first).unwrap().retired_at.is_some());
+        assert!(store.metadata(&second).unwrap().retired_at.is_none());
+        // The retired key set stays usable for ciphertexts tagged with it
+        assert!(store.load_server_key(&first).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fresh_key_is_kept() {
+        let (dir, store) = temp_store("fresh", DEFAULT_ROTATION_INTERVAL);
+        let key_id = store.generate_keys("passphrase").unwrap();
+        assert_eq!(store.rotate_if_due("passphrase").unwrap(), None);
+        assert_eq!(store.active_key_id().unwrap(), Some(key_id));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn schedule_first_checks_after_one_interval() {
+        let (dir, store) = temp_store("schedule", DEFAULT_ROTATION_INTERVAL);
+        let store = Arc::new(store);
+        let schedule = tokio::spawn(run_rotation_schedule(store.clone(), "passphrase".into(), Duration::from_millis(200)));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(store.active_key_id().unwrap(), None, "the first check waits one interval");
+
+        // An empty store gets its first key at the first check; key generation takes seconds
+        for _ in 0..600 {
+            if store.active_key_id().unwrap().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(store.active_key_id().unwrap().is_some());
+        schedule.abort();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,24 @@
+//! Privacy-preserving credit evaluation: FHE and ZK building blocks and the services built on them
+
+pub mod zk_salary_circuit;
+pub mod zk_ciphertext_link;
+pub mod fhe_utils;
+pub mod nbfc_service;
+pub mod bank_service;
+pub mod decryption_service;
+pub mod privacy_budget;
+pub mod credit_scoring;
+pub mod key_store;
+pub mod fhe_params;
+pub mod threshold_decryption;
+pub mod portfolio;
+pub mod proof_registry;
+pub mod tls;
+pub mod auth;
+pub mod config;
+pub mod cli;
+pub mod chunked_upload;
+pub mod proof_jobs;
+pub mod error;
+pub mod signing;
+pub mod attestation;
@@ -1,121 +1,242 @@
-use std::error::Error;
-use tokio;
-use tonic::transport::Channel;
-
-mod zk_salary_circuit;
-mod fhe_utils;
-mod nbfc_service;
-mod bank_service;
-
-use nbfc_service::credit_evaluation::nbfc_service_client::NbfcServiceClient;
-use bank_service::credit_evaluation::bank_service_client::BankServiceClient;
-use nbfc_service::credit_evaluation::{EncryptedFinancialRequest};
-use bank_service::credit_evaluation::{CreditProofRequest};
-
-// Main function to demonstrate the workflow
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    println!("Starting Privacy-Preserving Credit Evaluation System");
-    
-    // Start servers in separate tasks
-    tokio::spawn(async {
-        if let Err(e) = nbfc_service::start_nbfc_server().await {
-            eprintln!("NBFC server error: {}", e);
-        }
-    });
-    
-    tokio::spawn(async {
-        if let Err(e) = bank_service::start_bank_server().await {
-            eprintln!("Bank server error: {}", e);
-        }
-    });
-    
-    // Allow servers to start
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    
-    // Run the demonstration workflow
-    run_demonstration_workflow().await?;
-    
-    Ok(())
-}
-
-async fn run_demonstration_workflow() -> Result<(), Box<dyn Error>> {
-    println!("\n=== DEMONSTRATION WORKFLOW ===\n");
-    
-    // Step 1: Simulate user data (in a real application, this would be collected securely)
-    let salary = 6000; // Changed from 6000.0 to 6000 (u64)
-    let expenses = vec![1200, 800, 350, 450, 200]; // Changed from f64 to u64
-    let threshold = 5000;
-    let max_expense_ratio = 50; // 50%
-    
-    println!("User data (for demonstration only - would be private in real system):");
-    println!("  Salary: ${}", salary);
-    println!("  Monthly Expenses: ${}", expenses.iter().sum::<u64>());
-    println!("  Threshold: ${}", threshold);
-    println!("  Max Expense Ratio: {}%", max_expense_ratio);
-    
-    // Step 2: Encrypt the financial data
-    println!("\nEncrypting financial data...");
-    let (encrypted_salary, client_key, server_key) = fhe_utils::encrypt_salary(salary)?;
-    // Fix: Clone expenses to avoid ownership issues
-    let encrypted_expenses = fhe_utils::encrypt_expenses(expenses.clone(), &client_key)?;
-    
-    // Serialize the encryption context (in a real implementation, this would be more secure)
-    let encryption_context = vec![1, 2, 3, 4]; // Placeholder
-    
-    // Step 3: Connect to NBFC service
-    println!("Connecting to NBFC service...");
-    let mut nbfc_client = NbfcServiceClient::connect("http://[::1]:50051").await?;
-    
-    // Step 4: Send encrypted data to NBFC
-    println!("Sending encrypted data to NBFC...");
-    let encrypted_salary_bytes = vec![1, 2, 3, 4]; // Placeholder for serialized encrypted salary
-    let encrypted_expenses_bytes: Vec<Vec<u8>> = (0..expenses.len())
-        .map(|_| vec![1, 2, 3, 4]) // Placeholder for serialized encrypted expenses
-        .collect();
-    
-    let nbfc_request = tonic::Request::new(EncryptedFinancialRequest {
-        encrypted_salary: encrypted_salary_bytes,
-        encrypted_expenses: encrypted_expenses_bytes,
-        encryption_context,
-        threshold,
-    });
-    
-    // Step 5: Get proof from NBFC
-    println!("Generating credit proof at NBFC...");
-    let nbfc_response = nbfc_client.generate_credit_proof(nbfc_request).await?;
-    let proof_response = nbfc_response.into_inner();
-    
-    println!("Proof generated successfully!");
-    
-    // Step 6: Connect to Bank service
-    println!("\nConnecting to Bank service...");
-    let mut bank_client = BankServiceClient::connect("http://[::1]:50052").await?;
-    
-    // Step 7: Send proof to Bank
-    println!("Sending proof to Bank for verification...");
-    let bank_request = tonic::Request::new(CreditProofRequest {
-        zkp_proof: proof_response.zkp_proof,
-        public_inputs: proof_response.public_inputs,
-        encrypted_avg_expense: proof_response.encrypted_avg_expense,
-        nonce: proof_response.nonce,
-        threshold,
-        max_expense_ratio,
-    });
-    
-    // Step 8: Get loan decision from Bank
-    println!("Getting loan decision...");
-    let bank_response = bank_client.verify_proof_and_decide(bank_request).await?;
-    let decision = bank_response.into_inner();
-    
-    // Step 9: Display results
-    println!("\n=== LOAN DECISION ===");
-    println!("Eligible: {}", decision.eligible);
-    println!("Reason: {}", decision.reason);
-    println!("Credit Score: {}", decision.credit_score);
-    
-    println!("\nNote: The financial data was never revealed to the Bank!");
-    println!("Only proof of eligibility and differentially private expense metrics were shared.");
-    
-    Ok(())
-}
\ No newline at end of file
+use std::error::Error;
+use tokio;
+
+use privacy_preserving_credit::{auth, bank_service, chunked_upload, cli, config, decryption_service, key_store};
+use privacy_preserving_credit::{nbfc_service, portfolio, proof_jobs, signing, tls};
+
+use nbfc_service::credit_evaluation::nbfc_service_client::NbfcServiceClient;
+use bank_service::credit_evaluation::bank_service_client::BankServiceClient;
+use nbfc_service::credit_evaluation::{PortfolioReportRequest, PrivacyBudgetRequest};
+use nbfc_service::credit_evaluation::{ListProofBundlesRequest, PublishProofBundleRequest, SharedProofBundle};
+use bank_service::credit_evaluation::{CreditProofRequest, ReasonCode};
+use clap::Parser;
+use cli::{ApplicantInput, Cli, Command};
+use tls::TlsPaths;
+use auth::{BearerToken, Claims, Role};
+use config::AppConfig;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    
+    // Load and validate the configuration before any service starts
+    let config = AppConfig::load()?;
+    
+    match cli.command.unwrap_or(Command::Demo) {
+        Command::Demo => run_demonstration(config).await,
+        command => cli::run(command, cli.json, config).await,
+    }
+}
+
+// Runs every service in this process and walks through the workflow
+async fn run_demonstration(config: AppConfig) -> Result<(), Box<dyn Error>> {
+    println!("Starting Privacy-Preserving Credit Evaluation System");
+    
+    // The demo runs every party in one process, so they share the key store passphrase
+    if std::env::var(key_store::KEY_PASSPHRASE_ENV).is_err() {
+        std::env::set_var(key_store::KEY_PASSPHRASE_ENV, "demo-passphrase"); // Demonstration default only
+    }
+    
+    // The key holder makes sure an active key set exists before any party starts
+    key_store::KeyStore::open(key_store::DEFAULT_KEY_STORE_DIR, key_store::DEFAULT_ROTATION_INTERVAL)?
+        .with_parameter_set(config.fhe.parameter_set)?
+        .rotate_if_due(&std::env::var(key_store::KEY_PASSPHRASE_ENV)?)?;
+    
+    // Every party authenticates with a certificate from the local development CA
+    tls::ensure_dev_ca(tls::tls_dir(), &tls::DEV_IDENTITIES)?;
+    // The NBFC signs each application's outputs; the Bank and the key holder check the signature
+    signing::ensure_signing_keys(tls::tls_dir(), &[tls::NBFC_IDENTITY, auth::TOKEN_ISSUER_IDENTITY])?;
+    
+    // The demo also plays the token issuer, handing each party a fresh token through the environment
+    let applicant = ApplicantInput::demo();
+    let ttl = auth::DEFAULT_TOKEN_TTL;
+    for claims in [
+        Claims::for_user(tls::APPLICANT_IDENTITY, &applicant.user_id, ttl),
+        Claims::new(tls::BANK_IDENTITY, Role::Bank, Vec::new(), ttl),
+        Claims::new(tls::AUDITOR_IDENTITY, Role::Auditor, Vec::new(), ttl),
+    ] {
+        std::env::set_var(auth::token_env(&claims.institution), auth::issue_token_from_env(&claims)?);
+    }
+    
+    // Start servers in separate tasks
+    let nbfc_config = config.clone();
+    tokio::spawn(async move {
+        if let Err(e) = nbfc_service::start_nbfc_server(nbfc_config).await {
+            eprintln!("NBFC server error: {}", e);
+        }
+    });
+    
+    let bank_config = config.clone();
+    tokio::spawn(async move {
+        if let Err(e) = bank_service::start_bank_server(bank_config).await {
+            eprintln!("Bank server error: {}", e);
+        }
+    });
+    
+    let decryption_config = config.clone();
+    tokio::spawn(async move {
+        if let Err(e) = decryption_service::start_decryption_server(decryption_config).await {
+            eprintln!("Decryption server error: {}", e);
+        }
+    });
+    
+    // Allow servers to start
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    
+    // Run the demonstration workflow
+    run_demonstration_workflow(&config).await?;
+    
+    Ok(())
+}
+
+async fn run_demonstration_workflow(config: &AppConfig) -> Result<(), Box<dyn Error>> {
+    println!("\n=== DEMONSTRATION WORKFLOW ===\n");
+    
+    // Step 1: Simulate user data (in a real application, this would be collected securely)
+    let applicant = ApplicantInput::demo();
+    let threshold = config.policy.threshold;
+    let max_expense_ratio = config.policy.max_expense_ratio;
+    
+    println!("User data (for demonstration only - would be private in real system):");
+    println!("  Salary: ${}", applicant.salary);
+    println!("  Monthly Expenses: ${}", applicant.expenses.iter().map(|e| e.amount).sum::<u64>());
+    println!("  Tenure: {} years", applicant.tenure_years);
+    println!("  Threshold: ${}", threshold);
+    println!("  Max Expense Ratio: {}%", max_expense_ratio);
+    
+    // Step 2: Encrypt the financial data
+    // The key holder publishes a compact public key; the applicant encrypts with it alone
+    println!("\nEncrypting financial data with the key holder's public key...");
+    let passphrase = std::env::var(key_store::KEY_PASSPHRASE_ENV)?;
+    let key_store = key_store::KeyStore::open(key_store::DEFAULT_KEY_STORE_DIR, key_store::DEFAULT_ROTATION_INTERVAL)?
+        .with_parameter_set(config.fhe.parameter_set)?;
+    println!("Proving the encrypted salary matches the salary commitment...");
+    let mut application = cli::encrypt_application(&applicant, config, &key_store)?;
+    let key_id = application.key_id.clone();
+    println!("Using FHE key {}", key_id);
+    
+    // The demo runs with a single key holder; keygen --split splits the key between the
+    // Bank and the NBFC instead
+    let key_holder_key = key_store.load_client_key(&key_id, &passphrase)?;
+    let user_id_hash = applicant.user_id_hash();
+    
+    // Step 3: Connect to NBFC service
+    println!("Connecting to NBFC service...");
+    let applicant_tls = TlsPaths::from_env(tls::APPLICANT_IDENTITY);
+    let applicant_token = BearerToken(auth::token_from_env(tls::APPLICANT_IDENTITY)?);
+    let mut nbfc_client = NbfcServiceClient::with_interceptor(
+        tls::connect(&config.services.nbfc_url(), &applicant_tls).await?,
+        applicant_token.clone(),
+    );
+    
+    // Step 4: Send encrypted data to NBFC, uploading the bulky expense ciphertexts in chunks
+    println!("Uploading encrypted expenses to NBFC in chunks...");
+    let upload_id = chunked_upload::upload_request_payloads(&mut nbfc_client, &mut application.request, None).await?;
+    println!("Upload {} complete and verified", upload_id);
+    
+    // Step 5: Submit the application as a proof job and follow it until the proof is ready
+    println!("Submitting credit proof job to NBFC...");
+    let proof_response = proof_jobs::submit_and_follow(&mut nbfc_client, application.request, |status| {
+        println!("  [{}/{}] {}", status.completed_steps, status.total_steps, status.stage);
+    })
+    .await?;
+    
+    println!("Proof generated successfully!");
+    
+    // The key holder releases the score and the offer to the applicant alone
+    let applicant_result = cli::decrypt_applicant_result(&proof_response, config).await?;
+    println!("Your credit score: {}", applicant_result.credit_score);
+    if let (Some(max_emi), Some(max_principal)) = (applicant_result.max_emi, applicant_result.max_principal) {
+        println!("Your loan offer: maximum EMI {}, maximum principal {}", max_emi, max_principal);
+    }
+    
+    let budget = nbfc_client
+        .get_remaining_budget(tonic::Request::new(PrivacyBudgetRequest { user_id_hash: user_id_hash.clone() }))
+        .await?
+        .into_inner();
+    println!("Remaining privacy budget: epsilon {} of {}", budget.remaining_epsilon, budget.total_epsilon);
+    
+    // An auditor requests the NBFC's portfolio aggregates; only the key holder can release the statistics
+    println!("Requesting encrypted portfolio report...");
+    let auditor_tls = TlsPaths::from_env(tls::AUDITOR_IDENTITY);
+    let mut auditor_client = NbfcServiceClient::with_interceptor(
+        tls::connect(&config.services.nbfc_url(), &auditor_tls).await?,
+        BearerToken(auth::token_from_env(tls::AUDITOR_IDENTITY)?),
+    );
+    let portfolio_response = auditor_client
+        .generate_portfolio_report(tonic::Request::new(PortfolioReportRequest {
+            key_id: key_id.clone(),
+            income_bucket_bounds: Vec::new(),
+            epsilon: 0.0,
+        }))
+        .await?
+        .into_inner();
+    let portfolio_report = nbfc_service::decode_portfolio_report(&portfolio_response)?;
+    let portfolio_statistics = portfolio::release_portfolio_report(&portfolio_report, &key_holder_key)?;
+    println!(
+        "Portfolio (epsilon {}): income histogram {:?}, average FOIR {:.1}%",
+        portfolio_response.epsilon,
+        portfolio_statistics.income_histogram,
+        portfolio_statistics.average_foir_percent
+    );
+    
+    // Step 6: Publish the proof as a reusable bundle the Bank may fetch
+    println!("\nPublishing reusable proof bundle...");
+    let published = nbfc_client
+        .publish_proof_bundle(tonic::Request::new(PublishProofBundleRequest {
+            bundle: Some(SharedProofBundle {
+                zkp_proof: proof_response.zkp_proof,
+                public_inputs: proof_response.public_inputs,
+                encrypted_avg_expense_blocks: proof_response.encrypted_avg_expense_blocks,
+                proof_id: Vec::new(),
+                expiration_timestamp: 0,
+                user_id_hash: user_id_hash.clone(),
+                key_id: proof_response.key_id,
+                threshold,
+                salary_commitment: proof_response.salary_commitment,
+                encrypted_ratio_bucket: proof_response.encrypted_ratio_bucket,
+                encrypted_affordability_check: proof_response.encrypted_affordability_check,
+                nonce: proof_response.nonce,
+                decision_attestation: proof_response.decision_attestation,
+            }),
+            consented_requesters: vec![tls::BANK_IDENTITY.to_string()],
+        }))
+        .await?
+        .into_inner();
+    let bundles = nbfc_client
+        .list_proof_bundles(tonic::Request::new(ListProofBundlesRequest { user_id_hash }))
+        .await?
+        .into_inner()
+        .bundles;
+    println!("You have {} published proof bundle(s)", bundles.len());
+    
+    // Step 7: Connect to Bank service
+    println!("\nConnecting to Bank service...");
+    let mut bank_client = BankServiceClient::with_interceptor(
+        tls::connect(&config.services.bank_url(), &applicant_tls).await?,
+        applicant_token,
+    );
+    
+    // Step 8: Send the proof ID to the Bank, which fetches the bundle with the applicant's consent
+    println!("Sending proof to Bank for verification...");
+    let bank_request = tonic::Request::new(CreditProofRequest {
+        proof_id: published.proof_id,
+    });
+    
+    // Step 9: Get loan decision from Bank
+    println!("Getting loan decision...");
+    let bank_response = bank_client.verify_proof_and_decide(bank_request).await?;
+    let decision = bank_response.into_inner();
+    
+    // Step 10: Display results
+    println!("\n=== LOAN DECISION ===");
+    println!("Eligible: {}", decision.eligible);
+    let reason_code = ReasonCode::from_i32(decision.reason_code).unwrap_or(ReasonCode::Unspecified);
+    println!("Reason: {} ({:?})", decision.reason, reason_code);
+    println!("Credit Score: {}", decision.credit_score);
+    
+    println!("\nNote: The financial data was never revealed to the Bank!");
+    println!("Only proof of eligibility and differentially private expense metrics were shared.");
+    
+    Ok(())
+}
@@ -1,148 +1,1144 @@
-use tonic::{transport::Server, Request, Response, Status};
-use bincode;
-use std::sync::Arc;
-
-// Generate the server code from our proto definition
-pub mod credit_evaluation {
-    tonic::include_proto!("credit_evaluation");
-}
-
-use credit_evaluation::nbfc_service_server::{NbfcService, NbfcServiceServer};
-use credit_evaluation::{EncryptedFinancialRequest, CreditProofResponse};
-
-// Import our custom modules
-use crate::fhe_utils;
-use crate::zk_salary_circuit::generate_salary_threshold_proof;
-
-// Implementation of our NBFC service
-#[derive(Debug, Default)]
-pub struct NBFCServiceImpl {}
-
-#[tonic::async_trait]
-impl NbfcService for NBFCServiceImpl {
-    async fn generate_credit_proof(
-        &self,
-        request: Request<EncryptedFinancialRequest>,
-    ) -> Result<Response<CreditProofResponse>, Status> {
-        let req = request.into_inner();
-        
-        // Step 1: Deserialize encryption context
-        let encryption_context = deserialize_encryption_context(&req.encryption_context)
-            .map_err(|e| Status::internal(format!("Failed to deserialize encryption context: {}", e)))?;
-        
-        // Step 2: Extract encrypted data
-        let encrypted_salary = deserialize_lwe(&req.encrypted_salary)
-            .map_err(|e| Status::internal(format!("Failed to deserialize encrypted salary: {}", e)))?;
-        
-        let encrypted_expenses: Vec<_> = req.encrypted_expenses
-            .iter()
-            .map(|e| deserialize_lwe(e))
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| Status::internal(format!("Failed to deserialize encrypted expenses: {}", e)))?;
-        
-        // Step 3: Decrypt salary internally (only within NBFC, never shared)
-        // In a real implementation, this would be done using secure enclaves or MPC
-        let salary = decrypt_salary(&encrypted_salary, &encryption_context)
-            .map_err(|e| Status::internal(format!("Failed to process salary: {}", e)))?;
-        
-        // Step 4: Compute average expense (remains encrypted)
-        let encrypted_avg_expense = compute_encrypted_average_expense(
-            &encrypted_expenses, 
-            &encryption_context.encoder, 
-            &encryption_context.sk
-        ).map_err(|e| Status::internal(format!("Failed to compute average expense: {}", e)))?;
-        
-        // Step 5: Generate ZK proof that salary > threshold
-        let threshold = req.threshold;
-        let proof_successful = generate_salary_threshold_proof(salary as u64, threshold)
-            .map_err(|e| Status::internal(format!("Failed to generate proof: {}", e)))?;
-        
-        if !proof_successful {
-            return Err(Status::invalid_argument("Salary does not meet threshold requirements"));
-        }
-        
-        // Step 6: Prepare the response
-        // In a real implementation, this would include properly serialized ZK proof
-        let zkp_proof = vec![1, 2, 3, 4]; // Placeholder for actual proof
-        let nonce = generate_nonce();
-        let public_inputs = serialize_public_inputs(threshold);
-        
-        let response = CreditProofResponse {
-            zkp_proof,
-            encrypted_avg_expense: serialize_lwe(&encrypted_avg_expense),
-            nonce,
-            public_inputs,
-        };
-        
-        Ok(Response::new(response))
-    }
-}
-
-// Helper Functions
-
-fn deserialize_encryption_context(data: &[u8]) -> Result<EncryptionContext, Box<dyn std::error::Error>> {
-    Ok(EncryptionContext::default())
-}
-
-fn deserialize_lwe(data: &[u8]) -> Result<LWE, Box<dyn std::error::Error>> {
-    Ok(LWE::default())
-}
-
-fn decrypt_salary(encrypted_salary: &LWE, context: &EncryptionContext) -> Result<f64, Box<dyn std::error::Error>> {
-    Ok(6000.0)
-}
-
-fn compute_encrypted_average_expense(
-    encrypted_expenses: &[LWE],
-    encoder: &Encoder,
-    sk: &LWESecretKey,
-) -> Result<LWE, Box<dyn std::error::Error>> {
-    Ok(LWE::default())
-}
-
-fn serialize_lwe(lwe: &LWE) -> Vec<u8> {
-    vec![1, 2, 3, 4]
-}
-
-fn generate_nonce() -> Vec<u8> {
-    // Generate a random nonce for proof verification
-    vec![5, 6, 7, 8]
-}
-
-fn serialize_public_inputs(threshold: u64) -> Vec<u8> {
-    // Serialize the public inputs for the ZK proof
-    let mut buf = Vec::new();
-    buf.extend_from_slice(&threshold.to_le_bytes());
-    buf
-}
-
-// Placeholder structs for demonstration
-#[derive(Debug, Default)]
-struct EncryptionContext {
-    encoder: Encoder,
-    sk: LWESecretKey,
-}
-
-#[derive(Debug, Default, Clone)]
-struct LWE;
-
-#[derive(Debug, Default)]
-struct Encoder;
-
-#[derive(Debug, Default)]
-struct LWESecretKey;
-
-// Start the NBFC server
-pub async fn start_nbfc_server() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::1]:50051".parse()?;
-    let service = NBFCServiceImpl::default();
-    
-    println!("NBFC Server listening on {}", addr);
-    
-    Server::builder()
-        .add_service(NbfcServiceServer::new(service))
-        .serve(addr)
-        .await?;
-    
-    Ok(())
-}
\ No newline at end of file
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use bincode;
+use std::sync::{Arc, Mutex};
+use rayon::ThreadPool;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tfhe::shortint::{Ciphertext, ServerKey};
+use ed25519_dalek::SigningKey;
+
+// Generate the server code from our proto definition
+pub mod credit_evaluation {
+    tonic::include_proto!("credit_evaluation");
+}
+
+use credit_evaluation::nbfc_service_server::{NbfcService, NbfcServiceServer};
+use credit_evaluation::{EncryptedFinancialRequest, CreditProofResponse};
+use credit_evaluation::{PrivacyBudgetRequest, PrivacyBudgetResponse};
+use credit_evaluation::{CategorizedExpense, ExpenseCategory};
+use credit_evaluation::{EncryptedLoanOffer, LoanTerms};
+use credit_evaluation::{PartialDecryptionRequest, PartialDecryptionResponse};
+use credit_evaluation::{EncryptedAggregate, PortfolioReportRequest, PortfolioReportResponse};
+use credit_evaluation::{FetchProofBundleRequest, PublishProofBundleRequest, PublishProofBundleResponse, SharedProofBundle};
+use credit_evaluation::{ListProofBundlesRequest, ListProofBundlesResponse, ProofBundleSummary};
+use credit_evaluation::{RevokeProofBundleRequest, RevokeProofBundleResponse};
+use credit_evaluation::{BeginUploadRequest, UploadChunk, UploadStatusRequest, UploadStatusResponse};
+use credit_evaluation::{JobStatus, JobStatusRequest};
+
+// Import our custom modules
+use crate::attestation::{self, DecisionAttestation, DecisionStatement};
+use crate::credit_scoring::{self, ScoringModel};
+use crate::fhe_params::{self, ParameterPolicy};
+use crate::fhe_utils::{self, CategoryWeights, EncryptedCategorizedExpense};
+use crate::key_store::{self, KeyStore};
+use crate::portfolio::{self, EncryptedPortfolioReport, PortfolioEntry, PortfolioReportConfig, PortfolioStore};
+use crate::proof_registry::{self, ProofRegistry, RegistryError};
+use crate::chunked_upload::{self, UploadError, UploadStore};
+use crate::proof_jobs::{self, JobContext, JobError, ProofJobs};
+use crate::error::CreditError;
+use crate::tls::{self, TlsPaths};
+use crate::auth::{self, AuthAuditLog, AuthInterceptor, Authorizer};
+use crate::privacy_budget::{BudgetExhausted, PrivacyBudgetLedger};
+use crate::config::{AppConfig, PrivacyConfig};
+use crate::decryption_service::credit_evaluation::CiphertextKind;
+use crate::signing::SigningKeyPaths;
+use crate::threshold_decryption::{self, KeyShare, ReleasedBlocks};
+use crate::bank_service;
+use crate::zk_ciphertext_link;
+use crate::zk_salary_circuit;
+
+// Implementation of our NBFC service
+#[derive(Debug, Default, Clone)]
+pub struct NBFCServiceImpl {
+    budget_ledger: Arc<PrivacyBudgetLedger>,
+    category_weights: CategoryWeights,
+    scoring_model: ScoringModel,
+    parameter_policy: ParameterPolicy,
+    key_share: Option<Arc<KeyShare>>,
+    /// Output blocks the NBFC has released to the Bank for joint decryption
+    released_blocks: Arc<Mutex<ReleasedBlocks>>,
+    key_store: Option<Arc<KeyStore>>,
+    /// Key the NBFC signs each application's outputs with
+    attestation_key: Option<Arc<SigningKey>>,
+    portfolio: Arc<PortfolioStore>,
+    proof_registry: Arc<ProofRegistry>,
+    uploads: Arc<UploadStore>,
+    jobs: Option<Arc<ProofJobs>>,
+    fhe_pool: Option<Arc<ThreadPool>>,
+    authorizer: Arc<Authorizer>,
+    privacy: PrivacyConfig,
+}
+
+impl NBFCServiceImpl {
+    fn jobs(&self) -> Result<&ProofJobs, Status> {
+        self.jobs
+            .as_deref()
+            .ok_or_else(|| CreditError::FailedPrecondition("NBFC has no proof job queue".into()).into())
+    }
+    
+    /// Runs homomorphic work on the FHE thread pool, so its parallel steps share bounded workers
+    ///
+    /// Every call into the radix helpers goes through here; their parallel iterators would
+    /// otherwise run on rayon's global pool.
+    fn on_fhe_pool<T: Send>(
+        &self,
+        work: impl FnOnce() -> Result<T, Box<dyn std::error::Error>> + Send,
+    ) -> Result<T, String> {
+        match &self.fhe_pool {
+            Some(pool) => pool.install(|| work().map_err(|e| e.to_string())),
+            None => work().map_err(|e| e.to_string()),
+        }
+    }
+    
+    /// Signs a statement binding the response's output ciphertexts to its nonce, key, threshold
+    /// and expense ratio limit
+    ///
+    /// The Bank and the key holder refuse to act on any ciphertext the statement does not cover.
+    fn attest(&self, response: &CreditProofResponse, threshold: u64, max_expense_ratio: u64) -> Result<Vec<u8>, CreditError> {
+        let key = self.attestation_key
+            .as_ref()
+            .ok_or_else(|| CreditError::Internal("NBFC has no attestation key".into()))?;
+        let mut statement = DecisionStatement {
+            nonce: response.nonce.clone(),
+            key_id: response.key_id.clone(),
+            threshold,
+            max_expense_ratio,
+            salary_commitment: response.salary_commitment.clone(),
+            issued_at: attestation::now_unix(),
+            ciphertext_hashes: Default::default(),
+        };
+        statement.attest_ciphertext(CiphertextKind::RatioBucket, std::slice::from_ref(&response.encrypted_ratio_bucket));
+        statement.attest_ciphertext(
+            CiphertextKind::AffordabilityCheck,
+            std::slice::from_ref(&response.encrypted_affordability_check),
+        );
+        statement.attest_ciphertext(CiphertextKind::AvgExpense, &response.encrypted_avg_expense_blocks);
+        statement.attest_ciphertext(CiphertextKind::CreditScore, &response.encrypted_credit_score_blocks);
+        statement.attest_ciphertext(CiphertextKind::MaxExpense, &response.encrypted_max_expense_blocks);
+        statement.attest_ciphertext(CiphertextKind::MinExpense, &response.encrypted_min_expense_blocks);
+        statement.attest_ciphertext(CiphertextKind::ExpenseVariance, &response.encrypted_expense_variance_blocks);
+        // The trend indicators are attested together, as the hash of each indicator's hash
+        let trend_hashes: Vec<Vec<u8>> = response.encrypted_expense_trend
+            .iter()
+            .map(|indicator| attestation::hash_ciphertext(&indicator.encrypted_blocks).to_vec())
+            .collect();
+        statement.attest_ciphertext(CiphertextKind::ExpenseTrend, &trend_hashes);
+        if let Some(offer) = &response.loan_offer {
+            statement.attest_ciphertext(CiphertextKind::MaxEmi, &offer.encrypted_max_emi_blocks);
+            statement.attest_ciphertext(CiphertextKind::MaxPrincipal, &offer.encrypted_max_principal_blocks);
+        }
+        DecisionAttestation::sign(statement, key)?.encode()
+    }
+    
+    /// Sums radix values by parallel tree reduction on the FHE pool, or serially without one
+    fn sum_expenses(&self, expenses: &[Vec<Ciphertext>], server_key: &ServerKey) -> Result<Vec<Ciphertext>, String> {
+        self.on_fhe_pool(|| match &self.fhe_pool {
+            Some(pool) => fhe_utils::tree_reduce_sum_parallel(expenses, server_key, pool),
+            None => fhe_utils::sum_encrypted_expenses(expenses, server_key),
+        })
+    }
+    
+    /// Evaluates an application homomorphically and proves its salary threshold, reporting each step
+    fn evaluate_application(
+        &self,
+        owner: &str,
+        req: EncryptedFinancialRequest,
+        job: &JobContext,
+    ) -> Result<CreditProofResponse, Status> {
+        // Every ciphertext must be tagged with the key it was encrypted under
+        if req.key_id.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing FHE key ID".into()).into());
+        }
+        
+        // Step 1: Deserialize encryption context, rejecting parameters this service does not evaluate under
+        job.step(1, "Checking encryption context")?;
+        let parameter_context = fhe_params::decode_parameter_context(&req.encryption_context)
+            .map_err(|e| CreditError::deserialization("encryption context", e))?;
+        self.parameter_policy
+            .check(&parameter_context)
+            .map_err(|message| CreditError::KeyMismatch { key_id: req.key_id.clone(), message })?;
+        
+        // Ciphertexts are evaluated under the server key of the key set they are tagged with
+        let key_store = self.key_store
+            .as_ref()
+            .ok_or_else(|| CreditError::FailedPrecondition("NBFC has no key store".into()))?;
+        let key_metadata = key_store
+            .metadata(&req.key_id)
+            .map_err(|e| CreditError::NotFound(e.to_string()))?;
+        if key_metadata.parameter_set != parameter_context.parameter_set {
+            return Err(CreditError::KeyMismatch {
+                message: format!(
+                    "Key {} uses parameter set {}, not {}",
+                    req.key_id, key_metadata.parameter_set, parameter_context.parameter_set
+                ),
+                key_id: req.key_id,
+            }
+            .into());
+        }
+        
+        // Large payloads may have been uploaded in chunks ahead of this request
+        let upload = if req.upload_id.is_empty() {
+            None
+        } else {
+            Some(
+                self.uploads
+                    .take(owner, &req.upload_id, &req.key_id)
+                    .map_err(upload_status)?,
+            )
+        };
+        // An uploaded server key must be the stored one, or the applicant would choose the
+        // key the NBFC evaluates and attests under
+        let server_key: ServerKey = match upload.as_ref().and_then(|upload| upload.server_key.as_ref()) {
+            Some(bytes) => {
+                check_uploaded_server_key(key_store, &req.key_id, bytes)?;
+                bincode::deserialize(bytes)
+                    .map_err(|e| CreditError::deserialization("uploaded server key", e))?
+            }
+            None => key_store
+                .load_server_key(&req.key_id)
+                .map_err(|e| CreditError::internal("load server key", e))?,
+        };
+        
+        // Step 2: Extract encrypted data
+        job.step(2, "Deserializing ciphertexts")?;
+        let uploaded_expenses = match &upload {
+            Some(upload) => upload.expenses
+                .iter()
+                .map(|bytes| CategorizedExpense::decode(bytes.as_slice()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| CreditError::deserialization("uploaded expenses", e))?,
+            None => Vec::new(),
+        };
+        let categorized_expenses: Vec<_> = req.categorized_expenses
+            .iter()
+            .chain(&uploaded_expenses)
+            .map(deserialize_categorized_expense)
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        // Every aggregate works from the categorized expenses, so the total always
+        // equals the sum of the categories
+        let encrypted_expenses: Vec<Vec<Ciphertext>> = categorized_expenses
+            .iter()
+            .map(|e| e.blocks.clone())
+            .collect();
+        
+        let encrypted_tenure = deserialize_ciphertexts(&req.encrypted_tenure_blocks)
+            .map_err(|e| CreditError::deserialization("encrypted tenure", e))?;
+        if encrypted_tenure.is_empty() {
+            return Err(CreditError::deserialization("encrypted tenure", "tenure has no blocks").into());
+        }
+        
+        let salary_blocks = deserialize_ciphertexts(&req.encrypted_salary_blocks)
+            .map_err(|e| CreditError::deserialization("salary blocks", e))?;
+        
+        // Step 3: Check the salary blocks open to the committed salary, and that the
+        // applicant's proof shows the committed salary exceeds the threshold
+        job.step(3, "Verifying salary proofs")?;
+        let salary_commitment = zk_salary_circuit::commitment_from_bytes(&req.salary_commitment)
+            .map_err(|e| CreditError::deserialization("salary commitment", e))?;
+        // The salary blocks must be encryptions under the key holder's published public key
+        let public_key = key_store
+            .load_public_key_bytes(&req.key_id)
+            .and_then(|bytes| fhe_utils::deserialize_public_key(&bytes))
+            .map_err(|e| CreditError::internal("load public key", e))?;
+        
+        let linked = zk_ciphertext_link::verify_ciphertext_link(
+            &salary_blocks,
+            &req.ciphertext_link_proof,
+            &salary_commitment,
+            &public_key,
+            parameter_context.parameter_set,
+        ).map_err(|e| CreditError::ProofInvalid(format!("Failed to verify ciphertext link: {}", e)))?;
+        if !linked {
+            return Err(CreditError::ProofInvalid("Encrypted salary does not match the salary commitment".into()).into());
+        }
+        
+        // An applicant whose salary does not exceed the threshold cannot produce a proof
+        let threshold = req.threshold;
+        if req.salary_threshold_proof.is_empty() {
+            return Err(CreditError::ThresholdNotMet { threshold }.into());
+        }
+        let nonce = zk_salary_circuit::nonce_from_bytes(&req.nonce)
+            .map_err(CreditError::InvalidArgument)?;
+        let proved = zk_salary_circuit::verify_committed_salary_threshold_proof(
+            &req.salary_threshold_proof,
+            threshold,
+            &salary_commitment,
+            &nonce,
+        ).map_err(|e| CreditError::ProofInvalid(format!("Failed to verify salary threshold proof: {}", e)))?;
+        if !proved {
+            return Err(CreditError::ProofInvalid("Salary threshold proof does not match the commitment, threshold and nonce".into()).into());
+        }
+        
+        // Step 4: Check the privacy budget covers another set of noisy expense statistics; it is charged only
+        // once the response is ready, so a failed or cancelled evaluation costs nothing
+        job.step(4, "Checking privacy budget")?;
+        if req.user_id_hash.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing user identity hash".into()).into());
+        }
+        let epsilon = self.privacy.application_epsilon();
+        let remaining = self.budget_ledger.remaining(&req.user_id_hash);
+        if epsilon > remaining {
+            return Err(CreditError::BudgetExhausted(
+                BudgetExhausted { requested: epsilon, remaining }.to_string(),
+            ).into());
+        }
+        
+        // Step 5: Compute the expense total by parallel tree reduction, then the noisy average,
+        // maximum, minimum, variance and trend of the clipped expenses (all remain encrypted)
+        job.step(5, "Computing expense statistics")?;
+        let encrypted_total_expenses = self.sum_expenses(&encrypted_expenses, &server_key)
+            .map_err(|e| CreditError::internal("sum expenses", e))?;
+        let clipped_expenses = self.on_fhe_pool(|| {
+            encrypted_expenses
+                .iter()
+                .map(|expense| fhe_utils::compute_encrypted_clipped_radix(expense, self.privacy.expense_clip, &server_key))
+                .collect::<Result<Vec<_>, _>>()
+        }).map_err(|e| CreditError::internal("clip expenses", e))?;
+        let encrypted_clipped_total = self.sum_expenses(&clipped_expenses, &server_key)
+            .map_err(|e| CreditError::internal("sum clipped expenses", e))?;
+        let average_noise = self.privacy.average_noise(encrypted_expenses.len());
+        let encrypted_avg_expense = self.on_fhe_pool(|| fhe_utils::compute_encrypted_average_of_total(
+            &encrypted_clipped_total,
+            encrypted_expenses.len(),
+            average_noise,
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute average expense", e))?;
+        let encrypted_max_expense = self.on_fhe_pool(|| fhe_utils::compute_encrypted_max_expense(
+            &clipped_expenses,
+            self.privacy.extremum_noise(),
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute maximum expense", e))?;
+        let encrypted_min_expense = self.on_fhe_pool(|| fhe_utils::compute_encrypted_min_expense(
+            &clipped_expenses,
+            self.privacy.extremum_noise(),
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute minimum expense", e))?;
+        let encrypted_expense_variance = self.on_fhe_pool(|| fhe_utils::compute_encrypted_expense_variance(
+            &clipped_expenses,
+            self.privacy.expense_clip,
+            self.privacy.variance_noise(clipped_expenses.len()),
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute expense variance", e))?;
+        // A single expense has no trend
+        let encrypted_expense_trend = if clipped_expenses.len() < 2 {
+            Vec::new()
+        } else {
+            self.on_fhe_pool(|| fhe_utils::compute_encrypted_expense_trend(
+                &clipped_expenses,
+                self.privacy.trend_noise(),
+                &server_key,
+            )).map_err(|e| CreditError::internal("compute expense trend", e))?
+        };
+        
+        // Step 6: Compute the expense-to-income ratio bucket (remains encrypted)
+        job.step(6, "Computing expense ratio")?;
+        let encrypted_ratio_bucket = self.on_fhe_pool(|| fhe_utils::compute_encrypted_expense_ratio_bucket(
+            &salary_blocks,
+            &encrypted_total_expenses,
+            req.max_expense_ratio,
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute expense ratio", e))?;
+        
+        // Step 7: Compute the category-weighted expense total (remains encrypted)
+        job.step(7, "Computing weighted expenses")?;
+        let encrypted_weighted_expenses = self.on_fhe_pool(|| fhe_utils::compute_encrypted_weighted_expense_sum(
+            &categorized_expenses,
+            &self.category_weights,
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute weighted expenses", e))?;
+        
+        // Step 8: Evaluate the credit-scoring model (score remains encrypted for the applicant)
+        job.step(8, "Evaluating scoring model")?;
+        let encrypted_credit_score = self.on_fhe_pool(|| compute_encrypted_credit_score(
+            &salary_blocks,
+            &encrypted_avg_expense,
+            &categorized_expenses,
+            &encrypted_tenure,
+            &self.scoring_model,
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute credit score", e))?;
+        
+        // Step 9: Compute the applicant's loan offer and the eligibility bit the Bank will see
+        job.step(9, "Computing loan offer")?;
+        let loan_terms = req.loan_terms
+            .ok_or_else(|| CreditError::InvalidArgument("Missing loan terms".into()))?;
+        let (loan_offer, encrypted_affordability_check) = self.on_fhe_pool(|| compute_encrypted_loan_offer(
+            &salary_blocks,
+            &encrypted_total_expenses,
+            &loan_terms,
+            &server_key,
+        )).map_err(|e| CreditError::internal("compute loan offer", e))?;
+        
+        // Step 10: Keep the application's ciphertexts for portfolio reporting
+        job.step(10, "Recording portfolio entry")?;
+        let obligations = categorized_expenses
+            .iter()
+            .filter(|e| e.category == fhe_utils::ExpenseCategory::Emi)
+            .map(|e| e.blocks.clone())
+            .collect();
+        self.portfolio.record(PortfolioEntry {
+            key_id: req.key_id.clone(),
+            salary_blocks,
+            obligations,
+        });
+        
+        // Step 11: Prepare the response, forwarding the applicant's threshold proof to the Bank
+        job.step(11, "Preparing response")?;
+        let public_inputs = serialize_public_inputs(threshold, &salary_commitment, &nonce);
+        
+        let mut response = CreditProofResponse {
+            zkp_proof: req.salary_threshold_proof,
+            nonce: nonce.to_vec(),
+            public_inputs,
+            encrypted_ratio_bucket: serialize_ciphertext(&encrypted_ratio_bucket)?,
+            encrypted_weighted_expense_blocks: serialize_radix(&encrypted_weighted_expenses)?,
+            encrypted_avg_expense_blocks: serialize_radix(&encrypted_avg_expense)?,
+            encrypted_credit_score_blocks: serialize_radix(&encrypted_credit_score)?,
+            encrypted_max_expense_blocks: serialize_radix(&encrypted_max_expense)?,
+            encrypted_min_expense_blocks: serialize_radix(&encrypted_min_expense)?,
+            encrypted_expense_variance_blocks: serialize_radix(&encrypted_expense_variance)?,
+            encrypted_expense_trend: encrypted_expense_trend
+                .iter()
+                .map(|indicator| Ok(EncryptedAggregate { encrypted_blocks: serialize_radix(indicator)? }))
+                .collect::<Result<Vec<_>, Status>>()?,
+            key_id: req.key_id,
+            loan_offer: Some(loan_offer),
+            encrypted_affordability_check: serialize_ciphertext(&encrypted_affordability_check)?,
+            salary_commitment: req.salary_commitment,
+            decision_attestation: Vec::new(),
+        };
+        response.decision_attestation = self.attest(&response, threshold, req.max_expense_ratio)?;
+        
+        // The noisy statistics are released with this response, so their epsilon is spent now
+        self.budget_ledger
+            .charge(&req.user_id_hash, epsilon)
+            .map_err(|e| CreditError::BudgetExhausted(e.to_string()))?;
+        
+        // With a split key, only the Bank's decision inputs may later be decrypted jointly
+        if self.key_share.is_some() {
+            self.released_blocks.lock().unwrap().release(
+                [&response.encrypted_ratio_bucket, &response.encrypted_affordability_check]
+                    .into_iter()
+                    .chain(&response.encrypted_avg_expense_blocks),
+                attestation::now_unix(),
+            );
+        }
+        
+        Ok(response)
+    }
+}
+
+#[tonic::async_trait]
+impl NbfcService for NBFCServiceImpl {
+    type StreamJobProgressStream = ReceiverStream<Result<JobStatus, Status>>;
+    
+    async fn generate_credit_proof(
+        &self,
+        request: Request<EncryptedFinancialRequest>,
+    ) -> Result<Response<CreditProofResponse>, Status> {
+        let principal = self.authorizer.authorize(&request, "NBFCService/GenerateCreditProof")?;
+        let owner = principal.owner();
+        let mut req = request.into_inner();
+        // The budget is charged to the user the token names, not one the request picks
+        req.user_id_hash = principal.user_id_hash(&req.user_id_hash)?;
+        
+        // Evaluation and proving run on the job workers; this call waits for a queue slot and the result
+        let service = self.clone();
+        let requester = owner.clone();
+        let response = self.jobs()?
+            .run(&owner, move |job| service.evaluate_application(&requester, req, job))
+            .await?;
+        
+        Ok(Response::new(response))
+    }
+    
+    async fn get_remaining_budget(
+        &self,
+        request: Request<PrivacyBudgetRequest>,
+    ) -> Result<Response<PrivacyBudgetResponse>, Status> {
+        let principal = self.authorizer.authorize(&request, "NBFCService/GetRemainingBudget")?;
+        let user_id_hash = principal.user_id_hash(&request.get_ref().user_id_hash)?;
+        
+        let response = PrivacyBudgetResponse {
+            total_epsilon: self.budget_ledger.total_epsilon(),
+            spent_epsilon: self.budget_ledger.spent(&user_id_hash),
+            remaining_epsilon: self.budget_ledger.remaining(&user_id_hash),
+        };
+        
+        Ok(Response::new(response))
+    }
+    
+    async fn partial_decrypt(
+        &self,
+        request: Request<PartialDecryptionRequest>,
+    ) -> Result<Response<PartialDecryptionResponse>, Status> {
+        self.authorizer.authorize(&request, "NBFCService/PartialDecrypt")?;
+        let req = request.into_inner();
+        
+        let key_share = self.key_share
+            .as_ref()
+            .ok_or_else(|| CreditError::FailedPrecondition("NBFC holds no key share".into()))?;
+        
+        // The share must not decrypt anything but the Bank's decision inputs, e.g. the salary
+        let released = self.released_blocks
+            .lock()
+            .unwrap()
+            .contains(&req.ciphertext, attestation::now_unix(), bank_service::NONCE_RETENTION);
+        if !released {
+            return Err(CreditError::PermissionDenied("NBFC has not released this ciphertext for joint decryption".into()).into());
+        }
+        
+        let ciphertext = bincode::deserialize(&req.ciphertext)
+            .map_err(|e| CreditError::deserialization("ciphertext", e))?;
+        
+        let partial = key_share
+            .partial_decrypt(&ciphertext)
+            .map_err(|e| CreditError::InvalidArgument(format!("Failed to partially decrypt: {}", e)))?;
+        
+        let response = PartialDecryptionResponse {
+            party: key_share.party.clone(),
+            partial_decryption: bincode::serialize(&partial)
+                .map_err(|e| CreditError::internal("serialize partial decryption", e))?,
+        };
+        
+        Ok(Response::new(response))
+    }
+    
+    async fn generate_portfolio_report(
+        &self,
+        request: Request<PortfolioReportRequest>,
+    ) -> Result<Response<PortfolioReportResponse>, Status> {
+        self.authorizer.authorize(&request, "NBFCService/GeneratePortfolioReport")?;
+        let req = request.into_inner();
+        
+        if req.key_id.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing FHE key ID".into()).into());
+        }
+        
+        let mut config = PortfolioReportConfig::default();
+        if !req.income_bucket_bounds.is_empty() {
+            config.income_bucket_bounds = req.income_bucket_bounds;
+        }
+        if req.epsilon > 0.0 {
+            config.epsilon = req.epsilon;
+        }
+        config
+            .validate()
+            .map_err(|e| CreditError::InvalidArgument(e.to_string()))?;
+        
+        let entries = self.portfolio.entries_for(&req.key_id);
+        if entries.is_empty() {
+            return Err(CreditError::NotFound(format!("No applications recorded for key {}", req.key_id)).into());
+        }
+        
+        let key_store = self.key_store
+            .as_ref()
+            .ok_or_else(|| CreditError::FailedPrecondition("NBFC has no key store".into()))?;
+        let server_key = key_store
+            .load_server_key(&req.key_id)
+            .map_err(|e| CreditError::NotFound(e.to_string()))?;
+        
+        // Every report draws on the portfolio's privacy budget, like a user's noisy average
+        self.budget_ledger
+            .charge(&portfolio_budget_id(&req.key_id), config.epsilon)
+            .map_err(|e| CreditError::BudgetExhausted(e.to_string()))?;
+        
+        let report = self.on_fhe_pool(|| portfolio::generate_encrypted_portfolio_report(&entries, &config, &server_key))
+            .map_err(|e| CreditError::internal("aggregate portfolio", e))?;
+        let response = encode_portfolio_report(&req.key_id, &report)
+            .map_err(|e| CreditError::internal("serialize portfolio report", e))?;
+        
+        Ok(Response::new(response))
+    }
+    
+    async fn publish_proof_bundle(
+        &self,
+        request: Request<PublishProofBundleRequest>,
+    ) -> Result<Response<PublishProofBundleResponse>, Status> {
+        let principal = self.authorizer.authorize(&request, "NBFCService/PublishProofBundle")?;
+        let req = request.into_inner();
+        let mut bundle = req.bundle
+            .ok_or_else(|| CreditError::InvalidArgument("Missing proof bundle".into()))?;
+        
+        // A bundle can only be published for the user the token names
+        bundle.user_id_hash = principal.user_id_hash(&bundle.user_id_hash)?;
+        if bundle.key_id.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing FHE key ID".into()).into());
+        }
+        if req.consented_requesters.is_empty() {
+            return Err(CreditError::InvalidArgument("A bundle must be shared with at least one requester".into()).into());
+        }
+        
+        // Only bundles carrying a valid proof are worth sharing
+        let salary_commitment = zk_salary_circuit::commitment_from_bytes(&bundle.salary_commitment)
+            .map_err(|e| CreditError::deserialization("salary commitment", e))?;
+        let nonce = zk_salary_circuit::nonce_from_bytes(&bundle.nonce)
+            .map_err(CreditError::InvalidArgument)?;
+        let proof_valid = zk_salary_circuit::verify_committed_salary_threshold_proof(
+            &bundle.zkp_proof,
+            bundle.threshold,
+            &salary_commitment,
+            &nonce,
+        ).map_err(|e| CreditError::ProofInvalid(format!("Failed to verify proof: {}", e)))?;
+        if !proof_valid {
+            return Err(CreditError::ProofInvalid("Proof bundle does not verify".into()).into());
+        }
+        
+        let (proof_id, expiration_timestamp) = self.proof_registry
+            .publish(bundle, req.consented_requesters.into_iter().collect())
+            .map_err(registry_status)?;
+        
+        Ok(Response::new(PublishProofBundleResponse {
+            proof_id,
+            expiration_timestamp,
+        }))
+    }
+    
+    async fn fetch_proof_bundle(
+        &self,
+        request: Request<FetchProofBundleRequest>,
+    ) -> Result<Response<SharedProofBundle>, Status> {
+        // Consent is checked against the authenticated institution
+        let requester = self.authorizer.authorize(&request, "NBFCService/FetchProofBundle")?.institution;
+        let req = request.into_inner();
+        
+        let bundle = self.proof_registry
+            .fetch(&req.proof_id, &requester)
+            .map_err(registry_status)?;
+        
+        Ok(Response::new(bundle))
+    }
+    
+    async fn revoke_proof_bundle(
+        &self,
+        request: Request<RevokeProofBundleRequest>,
+    ) -> Result<Response<RevokeProofBundleResponse>, Status> {
+        let principal = self.authorizer.authorize(&request, "NBFCService/RevokeProofBundle")?;
+        let req = request.into_inner();
+        let user_id_hash = principal.user_id_hash(&req.user_id_hash)?;
+        
+        self.proof_registry
+            .revoke(&req.proof_id, &user_id_hash)
+            .map_err(registry_status)?;
+        
+        Ok(Response::new(RevokeProofBundleResponse { revoked: true }))
+    }
+    
+    async fn list_proof_bundles(
+        &self,
+        request: Request<ListProofBundlesRequest>,
+    ) -> Result<Response<ListProofBundlesResponse>, Status> {
+        let principal = self.authorizer.authorize(&request, "NBFCService/ListProofBundles")?;
+        let user_id_hash = principal.user_id_hash(&request.get_ref().user_id_hash)?;
+        
+        let now = proof_registry::unix_now();
+        let bundles = self.proof_registry
+            .list(&user_id_hash)
+            .into_iter()
+            .map(|published| {
+                let expired = published.is_expired(now);
+                let mut consented_requesters: Vec<String> = published.consented_requesters.into_iter().collect();
+                consented_requesters.sort();
+                ProofBundleSummary {
+                    proof_id: published.bundle.proof_id,
+                    expiration_timestamp: published.bundle.expiration_timestamp,
+                    revoked: published.revoked,
+                    expired,
+                    consented_requesters,
+                }
+            })
+            .collect();
+        
+        Ok(Response::new(ListProofBundlesResponse { bundles }))
+    }
+    
+    async fn begin_upload(
+        &self,
+        request: Request<BeginUploadRequest>,
+    ) -> Result<Response<UploadStatusResponse>, Status> {
+        let owner = self.authorizer.authorize(&request, "NBFCService/BeginUpload")?.owner();
+        let req = request.into_inner();
+        
+        if req.key_id.is_empty() {
+            return Err(CreditError::InvalidArgument("Missing FHE key ID".into()).into());
+        }
+        
+        let progress = self.uploads
+            .begin(&owner, &req.key_id, req.parts)
+            .map_err(upload_status)?;
+        
+        Ok(Response::new(progress.into()))
+    }
+    
+    async fn upload_chunks(
+        &self,
+        request: Request<Streaming<UploadChunk>>,
+    ) -> Result<Response<UploadStatusResponse>, Status> {
+        let owner = self.authorizer.authorize(&request, "NBFCService/UploadChunks")?.owner();
+        let mut chunks = request.into_inner();
+        
+        // Chunks are appended as they arrive, so bytes received before an interruption are kept.
+        // Writing and hashing them is file I/O, so it stays off the async workers.
+        let mut progress = None;
+        while let Some(chunk) = chunks.message().await? {
+            let uploads = self.uploads.clone();
+            let owner = owner.clone();
+            let appended = tokio::task::spawn_blocking(move || uploads.append(&owner, &chunk))
+                .await
+                .map_err(|e| CreditError::internal("append chunk", e))?;
+            progress = Some(appended.map_err(upload_status)?);
+        }
+        let progress = progress.ok_or_else(|| CreditError::InvalidArgument("Upload stream contained no chunks".into()))?;
+        
+        Ok(Response::new(progress.into()))
+    }
+    
+    async fn get_upload_status(
+        &self,
+        request: Request<UploadStatusRequest>,
+    ) -> Result<Response<UploadStatusResponse>, Status> {
+        let owner = self.authorizer.authorize(&request, "NBFCService/GetUploadStatus")?.owner();
+        let req = request.into_inner();
+        
+        let progress = self.uploads
+            .status(&owner, &req.upload_id)
+            .map_err(upload_status)?;
+        
+        Ok(Response::new(progress.into()))
+    }
+    
+    async fn submit_credit_proof_job(
+        &self,
+        request: Request<EncryptedFinancialRequest>,
+    ) -> Result<Response<JobStatus>, Status> {
+        let principal = self.authorizer.authorize(&request, "NBFCService/SubmitCreditProofJob")?;
+        let owner = principal.owner();
+        let mut req = request.into_inner();
+        req.user_id_hash = principal.user_id_hash(&req.user_id_hash)?;
+        
+        // A full queue refuses the job so callers back off instead of piling up work
+        let service = self.clone();
+        let requester = owner.clone();
+        let status = self.jobs()?
+            .submit(&owner, move |job| service.evaluate_application(&requester, req, job))
+            .map_err(job_status)?;
+        
+        Ok(Response::new(status))
+    }
+    
+    async fn get_job_status(
+        &self,
+        request: Request<JobStatusRequest>,
+    ) -> Result<Response<JobStatus>, Status> {
+        let owner = self.authorizer.authorize(&request, "NBFCService/GetJobStatus")?.owner();
+        let req = request.into_inner();
+        
+        let status = self.jobs()?
+            .status(&owner, &req.job_id)
+            .map_err(job_status)?;
+        
+        Ok(Response::new(status))
+    }
+    
+    async fn stream_job_progress(
+        &self,
+        request: Request<JobStatusRequest>,
+    ) -> Result<Response<Self::StreamJobProgressStream>, Status> {
+        let owner = self.authorizer.authorize(&request, "NBFCService/StreamJobProgress")?.owner();
+        let req = request.into_inner();
+        
+        let mut updates = self.jobs()?
+            .subscribe(&owner, &req.job_id)
+            .map_err(job_status)?;
+        
+        // Forward every status change until the job finishes or the caller hangs up
+        let (sender, receiver) = mpsc::channel(4);
+        tokio::spawn(async move {
+            loop {
+                let status = updates.borrow_and_update().clone();
+                let finished = proof_jobs::is_finished(&status);
+                if sender.send(Ok(status)).await.is_err() || finished {
+                    break;
+                }
+                if updates.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+        
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+    
+    async fn cancel_job(
+        &self,
+        request: Request<JobStatusRequest>,
+    ) -> Result<Response<JobStatus>, Status> {
+        let owner = self.authorizer.authorize(&request, "NBFCService/CancelJob")?.owner();
+        let req = request.into_inner();
+        
+        let status = self.jobs()?
+            .cancel(&owner, &req.job_id)
+            .map_err(job_status)?;
+        
+        Ok(Response::new(status))
+    }
+}
+
+/// Rebuilds an encrypted portfolio report from its wire form, for the key holder to release
+pub fn decode_portfolio_report(response: &PortfolioReportResponse) -> Result<EncryptedPortfolioReport, Box<dyn std::error::Error>> {
+    let config = PortfolioReportConfig {
+        income_bucket_bounds: response.income_bucket_bounds.clone(),
+        epsilon: response.epsilon,
+        income_clip: response.income_clip,
+        obligation_clip: response.obligation_clip,
+    };
+    let income_histogram = response.income_histogram
+        .iter()
+        .map(decode_aggregate)
+        .collect::<Result<Vec<_>, _>>()?;
+    let income_sum = decode_aggregate(response.income_sum.as_ref().ok_or("Missing income sum")?)?;
+    let obligation_sum = decode_aggregate(response.obligation_sum.as_ref().ok_or("Missing obligation sum")?)?;
+    
+    Ok(EncryptedPortfolioReport {
+        config,
+        income_histogram,
+        income_sum,
+        obligation_sum,
+    })
+}
+
+// Helper Functions
+
+fn registry_status(error: RegistryError) -> Status {
+    let message = error.to_string();
+    match error {
+        RegistryError::NotFound => CreditError::NotFound(message),
+        RegistryError::Expired => CreditError::Expired(message),
+        RegistryError::Revoked => CreditError::FailedPrecondition(message),
+        RegistryError::NotConsented { .. } | RegistryError::NotOwner => CreditError::PermissionDenied(message),
+        RegistryError::InvalidExpiration(_) => CreditError::InvalidArgument(message),
+    }
+    .into()
+}
+
+fn upload_status(error: UploadError) -> Status {
+    let message = error.to_string();
+    match error {
+        UploadError::NotFound => CreditError::NotFound(message),
+        UploadError::NotOwner => CreditError::PermissionDenied(message),
+        UploadError::Incomplete => CreditError::FailedPrecondition(message),
+        UploadError::KeyMismatch { expected } => CreditError::KeyMismatch { key_id: expected, message },
+        UploadError::InvalidManifest(_) | UploadError::InvalidChunk(_) => CreditError::InvalidArgument(message),
+        UploadError::HashMismatch { .. } => CreditError::DataLoss(message),
+        UploadError::TooManyUploads { limit } => CreditError::TooManyUploads { limit },
+        UploadError::Storage(_) => CreditError::Internal(message),
+    }
+    .into()
+}
+
+fn job_status(error: JobError) -> Status {
+    let message = error.to_string();
+    match error {
+        JobError::NotFound => CreditError::NotFound(message),
+        JobError::NotOwner => CreditError::PermissionDenied(message),
+        JobError::QueueFull => CreditError::QueueFull,
+        JobError::Stopped => CreditError::Unavailable(message),
+    }
+    .into()
+}
+
+fn portfolio_budget_id(key_id: &str) -> Vec<u8> {
+    format!("portfolio:{}", key_id).into_bytes()
+}
+
+fn encode_portfolio_report(key_id: &str, report: &EncryptedPortfolioReport) -> Result<PortfolioReportResponse, Box<dyn std::error::Error>> {
+    let income_histogram = report.income_histogram
+        .iter()
+        .map(|count| encode_aggregate(count))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    Ok(PortfolioReportResponse {
+        key_id: key_id.to_string(),
+        income_bucket_bounds: report.config.income_bucket_bounds.clone(),
+        epsilon: report.config.epsilon,
+        income_clip: report.config.income_clip,
+        obligation_clip: report.config.obligation_clip,
+        income_histogram,
+        income_sum: Some(encode_aggregate(&report.income_sum)?),
+        obligation_sum: Some(encode_aggregate(&report.obligation_sum)?),
+    })
+}
+
+fn encode_aggregate(blocks: &[Ciphertext]) -> Result<EncryptedAggregate, Box<dyn std::error::Error>> {
+    let encrypted_blocks = blocks
+        .iter()
+        .map(bincode::serialize)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(EncryptedAggregate { encrypted_blocks })
+}
+
+fn decode_aggregate(aggregate: &EncryptedAggregate) -> Result<Vec<Ciphertext>, Box<dyn std::error::Error>> {
+    let blocks = aggregate.encrypted_blocks
+        .iter()
+        .map(|block| bincode::deserialize(block))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(blocks)
+}
+
+fn deserialize_ciphertexts(data: &[Vec<u8>]) -> Result<Vec<Ciphertext>, Box<dyn std::error::Error>> {
+    let ciphertexts = data
+        .iter()
+        .map(|ct| bincode::deserialize(ct))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ciphertexts)
+}
+
+fn serialize_ciphertext(ct: &Ciphertext) -> Result<Vec<u8>, Status> {
+    bincode::serialize(ct).map_err(|e| CreditError::internal("serialize ciphertext", e).into())
+}
+
+fn serialize_radix(blocks: &[Ciphertext]) -> Result<Vec<Vec<u8>>, Status> {
+    blocks.iter().map(serialize_ciphertext).collect()
+}
+
+/// Refuses an uploaded server key unless it is byte for byte the one stored for the key set
+fn check_uploaded_server_key(key_store: &KeyStore, key_id: &str, uploaded: &[u8]) -> Result<(), CreditError> {
+    let stored = key_store
+        .server_key_sha256(key_id)
+        .map_err(|e| CreditError::internal("load server key", e))?;
+    if Sha256::digest(uploaded).as_slice() != stored.as_slice() {
+        return Err(CreditError::KeyMismatch {
+            key_id: key_id.to_string(),
+            message: format!("Uploaded server key does not match the stored key for {}", key_id),
+        });
+    }
+    Ok(())
+}
+
+fn deserialize_categorized_expense(expense: &CategorizedExpense) -> Result<EncryptedCategorizedExpense, CreditError> {
+    let category = match ExpenseCategory::from_i32(expense.category) {
+        Some(ExpenseCategory::Rent) => fhe_utils::ExpenseCategory::Rent,
+        Some(ExpenseCategory::Emi) => fhe_utils::ExpenseCategory::Emi,
+        Some(ExpenseCategory::Utilities) => fhe_utils::ExpenseCategory::Utilities,
+        Some(ExpenseCategory::Discretionary) => fhe_utils::ExpenseCategory::Discretionary,
+        _ => {
+            return Err(CreditError::deserialization(
+                "categorized expenses",
+                format!("unknown expense category {}", expense.category),
+            ))
+        }
+    };
+    // Every radix helper needs at least one block to work on
+    if expense.encrypted_amount_blocks.is_empty() {
+        return Err(CreditError::deserialization("categorized expenses", "expense amount has no blocks"));
+    }
+    Ok(EncryptedCategorizedExpense {
+        category,
+        blocks: deserialize_ciphertexts(&expense.encrypted_amount_blocks)
+            .map_err(|e| CreditError::deserialization("categorized expenses", e))?,
+    })
+}
+
+fn compute_encrypted_credit_score(
+    encrypted_salary: &[Ciphertext],
+    encrypted_avg_expense: &[Ciphertext],
+    categorized_expenses: &[EncryptedCategorizedExpense],
+    encrypted_tenure: &[Ciphertext],
+    model: &ScoringModel,
+    pk: &ServerKey,
+) -> Result<Vec<Ciphertext>, Box<dyn std::error::Error>> {
+    let features = credit_scoring::EncryptedFeatures {
+        salary: encrypted_salary.to_vec(),
+        avg_expense: encrypted_avg_expense.to_vec(),
+        emi_load: credit_scoring::compute_encrypted_emi_load(categorized_expenses, pk)?,
+        tenure: encrypted_tenure.to_vec(),
+    };
+    model.evaluate_encrypted(&features, pk)
+}
+
+fn compute_encrypted_loan_offer(
+    encrypted_salary: &[Ciphertext],
+    encrypted_total_expenses: &[Ciphertext],
+    loan_terms: &LoanTerms,
+    pk: &ServerKey,
+) -> Result<(EncryptedLoanOffer, Ciphertext), Box<dyn std::error::Error>> {
+    let encrypted_disposable_income = fhe_utils::compute_encrypted_disposable_income(
+        encrypted_salary,
+        encrypted_total_expenses,
+        pk,
+    )?;
+    let encrypted_max_emi = fhe_utils::compute_encrypted_max_emi(
+        &encrypted_disposable_income,
+        loan_terms.foir_percent,
+        pk,
+    )?;
+    let encrypted_max_principal = fhe_utils::compute_encrypted_max_principal(
+        &encrypted_max_emi,
+        loan_terms.annual_interest_percent,
+        loan_terms.tenure_months,
+        pk,
+    )?;
+    let encrypted_affordability_check = fhe_utils::compute_encrypted_affordability_check(
+        &encrypted_disposable_income,
+        loan_terms.foir_percent,
+        loan_terms.requested_emi,
+        pk,
+    )?;
+    
+    let offer = EncryptedLoanOffer {
+        encrypted_max_emi_blocks: encrypted_max_emi
+            .iter()
+            .map(bincode::serialize)
+            .collect::<Result<Vec<_>, _>>()?,
+        encrypted_max_principal_blocks: encrypted_max_principal
+            .iter()
+            .map(bincode::serialize)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    Ok((offer, encrypted_affordability_check))
+}
+
+fn serialize_public_inputs(
+    threshold: u64,
+    salary_commitment: &zk_salary_circuit::SalaryCommitment,
+    nonce: &zk_salary_circuit::ProofNonce,
+) -> Vec<u8> {
+    // Serialize the public inputs for the ZK proof, in circuit order
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&threshold.to_le_bytes());
+    buf.extend_from_slice(&zk_salary_circuit::commitment_to_bytes(salary_commitment));
+    buf.extend_from_slice(nonce);
+    buf
+}
+
+// Start the NBFC server
+pub async fn start_nbfc_server(config: AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = config.services.nbfc_addr.parse()?;
+    let scoring_model = ScoringModel::load(config.scoring.model, config.scoring.model_path())?;
+    // The NBFC's share of the secret key, if it takes part in joint decryption
+    let key_share = match std::env::var(threshold_decryption::NBFC_KEY_SHARE_ENV) {
+        Ok(path) => Some(Arc::new(KeyShare::load(path)?)),
+        Err(_) => None,
+    };
+    let key_store = Arc::new(
+        KeyStore::open(key_store::DEFAULT_KEY_STORE_DIR, key_store::DEFAULT_ROTATION_INTERVAL)?
+            .with_parameter_set(config.fhe.parameter_set)?,
+    );
+    // Rotation seals each new client key under the passphrase. A split key set is rotated by
+    // hand instead, since its successor must be split and the shares handed out again.
+    match (std::env::var(key_store::KEY_PASSPHRASE_ENV), &key_share) {
+        (Ok(passphrase), None) => {
+            tokio::spawn(key_store::run_rotation_schedule(
+                key_store.clone(),
+                passphrase,
+                key_store::ROTATION_CHECK_INTERVAL,
+            ));
+        }
+        (Err(_), None) => println!("Scheduled key rotation is off: {} is not set", key_store::KEY_PASSPHRASE_ENV),
+        (_, Some(_)) => println!("Scheduled key rotation is off: the key set is split"),
+    }
+    let authorizer = Arc::new(Authorizer::new(
+        auth::default_access_policy(),
+        AuthAuditLog::open(auth::DEFAULT_AUTH_AUDIT_LOG_PATH)?,
+    ));
+    let service = NBFCServiceImpl {
+        scoring_model,
+        key_share,
+        key_store: Some(key_store),
+        attestation_key: Some(Arc::new(SigningKeyPaths::from_env(tls::NBFC_IDENTITY).load_signing_key()?)),
+        authorizer: authorizer.clone(),
+        budget_ledger: Arc::new(PrivacyBudgetLedger::new(config.privacy.total_epsilon)),
+        category_weights: config.scoring.category_weights,
+        parameter_policy: config.fhe.parameter_policy(),
+        privacy: config.privacy,
+        uploads: Arc::new(UploadStore::open(chunked_upload::DEFAULT_UPLOAD_DIR)?),
+        jobs: Some(Arc::new(ProofJobs::start(config.jobs.queue_capacity, config.jobs.workers))),
+        fhe_pool: Some(Arc::new(fhe_utils::build_fhe_thread_pool(config.fhe.threads)?)),
+        ..Default::default()
+    };
+    
+    println!("NBFC Server listening on {}", addr);
+    
+    Server::builder()
+        .tls_config(tls::server_tls_config(&TlsPaths::from_env(tls::NBFC_IDENTITY))?)?
+        .add_service(NbfcServiceServer::with_interceptor(service, AuthInterceptor::from_env(authorizer)?))
+        .serve(addr)
+        .await?;
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+    use credit_evaluation::ReasonCode;
+    use tonic::Code;
+
+    fn reason(status: &Status) -> i32 {
+        error::error_detail(status).unwrap().reason
+    }
+
+    #[test]
+    fn registry_errors_carry_reason_codes() {
+        let status = registry_status(RegistryError::Revoked);
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(reason(&status), ReasonCode::FailedPrecondition as i32);
+        assert_eq!(reason(&registry_status(RegistryError::NotOwner)), ReasonCode::PermissionDenied as i32);
+        assert_eq!(reason(&registry_status(RegistryError::Expired)), ReasonCode::Expired as i32);
+    }
+
+    #[test]
+    fn upload_errors_carry_reason_codes() {
+        let status = upload_status(UploadError::KeyMismatch { expected: "key-1".into() });
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(error::error_detail(&status).unwrap().metadata.get("key_id").map(String::as_str), Some("key-1"));
+        assert_eq!(upload_status(UploadError::HashMismatch { part: 0 }).code(), Code::DataLoss);
+        assert_eq!(
+            reason(&upload_status(UploadError::TooManyUploads { limit: 4 })),
+            ReasonCode::TooManyUploads as i32
+        );
+    }
+
+    #[test]
+    fn expenses_without_blocks_are_rejected() {
+        let expense = CategorizedExpense {
+            category: ExpenseCategory::Rent as i32,
+            ..Default::default()
+        };
+        match deserialize_categorized_expense(&expense) {
+            Err(CreditError::Deserialization { field, .. }) => assert_eq!(field, "categorized expenses"),
+            other => panic!("expected a deserialization error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn only_the_stored_server_key_may_be_uploaded() {
+        let dir = std::env::temp_dir().join(format!("credit-nbfc-upload-key-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = KeyStore::open(&dir, key_store::DEFAULT_ROTATION_INTERVAL).unwrap();
+        let key_id = store.generate_keys("passphrase").unwrap();
+        let stored = bincode::serialize(&store.load_server_key(&key_id).unwrap()).unwrap();
+
+        assert!(check_uploaded_server_key(&store, &key_id, &stored).is_ok());
+        let mut forged = stored.clone();
+        forged[0] ^= 1;
+        match check_uploaded_server_key(&store, &key_id, &forged) {
+            Err(CreditError::KeyMismatch { key_id: rejected, .. }) => assert_eq!(rejected, key_id),
+            other => panic!("expected a key mismatch, got {:?}", other),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn job_errors_carry_reason_codes() {
+        let status = job_status(JobError::QueueFull);
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(reason(&status), ReasonCode::QueueFull as i32);
+        assert_eq!(job_status(JobError::Stopped).code(), Code::Unavailable);
+        assert_eq!(reason(&job_status(JobError::NotFound)), ReasonCode::NotFound as i32);
+    }
+}
@@ -0,0 +1,301 @@
+use std::error::Error;
+use std::sync::Mutex;
+use tfhe::shortint::prelude::*;
+
+use crate::fhe_utils::{self, EncryptedAccumulator, MAX_ACCUMULATOR_BLOCKS};
+
+/// Default monthly income bucket boundaries for the portfolio histogram
+pub const DEFAULT_INCOME_BUCKET_BOUNDS: [u64; 4] = [2000, 4000, 8000, 16000];
+
+/// Default epsilon spent on one portfolio report
+pub const DEFAULT_PORTFOLIO_EPSILON: f64 = 1.0;
+
+/// Incomes above this are clipped before summing, bounding one applicant's influence
+pub const DEFAULT_INCOME_CLIP: u64 = 50_000;
+
+/// Monthly obligations above this are clipped before summing
+pub const DEFAULT_OBLIGATION_CLIP: u64 = 25_000;
+
+/// Ciphertexts kept from one completed application for portfolio reporting
+#[derive(Clone)]
+pub struct PortfolioEntry {
+    pub key_id: String,
+    /// Salary as little-endian radix blocks
+    pub salary_blocks: Vec<Ciphertext>,
+    /// Encrypted existing EMI obligations, each as little-endian radix blocks
+    pub obligations: Vec<Vec<Ciphertext>>,
+}
+
+/// In-memory store of applications the NBFC has evaluated
+#[derive(Default)]
+pub struct PortfolioStore {
+    entries: Mutex<Vec<PortfolioEntry>>,
+}
+
+// Ciphertexts are left out of debug output
+impl std::fmt::Debug for PortfolioStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PortfolioStore")
+            .field("entries", &self.entries.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl PortfolioStore {
+    /// Records an evaluated application
+    pub fn record(&self, entry: PortfolioEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Returns the applications encrypted under the given key
+    pub fn entries_for(&self, key_id: &str) -> Vec<PortfolioEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.key_id == key_id)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Settings for one portfolio report
+#[derive(Debug, Clone)]
+pub struct PortfolioReportConfig {
+    /// Ascending lower bounds of the income buckets; the first bucket starts at zero
+    pub income_bucket_bounds: Vec<u64>,
+    pub epsilon: f64,
+    pub income_clip: u64,
+    pub obligation_clip: u64,
+}
+
+impl Default for PortfolioReportConfig {
+    fn default() -> Self {
+        Self {
+            income_bucket_bounds: DEFAULT_INCOME_BUCKET_BOUNDS.to_vec(),
+            epsilon: DEFAULT_PORTFOLIO_EPSILON,
+            income_clip: DEFAULT_INCOME_CLIP,
+            obligation_clip: DEFAULT_OBLIGATION_CLIP,
+        }
+    }
+}
+
+/// Encrypted portfolio aggregates, each as little-endian radix blocks
+///
+/// These are exact; noise is added when the key holder releases them.
+pub struct EncryptedPortfolioReport {
+    pub config: PortfolioReportConfig,
+    /// One count per bucket, from `[0, bounds[0])` to `[bounds[last], inf)`
+    pub income_histogram: Vec<Vec<Ciphertext>>,
+    pub income_sum: Vec<Ciphertext>,
+    pub obligation_sum: Vec<Ciphertext>,
+}
+
+/// Differentially private portfolio statistics
+#[derive(Debug, Clone)]
+pub struct PortfolioStatistics {
+    pub income_histogram: Vec<u64>,
+    pub income_sum: u64,
+    pub obligation_sum: u64,
+    /// Portfolio FOIR: total obligations over total income, in percent
+    pub average_foir_percent: f64,
+}
+
+impl PortfolioReportConfig {
+    /// Rejects bucket bounds and budgets that cannot produce a meaningful report
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.income_bucket_bounds.is_empty() {
+            return Err("At least one income bucket bound is required".into());
+        }
+        if self.income_bucket_bounds.windows(2).any(|w| w[0] >= w[1]) || self.income_bucket_bounds[0] == 0 {
+            return Err("Income bucket bounds must be positive and strictly ascending".into());
+        }
+        if !(self.epsilon > 0.0 && self.epsilon.is_finite()) {
+            return Err(format!("Epsilon must be positive, got {}", self.epsilon).into());
+        }
+        if self.income_clip == 0 || self.obligation_clip == 0 {
+            return Err("Clipping bounds must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+/// Aggregates applicants' ciphertexts into a histogram and sums without decrypting any of them
+pub fn generate_encrypted_portfolio_report(
+    entries: &[PortfolioEntry],
+    config: &PortfolioReportConfig,
+    pk: &ServerKey,
+) -> Result<EncryptedPortfolioReport, Box<dyn Error>> {
+    config.validate()?;
+    if entries.is_empty() {
+        return Err("No applications recorded for this key".into());
+    }
+
+    // Step 1: Count applicants per income bucket
+    let mut lowers = vec![0];
+    lowers.extend_from_slice(&config.income_bucket_bounds);
+    let mut histogram = Vec::with_capacity(lowers.len());
+    for (i, lower) in lowers.iter().enumerate() {
+        let upper = lowers.get(i + 1).copied();
+        let mut count: Option<EncryptedAccumulator> = None;
+        for entry in entries {
+            let indicator = fhe_utils::compute_encrypted_range_indicator(&entry.salary_blocks, *lower, upper, pk)?;
+            match count.as_mut() {
+                Some(count) => count.add(&indicator, pk)?,
+                None => count = Some(EncryptedAccumulator::new(&indicator, MAX_ACCUMULATOR_BLOCKS)),
+            }
+        }
+        let count = count.ok_or("No applications recorded for this key")?;
+        histogram.push(count.finish(pk)?);
+    }
+
+    // Step 2: Sum clipped incomes
+    let mut income_sum = EncryptedAccumulator::new(&pk.create_trivial(0), MAX_ACCUMULATOR_BLOCKS);
+    for entry in entries {
+        let clipped = fhe_utils::compute_encrypted_clipped_radix(&entry.salary_blocks, config.income_clip, pk)?;
+        income_sum.add_radix(&clipped, pk)?;
+    }
+    let income_sum = income_sum.finish(pk)?;
+
+    // Step 3: Sum clipped obligations, one applicant at a time so each is clipped as a whole
+    let mut obligation_sum = EncryptedAccumulator::new(&pk.create_trivial(0), MAX_ACCUMULATOR_BLOCKS);
+    for entry in entries {
+        let mut applicant = EncryptedAccumulator::new(&pk.create_trivial(0), MAX_ACCUMULATOR_BLOCKS);
+        for obligation in &entry.obligations {
+            applicant.add_radix(obligation, pk)?;
+        }
+        let applicant = applicant.finish(pk)?;
+        let clipped = fhe_utils::compute_encrypted_clipped_radix(&applicant, config.obligation_clip, pk)?;
+        obligation_sum.add_radix(&clipped, pk)?;
+    }
+    let obligation_sum = obligation_sum.finish(pk)?;
+
+    Ok(EncryptedPortfolioReport {
+        config: config.clone(),
+        income_histogram: histogram,
+        income_sum,
+        obligation_sum,
+    })
+}
+
+/// Decrypts a report and adds Laplace noise before release (to be done only by the key holder)
+///
+/// The epsilon is split evenly across the histogram, the income sum and the obligation
+/// sum. One applicant moves two histogram counts by one each, and at most the clip in
+/// each sum, which sets the sensitivity of each release.
+pub fn release_portfolio_report(
+    report: &EncryptedPortfolioReport,
+    sk: &ClientKey,
+) -> Result<PortfolioStatistics, Box<dyn Error>> {
+    let config = &report.config;
+    config.validate()?;
+    let release_epsilon = config.epsilon / 3.0;
+
+    let income_histogram = report
+        .income_histogram
+        .iter()
+        .map(|count| Ok(add_laplace_noise(fhe_utils::decrypt_radix(count, sk)?, 2.0 / release_epsilon)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let income_sum = add_laplace_noise(
+        fhe_utils::decrypt_radix(&report.income_sum, sk)?,
+        config.income_clip as f64 / release_epsilon,
+    );
+    let obligation_sum = add_laplace_noise(
+        fhe_utils::decrypt_radix(&report.obligation_sum, sk)?,
+        config.obligation_clip as f64 / release_epsilon,
+    );
+
+    // Post-processing of the noisy sums costs no extra budget
+    let average_foir_percent = if income_sum == 0 {
+        0.0
+    } else {
+        obligation_sum as f64 * 100.0 / income_sum as f64
+    };
+
+    Ok(PortfolioStatistics {
+        income_histogram,
+        income_sum,
+        obligation_sum,
+        average_foir_percent,
+    })
+}
+
+/// Adds rounded Laplace noise with the given scale, clamping at zero
+fn add_laplace_noise(value: u64, scale: f64) -> u64 {
+    (value as f64 + fhe_utils::sample_laplace(scale)).round().max(0.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe_params::FheParameterSet;
+    use std::sync::OnceLock;
+
+    fn keys() -> &'static (ClientKey, ServerKey) {
+        static KEYS: OnceLock<(ClientKey, ServerKey)> = OnceLock::new();
+        KEYS.get_or_init(|| {
+            let client_key = ClientKey::new(FheParameterSet::Message2Carry2.parameters());
+            let server_key = ServerKey::new(&client_key);
+            (client_key, server_key)
+        })
+    }
+
+    fn entry(client_key: &ClientKey, salary: u64, obligations: &[u64]) -> PortfolioEntry {
+        PortfolioEntry {
+            key_id: "key".into(),
+            salary_blocks: fhe_utils::encrypt_radix(salary, fhe_utils::SALARY_BITS, client_key).unwrap(),
+            obligations: obligations
+                .iter()
+                .map(|amount| fhe_utils::encrypt_radix(*amount, fhe_utils::EXPENSE_BITS, client_key).unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        assert!(PortfolioReportConfig::default().validate().is_ok());
+        for config in [
+            PortfolioReportConfig { income_bucket_bounds: vec![], ..Default::default() },
+            PortfolioReportConfig { income_bucket_bounds: vec![0, 100], ..Default::default() },
+            PortfolioReportConfig { income_bucket_bounds: vec![200, 100], ..Default::default() },
+            PortfolioReportConfig { epsilon: 0.0, ..Default::default() },
+            PortfolioReportConfig { income_clip: 0, ..Default::default() },
+        ] {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn store_keeps_entries_per_key() {
+        let (client_key, _) = keys();
+        let store = PortfolioStore::default();
+        store.record(entry(client_key, 1000, &[]));
+        assert_eq!(store.entries_for("key").len(), 1);
+        assert!(store.entries_for("other").is_empty());
+    }
+
+    #[test]
+    fn encrypted_report_counts_and_clips_exactly() {
+        let (client_key, server_key) = keys();
+        let entries = vec![entry(client_key, 3000, &[500, 700]), entry(client_key, 9000, &[4000])];
+        let config = PortfolioReportConfig {
+            income_bucket_bounds: vec![4000],
+            income_clip: 8000,
+            obligation_clip: 1000,
+            ..Default::default()
+        };
+
+        let report = generate_encrypted_portfolio_report(&entries, &config, server_key).unwrap();
+        let decrypt = |blocks: &[Ciphertext]| fhe_utils::decrypt_radix(blocks, client_key).unwrap();
+        let histogram: Vec<u64> = report.income_histogram.iter().map(|count| decrypt(count)).collect();
+        assert_eq!(histogram, vec![1, 1]);
+        assert_eq!(decrypt(&report.income_sum), 3000 + 8000);
+        assert_eq!(decrypt(&report.obligation_sum), 1000 + 1000);
+    }
+
+    #[test]
+    fn empty_portfolio_has_no_report() {
+        let (_, server_key) = keys();
+        assert!(generate_encrypted_portfolio_report(&[], &PortfolioReportConfig::default(), server_key).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Total epsilon each user may spend before further queries are refused
+pub const DEFAULT_TOTAL_EPSILON: f64 = 3.0;
+
+/// Epsilon charged for one differentially private average expense release
+pub const NOISY_AVERAGE_EPSILON: f64 = 0.5;
+
+/// Epsilon charged for one release of the expense maximum, minimum, variance and trend together
+pub const EXPENSE_STATISTICS_EPSILON: f64 = 0.5;
+
+/// Expenses above this are clipped before averaging, bounding one expense's influence
+pub const DEFAULT_EXPENSE_CLIP: u64 = 10_000;
+
+/// Public offset added with the noise so the encrypted noisy average never goes negative
+pub const DEFAULT_NOISE_OFFSET: u64 = 1 << 20;
+
+/// Error returned when a query would exceed a user's remaining budget
+#[derive(Debug)]
+pub struct BudgetExhausted {
+    pub requested: f64,
+    pub remaining: f64,
+}
+
+impl fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "privacy budget exhausted: requested epsilon {} but only {} remains",
+            self.requested, self.remaining
+        )
+    }
+}
+
+impl Error for BudgetExhausted {}
+
+/// Ledger tracking cumulative epsilon spent per user identity hash
+#[derive(Debug)]
+pub struct PrivacyBudgetLedger {
+    total_epsilon: f64,
+    spent: Mutex<HashMap<Vec<u8>, f64>>,
+}
+
+impl Default for PrivacyBudgetLedger {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOTAL_EPSILON)
+    }
+}
+
+impl PrivacyBudgetLedger {
+    /// Creates a ledger granting every user the given total epsilon
+    pub fn new(total_epsilon: f64) -> Self {
+        Self {
+            total_epsilon,
+            spent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the total epsilon granted to each user
+    pub fn total_epsilon(&self) -> f64 {
+        self.total_epsilon
+    }
+
+    /// Returns the epsilon already spent by a user
+    pub fn spent(&self, user_id_hash: &[u8]) -> f64 {
+        let spent = self.spent.lock().unwrap();
+        spent.get(user_id_hash).copied().unwrap_or(0.0)
+    }
+
+    /// Returns the epsilon a user can still spend
+    pub fn remaining(&self, user_id_hash: &[u8]) -> f64 {
+        (self.total_epsilon - self.spent(user_id_hash)).max(0.0)
+    }
+
+    /// Charges epsilon against a user's budget, refusing the query if it would overspend
+    pub fn charge(&self, user_id_hash: &[u8], epsilon: f64) -> Result<f64, BudgetExhausted> {
+        let mut spent = self.spent.lock().unwrap();
+        let used = spent.entry(user_id_hash.to_vec()).or_insert(0.0);
+
+        let remaining = (self.total_epsilon - *used).max(0.0);
+        if epsilon > remaining {
+            return Err(BudgetExhausted {
+                requested: epsilon,
+                remaining,
+            });
+        }
+
+        *used += epsilon;
+        Ok(self.total_epsilon - *used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charges_accumulate_until_the_budget_is_spent() {
+        let ledger = PrivacyBudgetLedger::new(1.0);
+        assert_eq!(ledger.charge(b"alice", 0.5).unwrap(), 0.5);
+        assert_eq!(ledger.charge(b"alice", 0.5).unwrap(), 0.0);
+
+        let error = ledger.charge(b"alice", 0.1).unwrap_err();
+        assert_eq!(error.requested, 0.1);
+        assert_eq!(error.remaining, 0.0);
+        assert_eq!(ledger.spent(b"alice"), 1.0);
+    }
+
+    #[test]
+    fn refused_charge_spends_nothing() {
+        let ledger = PrivacyBudgetLedger::new(1.0);
+        ledger.charge(b"alice", 0.75).unwrap();
+        assert!(ledger.charge(b"alice", 0.5).is_err());
+        assert_eq!(ledger.remaining(b"alice"), 0.25);
+    }
+
+    #[test]
+    fn users_have_separate_budgets() {
+        let ledger = PrivacyBudgetLedger::default();
+        ledger.charge(b"alice", DEFAULT_TOTAL_EPSILON).unwrap();
+        assert_eq!(ledger.remaining(b"alice"), 0.0);
+        assert_eq!(ledger.remaining(b"bob"), DEFAULT_TOTAL_EPSILON);
+        assert_eq!(ledger.total_epsilon(), DEFAULT_TOTAL_EPSILON);
+    }
+}
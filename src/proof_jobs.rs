@@ -0,0 +1,486 @@
+use rand::RngCore;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tonic::codegen::{Bytes, InterceptedService};
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+
+use crate::auth::BearerToken;
+use crate::error::CreditError;
+use crate::nbfc_service::credit_evaluation::nbfc_service_client::NbfcServiceClient;
+use crate::nbfc_service::credit_evaluation::{
+    CreditProofResponse, EncryptedFinancialRequest, JobState, JobStatus, JobStatusRequest,
+};
+use crate::proof_registry;
+
+/// Time a finished job's status stays available for polling
+pub const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Steps reported while evaluating an application
+pub const EVALUATION_STEPS: u32 = 11;
+
+type ProofTask = Box<dyn FnOnce(&JobContext) -> Result<CreditProofResponse, Status> + Send>;
+
+/// Reasons the job queue refuses a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    NotFound,
+    NotOwner,
+    QueueFull,
+    Stopped,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::NotFound => write!(f, "job not found"),
+            JobError::NotOwner => write!(f, "job belongs to another caller"),
+            JobError::QueueFull => write!(f, "proof job queue is full, retry later"),
+            JobError::Stopped => write!(f, "proof workers have stopped"),
+        }
+    }
+}
+
+impl Error for JobError {}
+
+#[derive(Debug)]
+struct Job {
+    owner: String,
+    cancelled: AtomicBool,
+    /// Unix time the job finished, 0 while it is queued or running
+    finished_at: AtomicU64,
+    status: watch::Sender<JobStatus>,
+}
+
+// Every state change goes through the watch channel's lock, so checking a job's state and
+// moving it on happen in one step and a finished job is never changed again.
+impl Job {
+    fn snapshot(&self) -> JobStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Moves a queued job to running, returning false if it was cancelled first
+    fn start(&self) -> bool {
+        self.status.send_if_modified(|status| {
+            if status.state != JobState::Queued as i32 {
+                return false;
+            }
+            status.state = JobState::Running as i32;
+            status.stage = "Starting".to_string();
+            true
+        })
+    }
+
+    /// Records the job's outcome unless it has already finished
+    fn finish(&self, outcome: Result<CreditProofResponse, Status>) {
+        let finished = self.status.send_if_modified(|status| {
+            if is_finished(status) {
+                return false;
+            }
+            match outcome {
+                Ok(result) => {
+                    status.state = JobState::Succeeded as i32;
+                    status.completed_steps = status.total_steps;
+                    status.result = Some(result);
+                }
+                Err(error) => record_failure(status, error),
+            }
+            true
+        });
+        if finished {
+            self.finished_at.store(proof_registry::unix_now(), Ordering::SeqCst);
+        }
+    }
+
+    /// Cancels a queued job outright and flags a running one to stop at its next step
+    fn cancel(&self) {
+        let cancelled = self.status.send_if_modified(|status| {
+            if is_finished(status) {
+                return false;
+            }
+            self.cancelled.store(true, Ordering::SeqCst);
+            if status.state != JobState::Queued as i32 {
+                return false;
+            }
+            record_failure(status, CreditError::Cancelled.into());
+            true
+        });
+        if cancelled {
+            self.finished_at.store(proof_registry::unix_now(), Ordering::SeqCst);
+        }
+    }
+}
+
+fn record_failure(status: &mut JobStatus, error: Status) {
+    status.state = if error.code() == Code::Cancelled {
+        JobState::Cancelled as i32
+    } else {
+        JobState::Failed as i32
+    };
+    status.error_code = error.code() as i32;
+    status.error = error.message().to_string();
+    status.error_details = error.details().to_vec();
+}
+
+/// The failure a finished job reports, with the error detail it failed with
+pub fn job_failure(status: &JobStatus) -> Status {
+    Status::with_details(
+        Code::from_i32(status.error_code),
+        status.error.clone(),
+        Bytes::from(status.error_details.clone()),
+    )
+}
+
+/// Whether a job has reached a final state
+pub fn is_finished(status: &JobStatus) -> bool {
+    matches!(
+        JobState::from_i32(status.state),
+        Some(JobState::Succeeded | JobState::Failed | JobState::Cancelled)
+    )
+}
+
+/// Handle a running job uses to report progress and notice cancellation
+pub struct JobContext {
+    job: Arc<Job>,
+}
+
+impl JobContext {
+    /// Records that a step has started, failing if the job was cancelled
+    pub fn step(&self, step: u32, stage: &str) -> Result<(), Status> {
+        if self.job.cancelled.load(Ordering::SeqCst) {
+            return Err(CreditError::Cancelled.into());
+        }
+        self.job.status.send_modify(|status| {
+            status.completed_steps = step.saturating_sub(1);
+            status.stage = stage.to_string();
+        });
+        Ok(())
+    }
+}
+
+/// Cancels a job whose caller stopped waiting for it
+struct CancelOnDrop<'a> {
+    jobs: &'a ProofJobs,
+    job_id: String,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Some(job) = self.jobs.jobs.lock().unwrap().remove(&self.job_id) {
+            job.cancel();
+        }
+    }
+}
+
+/// Bounded queue of proof jobs, run by workers on the blocking thread pool
+#[derive(Debug)]
+pub struct ProofJobs {
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+    queue: mpsc::Sender<(Arc<Job>, ProofTask)>,
+}
+
+impl ProofJobs {
+    /// Starts `workers` workers taking jobs from a queue holding at most `capacity` pending jobs
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn start(capacity: usize, workers: usize) -> Self {
+        let (queue, receiver) = mpsc::channel(capacity);
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        for _ in 0..workers {
+            tokio::spawn(run_worker(receiver.clone()));
+        }
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            queue,
+        }
+    }
+
+    /// Queues a job and returns its status, refusing it if the queue is full
+    pub fn submit<F>(&self, owner: &str, task: F) -> Result<JobStatus, JobError>
+    where
+        F: FnOnce(&JobContext) -> Result<CreditProofResponse, Status> + Send + 'static,
+    {
+        let job = self.register(owner);
+        let job_id = job.snapshot().job_id;
+        match self.queue.try_send((job.clone(), Box::new(task))) {
+            Ok(()) => Ok(job.snapshot()),
+            Err(e) => {
+                self.jobs.lock().unwrap().remove(&job_id);
+                match e {
+                    mpsc::error::TrySendError::Full(_) => Err(JobError::QueueFull),
+                    mpsc::error::TrySendError::Closed(_) => Err(JobError::Stopped),
+                }
+            }
+        }
+    }
+
+    /// Queues a job once there is room and waits for its result
+    ///
+    /// Dropping the returned future cancels the job.
+    pub async fn run<F>(&self, owner: &str, task: F) -> Result<CreditProofResponse, Status>
+    where
+        F: FnOnce(&JobContext) -> Result<CreditProofResponse, Status> + Send + 'static,
+    {
+        let job = self.register(owner);
+        let _cancel_on_drop = CancelOnDrop {
+            jobs: self,
+            job_id: job.snapshot().job_id,
+        };
+        let mut updates = job.status.subscribe();
+        self.queue
+            .send((job.clone(), Box::new(task)))
+            .await
+            .map_err(|_| CreditError::Unavailable(JobError::Stopped.to_string()))?;
+
+        while !is_finished(&updates.borrow_and_update()) {
+            updates
+                .changed()
+                .await
+                .map_err(|_| CreditError::Unavailable(JobError::Stopped.to_string()))?;
+        }
+        let status = job.snapshot();
+        match status.result {
+            Some(result) if status.state == JobState::Succeeded as i32 => Ok(result),
+            _ => Err(job_failure(&status)),
+        }
+    }
+
+    /// Current status of a job
+    pub fn status(&self, owner: &str, job_id: &str) -> Result<JobStatus, JobError> {
+        Ok(self.owned_job(owner, job_id)?.snapshot())
+    }
+
+    /// Receiver notified whenever a job's status changes
+    pub fn subscribe(&self, owner: &str, job_id: &str) -> Result<watch::Receiver<JobStatus>, JobError> {
+        Ok(self.owned_job(owner, job_id)?.status.subscribe())
+    }
+
+    /// Cancels a job; a queued job never starts and a running job stops at its next step
+    pub fn cancel(&self, owner: &str, job_id: &str) -> Result<JobStatus, JobError> {
+        let job = self.owned_job(owner, job_id)?;
+        job.cancel();
+        Ok(job.snapshot())
+    }
+
+    fn owned_job(&self, owner: &str, job_id: &str) -> Result<Arc<Job>, JobError> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(job_id).ok_or(JobError::NotFound)?;
+        if job.owner != owner {
+            return Err(JobError::NotOwner);
+        }
+        Ok(job.clone())
+    }
+
+    fn register(&self, owner: &str) -> Arc<Job> {
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let job_id = hex::encode(id);
+        let (status, _) = watch::channel(JobStatus {
+            job_id: job_id.clone(),
+            state: JobState::Queued as i32,
+            stage: "Queued".to_string(),
+            completed_steps: 0,
+            total_steps: EVALUATION_STEPS,
+            ..Default::default()
+        });
+        let job = Arc::new(Job {
+            owner: owner.to_string(),
+            cancelled: AtomicBool::new(false),
+            finished_at: AtomicU64::new(0),
+            status,
+        });
+
+        let mut jobs = self.jobs.lock().unwrap();
+        let now = proof_registry::unix_now();
+        jobs.retain(|_, job| {
+            let finished_at = job.finished_at.load(Ordering::SeqCst);
+            finished_at == 0 || now < finished_at + JOB_RETENTION.as_secs()
+        });
+        jobs.insert(job_id, job.clone());
+        job
+    }
+}
+
+async fn run_worker(receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<(Arc<Job>, ProofTask)>>>) {
+    loop {
+        let next = receiver.lock().await.recv().await;
+        let (job, task) = match next {
+            Some(next) => next,
+            None => return,
+        };
+        // Cancelled while queued
+        if !job.start() {
+            continue;
+        }
+        let context = JobContext { job: job.clone() };
+        // FHE evaluation and proving block for seconds, so they stay off the async workers
+        let outcome = tokio::task::spawn_blocking(move || task(&context))
+            .await
+            .unwrap_or_else(|e| Err(CreditError::internal("run proof job", e).into()));
+        job.finish(outcome);
+    }
+}
+
+/// Submits an application as a proof job and follows its progress until it finishes
+///
+/// A failed job is returned as the status it failed with, error detail included.
+pub async fn submit_and_follow<F>(
+    client: &mut NbfcServiceClient<InterceptedService<Channel, BearerToken>>,
+    request: EncryptedFinancialRequest,
+    mut on_progress: F,
+) -> Result<CreditProofResponse, Status>
+where
+    F: FnMut(&JobStatus),
+{
+    let submitted = client.submit_credit_proof_job(request).await?.into_inner();
+    on_progress(&submitted);
+
+    let mut progress = client
+        .stream_job_progress(JobStatusRequest { job_id: submitted.job_id.clone() })
+        .await?
+        .into_inner();
+    let mut last = submitted;
+    while let Some(status) = progress.message().await? {
+        on_progress(&status);
+        last = status;
+    }
+    // The stream ends early if the connection drops, so confirm the final state
+    if !is_finished(&last) {
+        last = client
+            .get_job_status(JobStatusRequest { job_id: last.job_id.clone() })
+            .await?
+            .into_inner();
+    }
+
+    match last.result {
+        Some(result) if last.state == JobState::Succeeded as i32 => Ok(result),
+        _ if is_finished(&last) => Err(job_failure(&last)),
+        _ => Err(CreditError::Unavailable(format!(
+            "Lost track of proof job {} before it finished; check it with job status",
+            last.job_id
+        ))
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+    use crate::nbfc_service::credit_evaluation::ReasonCode;
+    use std::sync::mpsc as std_mpsc;
+
+    async fn wait_until_finished(jobs: &ProofJobs, job_id: &str) -> JobStatus {
+        let mut updates = jobs.subscribe("applicant", job_id).unwrap();
+        while !is_finished(&updates.borrow_and_update()) {
+            updates.changed().await.unwrap();
+        }
+        let status = updates.borrow().clone();
+        status
+    }
+
+    /// Submits a job that blocks its worker until the returned sender is used
+    async fn occupy_worker(jobs: &ProofJobs) -> std_mpsc::Sender<()> {
+        let (started_tx, started_rx) = std_mpsc::channel();
+        let (resume_tx, resume_rx) = std_mpsc::channel::<()>();
+        jobs.submit("applicant", move |_| {
+            started_tx.send(()).unwrap();
+            let _ = resume_rx.recv();
+            Ok(CreditProofResponse::default())
+        })
+        .unwrap();
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap()).await.unwrap();
+        resume_tx
+    }
+
+    #[tokio::test]
+    async fn cancelled_queued_job_never_starts_and_stays_cancelled() {
+        let jobs = ProofJobs::start(4, 1);
+        let resume = occupy_worker(&jobs).await;
+        let job_id = jobs.submit("applicant", |_| Ok(CreditProofResponse::default())).unwrap().job_id;
+
+        let status = jobs.cancel("applicant", &job_id).unwrap();
+        assert_eq!(status.state, JobState::Cancelled as i32);
+        let detail = error::error_detail(&job_failure(&status)).unwrap();
+        assert_eq!(detail.reason, ReasonCode::Cancelled as i32);
+
+        let job = jobs.owned_job("applicant", &job_id).unwrap();
+        assert!(!job.start());
+        job.finish(Ok(CreditProofResponse::default()));
+        assert_eq!(job.snapshot().state, JobState::Cancelled as i32);
+        assert!(job.snapshot().result.is_none());
+
+        // The worker skips the cancelled job when it reaches it
+        resume.send(()).unwrap();
+        let next = jobs.submit("applicant", |_| Ok(CreditProofResponse::default())).unwrap().job_id;
+        assert_eq!(wait_until_finished(&jobs, &next).await.state, JobState::Succeeded as i32);
+        assert_eq!(jobs.status("applicant", &job_id).unwrap().state, JobState::Cancelled as i32);
+    }
+
+    #[tokio::test]
+    async fn running_job_stops_at_its_next_step() {
+        let jobs = ProofJobs::start(4, 1);
+        let (started_tx, started_rx) = std_mpsc::channel();
+        let (resume_tx, resume_rx) = std_mpsc::channel::<()>();
+        let job_id = jobs
+            .submit("applicant", move |context| {
+                context.step(1, "First")?;
+                started_tx.send(()).unwrap();
+                resume_rx.recv().unwrap();
+                context.step(2, "Second")?;
+                Ok(CreditProofResponse::default())
+            })
+            .unwrap()
+            .job_id;
+
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap()).await.unwrap();
+        assert_eq!(jobs.cancel("applicant", &job_id).unwrap().state, JobState::Running as i32);
+        resume_tx.send(()).unwrap();
+
+        let status = wait_until_finished(&jobs, &job_id).await;
+        assert_eq!(status.state, JobState::Cancelled as i32);
+        assert_eq!(Code::from_i32(status.error_code), Code::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn failures_keep_their_error_detail() {
+        let jobs = ProofJobs::start(4, 1);
+        let error = jobs
+            .run("applicant", |_| Err(CreditError::ThresholdNotMet { threshold: 5000 }.into()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::FailedPrecondition);
+        let detail = error::error_detail(&error).unwrap();
+        assert_eq!(detail.reason, ReasonCode::ThresholdNotMet as i32);
+        assert_eq!(detail.metadata.get("threshold").map(String::as_str), Some("5000"));
+    }
+
+    #[tokio::test]
+    async fn successful_job_reports_its_result() {
+        let jobs = ProofJobs::start(4, 1);
+        let job_id = jobs.submit("applicant", |_| Ok(CreditProofResponse::default())).unwrap().job_id;
+        let status = wait_until_finished(&jobs, &job_id).await;
+        assert_eq!(status.state, JobState::Succeeded as i32);
+        assert_eq!(status.completed_steps, status.total_steps);
+
+        // Cancelling a finished job changes nothing
+        assert_eq!(jobs.cancel("applicant", &job_id).unwrap().state, JobState::Succeeded as i32);
+        assert_eq!(jobs.status("other", &job_id).unwrap_err(), JobError::NotOwner);
+    }
+
+    #[tokio::test]
+    async fn full_queue_refuses_jobs() {
+        let jobs = ProofJobs::start(1, 1);
+        let _resume = occupy_worker(&jobs).await;
+        jobs.submit("applicant", |_| Ok(CreditProofResponse::default())).unwrap();
+        assert_eq!(
+            jobs.submit("applicant", |_| Ok(CreditProofResponse::default())).unwrap_err(),
+            JobError::QueueFull
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use rand::RngCore;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::nbfc_service::credit_evaluation::SharedProofBundle;
+
+/// Lifetime of a bundle published without an explicit expiration
+pub const DEFAULT_BUNDLE_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Longest lifetime a bundle may be published with
+pub const MAX_BUNDLE_LIFETIME: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// Reasons the registry refuses a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    NotFound,
+    Expired,
+    Revoked,
+    NotConsented { requester: String },
+    NotOwner,
+    InvalidExpiration(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::NotFound => write!(f, "proof bundle not found"),
+            RegistryError::Expired => write!(f, "proof bundle has expired"),
+            RegistryError::Revoked => write!(f, "proof bundle has been revoked"),
+            RegistryError::NotConsented { requester } => {
+                write!(f, "applicant has not consented to sharing this bundle with {}", requester)
+            }
+            RegistryError::NotOwner => write!(f, "only the applicant who published a bundle can revoke it"),
+            RegistryError::InvalidExpiration(reason) => write!(f, "invalid expiration: {}", reason),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+/// A published bundle with its sharing state
+#[derive(Debug, Clone)]
+pub struct PublishedBundle {
+    pub bundle: SharedProofBundle,
+    pub consented_requesters: HashSet<String>,
+    pub revoked: bool,
+}
+
+impl PublishedBundle {
+    /// Whether the bundle's expiration has passed at `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.bundle.expiration_timestamp
+    }
+}
+
+/// In-memory registry of proof bundles applicants share with banks
+#[derive(Debug, Default)]
+pub struct ProofRegistry {
+    bundles: Mutex<HashMap<Vec<u8>, PublishedBundle>>,
+}
+
+impl ProofRegistry {
+    /// Stores a bundle under a fresh proof ID, returning the ID and expiration
+    ///
+    /// Revoked and expired bundles can never be fetched again, so each publish prunes them.
+    pub fn publish(
+        &self,
+        mut bundle: SharedProofBundle,
+        consented_requesters: HashSet<String>,
+    ) -> Result<(Vec<u8>, u64), RegistryError> {
+        let now = unix_now();
+        let latest = now + MAX_BUNDLE_LIFETIME.as_secs();
+        bundle.expiration_timestamp = match bundle.expiration_timestamp {
+            0 => now + DEFAULT_BUNDLE_LIFETIME.as_secs(),
+            t if t <= now => return Err(RegistryError::InvalidExpiration("already in the past".into())),
+            t if t > latest => {
+                return Err(RegistryError::InvalidExpiration(format!(
+                    "bundles may live at most {} days",
+                    MAX_BUNDLE_LIFETIME.as_secs() / (24 * 60 * 60)
+                )))
+            }
+            t => t,
+        };
+
+        let mut proof_id = vec![0u8; 16];
+        rand::thread_rng().fill_bytes(&mut proof_id);
+        bundle.proof_id = proof_id.clone();
+        let expiration = bundle.expiration_timestamp;
+
+        let mut bundles = self.bundles.lock().unwrap();
+        bundles.retain(|_, published| !published.revoked && !published.is_expired(now));
+        bundles.insert(
+            proof_id.clone(),
+            PublishedBundle {
+                bundle,
+                consented_requesters,
+                revoked: false,
+            },
+        );
+        Ok((proof_id, expiration))
+    }
+
+    /// Returns a live bundle to a requester the applicant consented to
+    pub fn fetch(&self, proof_id: &[u8], requester: &str) -> Result<SharedProofBundle, RegistryError> {
+        let bundles = self.bundles.lock().unwrap();
+        let published = bundles.get(proof_id).ok_or(RegistryError::NotFound)?;
+
+        if published.revoked {
+            return Err(RegistryError::Revoked);
+        }
+        if published.is_expired(unix_now()) {
+            return Err(RegistryError::Expired);
+        }
+        if !published.consented_requesters.contains(requester) {
+            return Err(RegistryError::NotConsented {
+                requester: requester.to_string(),
+            });
+        }
+        Ok(published.bundle.clone())
+    }
+
+    /// Revokes a bundle on behalf of the applicant who published it
+    pub fn revoke(&self, proof_id: &[u8], user_id_hash: &[u8]) -> Result<(), RegistryError> {
+        let mut bundles = self.bundles.lock().unwrap();
+        let published = bundles.get_mut(proof_id).ok_or(RegistryError::NotFound)?;
+
+        if published.bundle.user_id_hash != user_id_hash {
+            return Err(RegistryError::NotOwner);
+        }
+        published.revoked = true;
+        Ok(())
+    }
+
+    /// Returns every bundle an applicant has published, including revoked and expired ones not yet pruned
+    pub fn list(&self, user_id_hash: &[u8]) -> Vec<PublishedBundle> {
+        self.bundles
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|published| published.bundle.user_id_hash == user_id_hash)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(user: u8) -> SharedProofBundle {
+        SharedProofBundle {
+            user_id_hash: vec![user; 32],
+            ..Default::default()
+        }
+    }
+
+    fn publish(registry: &ProofRegistry, user: u8) -> Vec<u8> {
+        registry.publish(bundle(user), HashSet::from(["bank".to_string()])).unwrap().0
+    }
+
+    #[test]
+    fn consented_requester_fetches_a_live_bundle() {
+        let registry = ProofRegistry::default();
+        let proof_id = publish(&registry, 1);
+        assert_eq!(registry.fetch(&proof_id, "bank").unwrap().proof_id, proof_id);
+        assert_eq!(
+            registry.fetch(&proof_id, "other-bank"),
+            Err(RegistryError::NotConsented { requester: "other-bank".into() })
+        );
+        assert_eq!(registry.fetch(b"unknown", "bank"), Err(RegistryError::NotFound));
+    }
+
+    #[test]
+    fn revoked_bundle_cannot_be_fetched() {
+        let registry = ProofRegistry::default();
+        let proof_id = publish(&registry, 1);
+        assert_eq!(registry.revoke(&proof_id, &[2; 32]), Err(RegistryError::NotOwner));
+        registry.revoke(&proof_id, &[1; 32]).unwrap();
+        assert_eq!(registry.fetch(&proof_id, "bank"), Err(RegistryError::Revoked));
+    }
+
+    #[test]
+    fn publishing_prunes_revoked_and_expired_bundles() {
+        let registry = ProofRegistry::default();
+        let revoked = publish(&registry, 1);
+        registry.revoke(&revoked, &[1; 32]).unwrap();
+        let expired = publish(&registry, 1);
+        registry.bundles.lock().unwrap().get_mut(&expired).unwrap().bundle.expiration_timestamp = unix_now() - 1;
+        let live = publish(&registry, 1);
+
+        publish(&registry, 2);
+        let remaining: Vec<Vec<u8>> = registry.list(&[1; 32]).into_iter().map(|p| p.bundle.proof_id).collect();
+        assert_eq!(remaining, vec![live]);
+    }
+
+    #[test]
+    fn expiration_outside_the_allowed_lifetime_is_refused() {
+        let registry = ProofRegistry::default();
+        let past = SharedProofBundle { expiration_timestamp: 1, ..bundle(1) };
+        assert!(matches!(registry.publish(past, HashSet::new()), Err(RegistryError::InvalidExpiration(_))));
+        let far = SharedProofBundle {
+            expiration_timestamp: unix_now() + MAX_BUNDLE_LIFETIME.as_secs() + 60,
+            ..bundle(1)
+        };
+        assert!(matches!(registry.publish(far, HashSet::new()), Err(RegistryError::InvalidExpiration(_))));
+    }
+}
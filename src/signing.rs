@@ -0,0 +1,155 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::Rng;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::tls;
+
+/// Locations of the Ed25519 key pair a party signs statements with
+///
+/// The files sit beside the party's TLS files and hold the keys hex-encoded.
+#[derive(Debug, Clone)]
+pub struct SigningKeyPaths {
+    pub signing_key: PathBuf,
+    pub verifying_key: PathBuf,
+}
+
+impl SigningKeyPaths {
+    /// Paths of a party's key pair in a certificate directory
+    pub fn in_dir<P: AsRef<Path>>(dir: P, identity: &str) -> Self {
+        let dir = dir.as_ref();
+        Self {
+            signing_key: dir.join(format!("{}.sign.key", identity)),
+            verifying_key: dir.join(format!("{}.sign.pub", identity)),
+        }
+    }
+
+    /// Reads a party's paths from the environment, falling back to the certificate directory
+    ///
+    /// `CREDIT_<IDENTITY>_SIGNING_KEY` and `CREDIT_<IDENTITY>_VERIFYING_KEY` override single files,
+    /// e.g. `CREDIT_NBFC_VERIFYING_KEY` for the key the Bank checks NBFC attestations with.
+    pub fn from_env(identity: &str) -> Self {
+        let mut paths = Self::in_dir(tls::tls_dir(), identity);
+        let prefix = format!("CREDIT_{}", identity.to_uppercase().replace('-', "_"));
+        if let Ok(path) = std::env::var(format!("{}_SIGNING_KEY", prefix)) {
+            paths.signing_key = path.into();
+        }
+        if let Ok(path) = std::env::var(format!("{}_VERIFYING_KEY", prefix)) {
+            paths.verifying_key = path.into();
+        }
+        paths
+    }
+
+    /// Loads the party's private signing key
+    pub fn load_signing_key(&self) -> Result<SigningKey, Box<dyn Error>> {
+        Ok(SigningKey::from_bytes(&read_key_bytes(&self.signing_key)?))
+    }
+
+    /// Loads the party's public verifying key
+    pub fn load_verifying_key(&self) -> Result<VerifyingKey, Box<dyn Error>> {
+        Ok(VerifyingKey::from_bytes(&read_key_bytes(&self.verifying_key)?)?)
+    }
+}
+
+/// Generates a fresh key pair for each identity that does not have one yet
+pub fn ensure_signing_keys<P: AsRef<Path>>(dir: P, identities: &[&str]) -> Result<(), Box<dyn Error>> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    for identity in identities {
+        let paths = SigningKeyPaths::in_dir(dir, identity);
+        if paths.signing_key.exists() && paths.verifying_key.exists() {
+            continue;
+        }
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill(&mut secret);
+        let signing_key = SigningKey::from_bytes(&secret);
+        write_private_file(&paths.signing_key, hex::encode(signing_key.to_bytes()).as_bytes())?;
+        fs::write(&paths.verifying_key, hex::encode(signing_key.verifying_key().to_bytes()))?;
+    }
+    Ok(())
+}
+
+/// Signs a message, returning the 64-byte signature
+pub fn sign(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+    key.sign(message).to_bytes().to_vec()
+}
+
+/// Checks a signature over a message
+pub fn verify(key: &VerifyingKey, message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let signature = Signature::from_slice(signature).map_err(|_| "malformed signature".to_string())?;
+    key.verify_strict(message, &signature).map_err(|_| "invalid signature".to_string())
+}
+
+/// Writes a file only its owner can read
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+fn read_key_bytes(path: &Path) -> Result<[u8; 32], Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let bytes = hex::decode(text.trim())?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("{} does not hold a 32-byte key", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("credit-signing-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn generated_keys_sign_and_verify() {
+        let dir = temp_dir("roundtrip");
+        ensure_signing_keys(&dir, &["nbfc"]).unwrap();
+        let paths = SigningKeyPaths::in_dir(&dir, "nbfc");
+        let signature = sign(&paths.load_signing_key().unwrap(), b"statement");
+
+        let verifying_key = paths.load_verifying_key().unwrap();
+        assert!(verify(&verifying_key, b"statement", &signature).is_ok());
+        assert!(verify(&verifying_key, b"other statement", &signature).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn existing_keys_are_kept() {
+        let dir = temp_dir("keep");
+        ensure_signing_keys(&dir, &["nbfc"]).unwrap();
+        let paths = SigningKeyPaths::in_dir(&dir, "nbfc");
+        let before = fs::read(&paths.signing_key).unwrap();
+        ensure_signing_keys(&dir, &["nbfc", "bank"]).unwrap();
+
+        assert_eq!(fs::read(&paths.signing_key).unwrap(), before);
+        assert!(SigningKeyPaths::in_dir(&dir, "bank").verifying_key.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signing_key_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("mode");
+        ensure_signing_keys(&dir, &["nbfc"]).unwrap();
+        let mode = fs::metadata(SigningKeyPaths::in_dir(&dir, "nbfc").signing_key).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
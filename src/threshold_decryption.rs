@@ -0,0 +1,292 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tfhe::shortint::prelude::*;
+
+use crate::signing;
+
+/// Environment variable pointing at the NBFC's key share file
+pub const NBFC_KEY_SHARE_ENV: &str = "CREDIT_NBFC_KEY_SHARE";
+
+/// Environment variable pointing at the Bank's key share file
+pub const BANK_KEY_SHARE_ENV: &str = "CREDIT_BANK_KEY_SHARE";
+
+/// Parties a client key is split between by `keygen --split`
+pub const SPLIT_PARTIES: [&str; 2] = ["bank", "nbfc"];
+
+/// Bits of smudging noise each party adds to its partial decryption
+///
+/// Large enough to hide the party's key share, small enough to stay well below half
+/// the encoding step of every supported parameter set.
+const SMUDGING_NOISE_BITS: u32 = 40;
+
+/// One party's additive share of an FHE secret key
+///
+/// The secret key is split so that the shares sum to it modulo 2^64. Every share is
+/// needed to decrypt: any subset of parties learns nothing about the key.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyShare {
+    pub party: String,
+    pub index: usize,
+    pub share_count: usize,
+    big_key_share: Vec<u64>,
+    small_key_share: Vec<u64>,
+}
+
+/// A party's contribution towards decrypting one ciphertext
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialDecryption {
+    pub party: String,
+    pub index: usize,
+    pub share_count: usize,
+    value: u64,
+}
+
+/// Splits a client key into one additive share per party
+pub fn split_client_key(client_key: ClientKey, parties: &[&str]) -> Result<Vec<KeyShare>, Box<dyn Error>> {
+    if parties.len() < 2 {
+        return Err("Threshold decryption needs at least two parties".into());
+    }
+    let unique: HashSet<&&str> = parties.iter().collect();
+    if unique.len() != parties.len() {
+        return Err("Party names must be unique".into());
+    }
+
+    let (glwe_secret_key, lwe_secret_key, _parameters) = client_key.into_raw_parts();
+    let big_key: Vec<u64> = glwe_secret_key.as_lwe_secret_key().as_ref().to_vec();
+    let small_key: Vec<u64> = lwe_secret_key.as_ref().to_vec();
+
+    let big_shares = share_vector(&big_key, parties.len());
+    let small_shares = share_vector(&small_key, parties.len());
+
+    let shares = parties
+        .iter()
+        .zip(big_shares.into_iter().zip(small_shares))
+        .enumerate()
+        .map(|(index, (party, (big_key_share, small_key_share)))| KeyShare {
+            party: party.to_string(),
+            index,
+            share_count: parties.len(),
+            big_key_share,
+            small_key_share,
+        })
+        .collect();
+    Ok(shares)
+}
+
+// Key material is deliberately left out of debug output
+impl fmt::Debug for KeyShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("party", &self.party)
+            .field("index", &self.index)
+            .field("share_count", &self.share_count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyShare {
+    /// Loads a key share from a bincode file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyShare, Box<dyn Error>> {
+        let data = fs::read(path.as_ref())
+            .map_err(|e| format!("Failed to read key share {}: {}", path.as_ref().display(), e))?;
+        Ok(bincode::deserialize(&data)?)
+    }
+
+    /// Saves a key share to a bincode file only its owner can read
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        signing::write_private_file(path.as_ref(), &bincode::serialize(self)?)
+    }
+
+    /// Computes this party's partial decryption of a ciphertext
+    pub fn partial_decrypt(&self, ct: &Ciphertext) -> Result<PartialDecryption, Box<dyn Error>> {
+        let mask = ct.ct.get_mask();
+        let mask = mask.as_ref();
+
+        // Ciphertexts are encrypted under either the big (GLWE-derived) or the small LWE key
+        let key_share = if mask.len() == self.big_key_share.len() {
+            &self.big_key_share
+        } else if mask.len() == self.small_key_share.len() {
+            &self.small_key_share
+        } else {
+            return Err(format!(
+                "Ciphertext dimension {} does not match this key share",
+                mask.len()
+            ).into());
+        };
+
+        let inner_product = mask
+            .iter()
+            .zip(key_share)
+            .fold(0u64, |acc, (a, s)| acc.wrapping_add(a.wrapping_mul(*s)));
+
+        Ok(PartialDecryption {
+            party: self.party.clone(),
+            index: self.index,
+            share_count: self.share_count,
+            value: inner_product.wrapping_add(smudging_noise()),
+        })
+    }
+}
+
+/// Combines every party's partial decryption into the plaintext message
+pub fn combine_partial_decryptions(ct: &Ciphertext, partials: &[PartialDecryption]) -> Result<u64, Box<dyn Error>> {
+    let share_count = partials.first().ok_or("No partial decryptions supplied")?.share_count;
+    let indices: HashSet<usize> = partials.iter().map(|p| p.index).collect();
+    if partials.len() != share_count
+        || indices.len() != share_count
+        || partials.iter().any(|p| p.share_count != share_count || p.index >= share_count)
+    {
+        return Err(format!(
+            "Decryption needs exactly one partial decryption from each of {} parties, got {}",
+            share_count,
+            partials.len()
+        ).into());
+    }
+
+    let body = *ct.ct.get_body().data;
+    let phase = partials
+        .iter()
+        .fold(body, |acc, partial| acc.wrapping_sub(partial.value));
+
+    // Decode as shortint does: the top bit is padding, followed by carry and message
+    let message_modulus = ct.message_modulus.0 as u64;
+    let carry_modulus = ct.carry_modulus.0 as u64;
+    let delta = (1u64 << 63) / (message_modulus * carry_modulus);
+    let decoded = phase.wrapping_add(delta / 2) / delta;
+
+    Ok(decoded % message_modulus)
+}
+
+/// Runs the protocol locally: every party partially decrypts and the results are combined
+pub fn decrypt_with_all_parties(ct: &Ciphertext, shares: &[KeyShare]) -> Result<u64, Box<dyn Error>> {
+    let partials = shares
+        .iter()
+        .map(|share| share.partial_decrypt(ct))
+        .collect::<Result<Vec<_>, _>>()?;
+    combine_partial_decryptions(ct, &partials)
+}
+
+/// Radix blocks a party has released for joint decryption, forgotten after a retention window
+///
+/// A party contributes a partial decryption only for a block it released itself, so its share
+/// cannot be used to decrypt the applicant's inputs.
+#[derive(Debug, Default)]
+pub struct ReleasedBlocks {
+    released_at: HashMap<[u8; 32], u64>,
+}
+
+impl ReleasedBlocks {
+    /// Hash a released block is recorded under
+    pub fn block_hash(block: &[u8]) -> [u8; 32] {
+        Sha256::digest(block).into()
+    }
+
+    /// Records blocks released at Unix time `now`
+    pub fn release<'a>(&mut self, blocks: impl IntoIterator<Item = &'a Vec<u8>>, now: u64) {
+        for block in blocks {
+            self.released_at.insert(Self::block_hash(block), now);
+        }
+    }
+
+    /// Whether a block was released within `retention` of `now`, evicting older entries
+    pub fn contains(&mut self, block: &[u8], now: u64, retention: Duration) -> bool {
+        self.released_at
+            .retain(|_, released_at| now.saturating_sub(*released_at) <= retention.as_secs());
+        self.released_at.contains_key(&Self::block_hash(block))
+    }
+}
+
+/// Combines jointly decrypted little-endian radix digits into a value
+pub fn combine_radix_digits(digits: &[u64], message_modulus: u64) -> Result<u64, Box<dyn Error>> {
+    let mut value: u64 = 0;
+    for digit in digits.iter().rev() {
+        value = value
+            .checked_mul(message_modulus)
+            .and_then(|v| v.checked_add(*digit))
+            .ok_or("Decrypted value overflows u64")?;
+    }
+    Ok(value)
+}
+
+fn share_vector(secret: &[u64], parties: usize) -> Vec<Vec<u64>> {
+    let mut rng = rand::thread_rng();
+    let mut shares: Vec<Vec<u64>> = (0..parties - 1)
+        .map(|_| secret.iter().map(|_| rng.gen::<u64>()).collect())
+        .collect();
+
+    // The last share makes all shares sum to the secret modulo 2^64
+    let last = secret
+        .iter()
+        .enumerate()
+        .map(|(i, s)| shares.iter().fold(*s, |acc, share| acc.wrapping_sub(share[i])))
+        .collect();
+    shares.push(last);
+    shares
+}
+
+fn smudging_noise() -> u64 {
+    let bound = 1i64 << SMUDGING_NOISE_BITS;
+    rand::thread_rng().gen_range(-bound..bound) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe_params::FheParameterSet;
+
+    #[test]
+    fn split_key_decrypts_only_with_every_share() {
+        let client_key = ClientKey::new(FheParameterSet::Message2Carry2.parameters());
+        let ct = client_key.encrypt(3);
+        let shares = split_client_key(client_key, &SPLIT_PARTIES).unwrap();
+
+        assert_eq!(decrypt_with_all_parties(&ct, &shares).unwrap(), 3);
+        let bank_only = [shares[0].partial_decrypt(&ct).unwrap()];
+        assert!(combine_partial_decryptions(&ct, &bank_only).is_err());
+    }
+
+    #[test]
+    fn split_key_round_trips_radix_values() {
+        let client_key = ClientKey::new(FheParameterSet::Message2Carry2.parameters());
+        let blocks = crate::fhe_utils::encrypt_radix(2750, 32, &client_key).unwrap();
+        let shares = split_client_key(client_key, &SPLIT_PARTIES).unwrap();
+
+        let digits = blocks
+            .iter()
+            .map(|block| decrypt_with_all_parties(block, &shares))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(combine_radix_digits(&digits, 4).unwrap(), 2750);
+    }
+
+    #[test]
+    fn saved_share_loads_back() {
+        let client_key = ClientKey::new(FheParameterSet::Message2Carry2.parameters());
+        let ct = client_key.encrypt(2);
+        let shares = split_client_key(client_key, &SPLIT_PARTIES).unwrap();
+        let path = std::env::temp_dir().join(format!("credit-share-{}.share", std::process::id()));
+        shares[1].save(&path).unwrap();
+        let loaded = KeyShare::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(decrypt_with_all_parties(&ct, &[shares[0].clone(), loaded]).unwrap(), 2);
+    }
+
+    #[test]
+    fn released_blocks_expire() {
+        let mut released = ReleasedBlocks::default();
+        released.release(&[vec![1, 2, 3]], 100);
+        let retention = Duration::from_secs(60);
+
+        assert!(released.contains(&[1, 2, 3], 160, retention));
+        assert!(!released.contains(&[4], 160, retention));
+        assert!(!released.contains(&[1, 2, 3], 161, retention));
+    }
+}
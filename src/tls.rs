@@ -0,0 +1,290 @@
+use rcgen::{
+    BasicConstraints, Certificate as RcgenCertificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, KeyUsagePurpose,
+};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity, ServerTlsConfig};
+use tonic::{Request, Status};
+
+use crate::signing;
+
+/// Directory holding the CA certificate and every party's certificate and key
+pub const DEFAULT_TLS_DIR: &str = "certs";
+
+/// Environment variable overriding the certificate directory
+pub const TLS_DIR_ENV: &str = "CREDIT_TLS_DIR";
+
+/// Environment variable overriding the CA certificate path
+pub const TLS_CA_CERT_ENV: &str = "CREDIT_TLS_CA_CERT";
+
+/// Server name every service certificate is issued for
+pub const TLS_DOMAIN_NAME: &str = "localhost";
+
+// Certificate subjects of the parties in the system
+pub const APPLICANT_IDENTITY: &str = "applicant";
+pub const NBFC_IDENTITY: &str = "nbfc";
+pub const BANK_IDENTITY: &str = "bank";
+pub const KEY_HOLDER_IDENTITY: &str = "key-holder";
+pub const AUDITOR_IDENTITY: &str = "auditor";
+
+/// Parties the development CA issues certificates for
+pub const DEV_IDENTITIES: [&str; 5] = [
+    APPLICANT_IDENTITY,
+    NBFC_IDENTITY,
+    BANK_IDENTITY,
+    KEY_HOLDER_IDENTITY,
+    AUDITOR_IDENTITY,
+];
+
+const CA_CERT_FILE: &str = "ca.pem";
+const CA_KEY_FILE: &str = "ca.key";
+
+/// Locations of the PEM files one party presents and trusts
+#[derive(Debug, Clone)]
+pub struct TlsPaths {
+    pub ca_cert: PathBuf,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsPaths {
+    /// Paths of a party's files in a certificate directory laid out by `generate_dev_ca`
+    pub fn in_dir<P: AsRef<Path>>(dir: P, identity: &str) -> Self {
+        let dir = dir.as_ref();
+        Self {
+            ca_cert: dir.join(CA_CERT_FILE),
+            cert: dir.join(format!("{}.pem", identity)),
+            key: dir.join(format!("{}.key", identity)),
+        }
+    }
+
+    /// Reads a party's paths from the environment, falling back to the certificate directory
+    ///
+    /// `CREDIT_<IDENTITY>_TLS_CERT` and `CREDIT_<IDENTITY>_TLS_KEY` override single files,
+    /// e.g. `CREDIT_KEY_HOLDER_TLS_CERT` for the key holder.
+    pub fn from_env(identity: &str) -> Self {
+        let mut paths = Self::in_dir(tls_dir(), identity);
+        let prefix = format!("CREDIT_{}_TLS", identity.to_uppercase().replace('-', "_"));
+        if let Ok(path) = std::env::var(TLS_CA_CERT_ENV) {
+            paths.ca_cert = path.into();
+        }
+        if let Ok(path) = std::env::var(format!("{}_CERT", prefix)) {
+            paths.cert = path.into();
+        }
+        if let Ok(path) = std::env::var(format!("{}_KEY", prefix)) {
+            paths.key = path.into();
+        }
+        paths
+    }
+
+    fn load(&self) -> Result<(Certificate, Identity), Box<dyn Error>> {
+        let read = |path: &Path| fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e));
+        let ca_cert = Certificate::from_pem(read(&self.ca_cert)?);
+        let identity = Identity::from_pem(read(&self.cert)?, read(&self.key)?);
+        Ok((ca_cert, identity))
+    }
+}
+
+/// Certificate directory from the environment, or the default
+pub fn tls_dir() -> PathBuf {
+    std::env::var(TLS_DIR_ENV).unwrap_or_else(|_| DEFAULT_TLS_DIR.to_string()).into()
+}
+
+/// Server configuration that presents the party's certificate and requires a client certificate
+pub fn server_tls_config(paths: &TlsPaths) -> Result<ServerTlsConfig, Box<dyn Error>> {
+    let (ca_cert, identity) = paths.load()?;
+    Ok(ServerTlsConfig::new().identity(identity).client_ca_root(ca_cert))
+}
+
+/// Client configuration that presents the party's certificate and trusts only the CA
+pub fn client_tls_config(paths: &TlsPaths) -> Result<ClientTlsConfig, Box<dyn Error>> {
+    let (ca_cert, identity) = paths.load()?;
+    Ok(ClientTlsConfig::new()
+        .domain_name(TLS_DOMAIN_NAME)
+        .ca_certificate(ca_cert)
+        .identity(identity))
+}
+
+/// Opens a mutually authenticated channel to a service
+pub async fn connect(addr: &str, paths: &TlsPaths) -> Result<Channel, Box<dyn Error>> {
+    let channel = Channel::from_shared(addr.to_string())?
+        .tls_config(client_tls_config(paths)?)?
+        .connect()
+        .await?;
+    Ok(channel)
+}
+
+/// Identity of the caller, taken from the common name of its client certificate
+pub fn peer_identity<T>(request: &Request<T>) -> Result<String, Status> {
+    let certs = request
+        .peer_certs()
+        .ok_or_else(|| Status::unauthenticated("Missing client certificate"))?;
+    let cert = certs
+        .first()
+        .ok_or_else(|| Status::unauthenticated("Missing client certificate"))?;
+    common_name(cert.get_ref()).map_err(|e| Status::unauthenticated(format!("Invalid client certificate: {}", e)))
+}
+
+fn common_name(der: &[u8]) -> Result<String, Box<dyn Error>> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)?;
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .ok_or("certificate subject has no common name")?;
+    Ok(common_name.as_str()?.to_string())
+}
+
+/// Creates the development CA if there is none and issues certificates only to identities without one
+///
+/// Every certificate is valid for both server and client authentication so one set of
+/// files serves a party in either role. Not for production use.
+pub fn ensure_dev_ca<P: AsRef<Path>>(dir: P, identities: &[&str]) -> Result<(), Box<dyn Error>> {
+    let dir = dir.as_ref();
+    let missing: Vec<&str> = identities
+        .iter()
+        .copied()
+        .filter(|identity| {
+            let paths = TlsPaths::in_dir(dir, identity);
+            !(paths.cert.exists() && paths.key.exists())
+        })
+        .collect();
+    if !dir.join(CA_CERT_FILE).exists() {
+        return generate_dev_ca(dir, identities);
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let ca = load_dev_ca(dir)?;
+    for identity in missing {
+        issue_dev_cert(dir, &ca, identity)?;
+    }
+    Ok(())
+}
+
+/// Generates a fresh development CA and issues a certificate for each identity
+///
+/// Certificates issued by a previous CA in the directory stop validating.
+pub fn generate_dev_ca<P: AsRef<Path>>(dir: P, identities: &[&str]) -> Result<(), Box<dyn Error>> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    // Step 1: Self-sign the CA
+    let ca = RcgenCertificate::from_params(dev_ca_params())?;
+    signing::write_private_file(&dir.join(CA_KEY_FILE), ca.serialize_private_key_pem().as_bytes())?;
+    fs::write(dir.join(CA_CERT_FILE), ca.serialize_pem()?)?;
+
+    // Step 2: Issue each party a certificate whose subject names it
+    for identity in identities {
+        issue_dev_cert(dir, &ca, identity)?;
+    }
+
+    Ok(())
+}
+
+fn dev_ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, "privacy-preserving-credit dev CA");
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    params
+}
+
+/// Rebuilds the CA signer from its saved key, so new certificates chain to the existing `ca.pem`
+fn load_dev_ca(dir: &Path) -> Result<RcgenCertificate, Box<dyn Error>> {
+    let key_path = dir.join(CA_KEY_FILE);
+    let key_pem = fs::read_to_string(&key_path).map_err(|e| {
+        format!(
+            "Cannot issue certificates without the CA key {} ({}); run keygen --dev-ca to start a new CA",
+            key_path.display(),
+            e
+        )
+    })?;
+    let ca_pem = fs::read_to_string(dir.join(CA_CERT_FILE))?;
+    let params = CertificateParams::from_ca_cert_pem(&ca_pem, KeyPair::from_pem(&key_pem)?)?;
+    Ok(RcgenCertificate::from_params(params)?)
+}
+
+fn issue_dev_cert(dir: &Path, ca: &RcgenCertificate, identity: &str) -> Result<(), Box<dyn Error>> {
+    let mut params = CertificateParams::new(vec![TLS_DOMAIN_NAME.to_string()]);
+    params.distinguished_name.push(DnType::CommonName, identity);
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth, ExtendedKeyUsagePurpose::ClientAuth];
+    let cert = RcgenCertificate::from_params(params)?;
+
+    let paths = TlsPaths::in_dir(dir, identity);
+    signing::write_private_file(&paths.key, cert.serialize_private_key_pem().as_bytes())?;
+    fs::write(&paths.cert, cert.serialize_pem_with_signer(ca)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("credit-tls-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn cert_der(path: &Path) -> Vec<u8> {
+        let pem = fs::read_to_string(path).unwrap();
+        let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).unwrap();
+        pem.contents
+    }
+
+    #[test]
+    fn certificates_name_their_identity() {
+        let dir = temp_dir("names");
+        ensure_dev_ca(&dir, &[BANK_IDENTITY]).unwrap();
+        let der = cert_der(&TlsPaths::in_dir(&dir, BANK_IDENTITY).cert);
+        assert_eq!(common_name(&der).unwrap(), BANK_IDENTITY);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_identities_are_issued_under_the_existing_ca() {
+        let dir = temp_dir("extend");
+        ensure_dev_ca(&dir, &[BANK_IDENTITY]).unwrap();
+        let bank = TlsPaths::in_dir(&dir, BANK_IDENTITY);
+        let ca_before = fs::read(&bank.ca_cert).unwrap();
+        let bank_before = fs::read(&bank.cert).unwrap();
+
+        ensure_dev_ca(&dir, &[BANK_IDENTITY, NBFC_IDENTITY]).unwrap();
+        assert_eq!(fs::read(&bank.ca_cert).unwrap(), ca_before);
+        assert_eq!(fs::read(&bank.cert).unwrap(), bank_before);
+
+        let (_, ca) = x509_parser::parse_x509_certificate(&cert_der(&bank.ca_cert)).unwrap();
+        let nbfc_der = cert_der(&TlsPaths::in_dir(&dir, NBFC_IDENTITY).cert);
+        let (_, nbfc) = x509_parser::parse_x509_certificate(&nbfc_der).unwrap();
+        assert_eq!(nbfc.issuer(), ca.subject());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_ca_key_is_reported_instead_of_replacing_the_ca() {
+        let dir = temp_dir("no-ca-key");
+        ensure_dev_ca(&dir, &[BANK_IDENTITY]).unwrap();
+        fs::remove_file(dir.join(CA_KEY_FILE)).unwrap();
+        let ca_before = fs::read(dir.join(CA_CERT_FILE)).unwrap();
+
+        assert!(ensure_dev_ca(&dir, &[BANK_IDENTITY, NBFC_IDENTITY]).is_err());
+        assert_eq!(fs::read(dir.join(CA_CERT_FILE)).unwrap(), ca_before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_keys_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("mode");
+        ensure_dev_ca(&dir, &[BANK_IDENTITY]).unwrap();
+        for path in [dir.join(CA_KEY_FILE), TlsPaths::in_dir(&dir, BANK_IDENTITY).key] {
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600, "{}", path.display());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
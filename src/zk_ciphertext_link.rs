@@ -0,0 +1,715 @@
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::{
+    circuit_builder::CircuitBuilder,
+    circuit_data::{CircuitConfig, CircuitData},
+    config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
+    proof::ProofWithPublicInputs,
+};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use tfhe::core_crypto::prelude::{CiphertextModulus, LweCiphertextOwned};
+use tfhe::shortint::ciphertext::{Degree, NoiseLevel};
+use tfhe::shortint::prelude::*;
+use tfhe::shortint::{CompactPublicKey, PBSOrder};
+
+use crate::fhe_params::FheParameterSet;
+use crate::fhe_utils;
+use crate::zk_salary_circuit::{SalaryCommitment, BLINDING_ELEMENTS};
+
+// Define the configuration type
+type F = <PoseidonGoldilocksConfig as GenericConfig<2>>::F;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Independent random combinations checked per block; each lets a false mask through with probability 1/p
+const CHALLENGE_REPETITIONS: usize = 2;
+
+/// Noise is sampled within this many standard deviations, so the circuit can bound it
+const NOISE_TAIL_DEVIATIONS: f64 = 16.0;
+
+/// Poseidon commitment to the encryption randomness, prefixed to the proof
+type WitnessCommitment = [u64; 4];
+
+/// Randomness the applicant used to encrypt one salary block
+struct BlockRandomness {
+    digit: u64,
+    binary: Vec<bool>,
+    mask_noise: Vec<i64>,
+    body_noise: i64,
+}
+
+/// Salary blocks encrypted under the public key, with the randomness that proves what they encrypt
+pub struct LinkedSalaryEncryption {
+    pub blocks: Vec<Ciphertext>,
+    randomness: Vec<BlockRandomness>,
+}
+
+/// Compact public key as its mask and body polynomials
+struct PublicKeyPolynomials {
+    mask: Vec<u64>,
+    body: Vec<u64>,
+    digest: [u8; 32],
+}
+
+/// Shape of the encoding shared by all salary blocks
+struct BlockEncoding {
+    dimension: usize,
+    num_blocks: usize,
+    message_modulus: u64,
+    carry_modulus: u64,
+    message_bits: usize,
+    log_delta: u32,
+    /// Noise is offset by 2^noise_bits so the circuit only handles non-negative values
+    noise_bits: usize,
+    carry_bits: usize,
+}
+
+/// Witness values for one block, in the order they are committed
+struct BlockWitness {
+    digit: u64,
+    binary: Vec<bool>,
+    mask_noise: Vec<u64>,
+    mask_carries: Vec<(u64, u64)>,
+    body_noise: u64,
+    body_carries: (u64, u64),
+}
+
+/// Targets constraining one encrypted salary block
+struct BlockTargets {
+    digit: Target,
+    binary: Vec<BoolTarget>,
+    mask_noise: Vec<Target>,
+    mask_carries: Vec<(Target, Target)>,
+    body_noise: Target,
+    body_carries: (Target, Target),
+}
+
+/// Circuit proving that salary ciphertext blocks are public-key encryptions of the committed salary
+struct LinkCircuit {
+    data: CircuitData<F, C, D>,
+    salary: Target,
+    blinding: [Target; BLINDING_ELEMENTS],
+    blocks: Vec<BlockTargets>,
+}
+
+/// Encrypts a salary as radix blocks under the compact public key, keeping the randomness for the link proof
+///
+/// Each block is `mask = A * r + e1` and `body = <B, r> + e2 + delta * digit`, where `(A, B)`
+/// is the public key, `r` a fresh binary vector and `e1`, `e2` small noise, matching tfhe's
+/// compact public key encryption.
+pub fn encrypt_salary_for_link(
+    salary: u64,
+    public_key: &CompactPublicKey,
+    parameter_set: FheParameterSet,
+) -> Result<LinkedSalaryEncryption, String> {
+    let public_key = public_key_polynomials(public_key)?;
+    let encoding = block_encoding(parameter_set, public_key.mask.len())?;
+    let digits = fhe_utils::radix_digits(salary, fhe_utils::SALARY_BITS, parameter_set.message_bits())
+        .map_err(|e| e.to_string())?;
+
+    let mut rng = rand::thread_rng();
+    let mut blocks = Vec::with_capacity(digits.len());
+    let mut randomness = Vec::with_capacity(digits.len());
+    for digit in digits {
+        let block_randomness = BlockRandomness {
+            digit,
+            binary: (0..encoding.dimension).map(|_| rng.gen()).collect(),
+            mask_noise: (0..encoding.dimension).map(|_| sample_noise(&encoding, &mut rng)).collect(),
+            body_noise: sample_noise(&encoding, &mut rng),
+        };
+        blocks.push(encrypt_block(&block_randomness, &public_key, &encoding));
+        randomness.push(block_randomness);
+    }
+    Ok(LinkedSalaryEncryption { blocks, randomness })
+}
+
+/// Proves that the salary blocks are encryptions, under the public key, of the committed salary
+///
+/// The circuit checks every block against the randomness that produced it, using 32-bit
+/// limbs to stay inside the Goldilocks field. The `n` mask equations of a block are checked
+/// as random linear combinations, with challenges derived from a commitment to the
+/// randomness. The digits must recompose to the salary that opens the Poseidon commitment.
+/// No secret key is involved.
+pub fn prove_ciphertext_link(
+    encryption: &LinkedSalaryEncryption,
+    salary: u64,
+    blinding: &[u64; BLINDING_ELEMENTS],
+    public_key: &CompactPublicKey,
+    parameter_set: FheParameterSet,
+) -> Result<Vec<u8>, String> {
+    let public_key = public_key_polynomials(public_key)?;
+    let encoding = block_encoding(parameter_set, public_key.mask.len())?;
+    check_blocks(&encryption.blocks, &encoding)?;
+
+    let witnesses = encryption
+        .blocks
+        .iter()
+        .zip(&encryption.randomness)
+        .enumerate()
+        .map(|(j, (block, randomness))| block_witness(j, block, randomness, &public_key, &encoding))
+        .collect::<Result<Vec<_>, String>>()?;
+    let witness_commitment = commit_witnesses(&witnesses);
+
+    let circuit = build_link_circuit(&encryption.blocks, &public_key, &encoding, &witness_commitment)?;
+
+    let mut pw = PartialWitness::new();
+    pw.set_target(circuit.salary, F::from_canonical_u64(salary));
+    for (target, value) in circuit.blinding.iter().zip(blinding) {
+        pw.set_target(*target, F::from_canonical_u64(*value));
+    }
+    for (targets, witness) in circuit.blocks.iter().zip(&witnesses) {
+        pw.set_target(targets.digit, F::from_canonical_u64(witness.digit));
+        for (target, bit) in targets.binary.iter().zip(&witness.binary) {
+            pw.set_bool_target(*target, *bit);
+        }
+        for (target, noise) in targets.mask_noise.iter().zip(&witness.mask_noise) {
+            pw.set_target(*target, F::from_canonical_u64(*noise));
+        }
+        for ((low, high), (carry_low, carry_high)) in targets.mask_carries.iter().zip(&witness.mask_carries) {
+            pw.set_target(*low, F::from_canonical_u64(*carry_low));
+            pw.set_target(*high, F::from_canonical_u64(*carry_high));
+        }
+        pw.set_target(targets.body_noise, F::from_canonical_u64(witness.body_noise));
+        pw.set_target(targets.body_carries.0, F::from_canonical_u64(witness.body_carries.0));
+        pw.set_target(targets.body_carries.1, F::from_canonical_u64(witness.body_carries.1));
+    }
+
+    let proof = circuit.data.prove(pw).map_err(|e| format!("Proving error: {:?}", e))?;
+
+    let mut proof_bytes: Vec<u8> = witness_commitment.iter().flat_map(|e| e.to_le_bytes()).collect();
+    proof_bytes.extend(proof.to_bytes());
+    Ok(proof_bytes)
+}
+
+/// Verifies that the salary blocks are public-key encryptions of the committed salary
+pub fn verify_ciphertext_link(
+    salary_blocks: &[Ciphertext],
+    proof_bytes: &[u8],
+    salary_commitment: &SalaryCommitment,
+    public_key: &CompactPublicKey,
+    parameter_set: FheParameterSet,
+) -> Result<bool, String> {
+    let public_key = public_key_polynomials(public_key)?;
+    let encoding = block_encoding(parameter_set, public_key.mask.len())?;
+    check_blocks(salary_blocks, &encoding)?;
+
+    // The witness commitment comes first, since the circuit's challenges depend on it
+    if proof_bytes.len() < 32 {
+        return Err("Malformed proof: missing witness commitment".into());
+    }
+    let (commitment_bytes, proof_bytes) = proof_bytes.split_at(32);
+    let mut witness_commitment = [0u64; 4];
+    for (element, chunk) in witness_commitment.iter_mut().zip(commitment_bytes.chunks(8)) {
+        *element = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    let circuit = build_link_circuit(salary_blocks, &public_key, &encoding, &witness_commitment)?;
+    let proof = ProofWithPublicInputs::<F, C, D>::from_bytes(proof_bytes.to_vec(), &circuit.data.common)
+        .map_err(|e| format!("Malformed proof: {:?}", e))?;
+
+    // Public inputs are the salary commitment followed by the witness commitment
+    let mut expected = salary_commitment.to_vec();
+    expected.extend_from_slice(&witness_commitment);
+    let actual: Vec<u64> = proof.public_inputs.iter().map(|e| e.to_canonical_u64()).collect();
+    if actual != expected {
+        return Ok(false);
+    }
+
+    Ok(circuit.data.verify(proof).is_ok())
+}
+
+fn build_link_circuit(
+    salary_blocks: &[Ciphertext],
+    public_key: &PublicKeyPolynomials,
+    encoding: &BlockEncoding,
+    witness_commitment: &WitnessCommitment,
+) -> Result<LinkCircuit, String> {
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+
+    // Salary commitment
+    let salary = builder.add_virtual_target();
+    let blinding = [(); BLINDING_ELEMENTS].map(|_| builder.add_virtual_target());
+    let mut commitment_inputs = vec![salary];
+    commitment_inputs.extend_from_slice(&blinding);
+    let salary_commitment = builder.hash_n_to_hash_no_pad::<PoseidonHash>(commitment_inputs);
+    builder.register_public_inputs(&salary_commitment.elements);
+
+    let two_32 = F::from_canonical_u64(1 << 32);
+    let digit_scale = F::from_canonical_u64(1 << (encoding.log_delta - 32));
+    let noise_offset = 1u64 << encoding.noise_bits;
+
+    let mut blocks = Vec::with_capacity(salary_blocks.len());
+    let mut committed = Vec::new();
+    let mut recomposed = builder.zero();
+    for (j, ct) in salary_blocks.iter().enumerate() {
+        let targets = add_block_targets(&mut builder, encoding);
+        let mask_limbs: Vec<(Target, Target)> = targets
+            .mask_noise
+            .iter()
+            .map(|noise| noise_limbs(&mut builder, *noise, encoding))
+            .collect();
+        let mask = ct.ct.get_mask();
+        let mask = mask.as_ref();
+
+        // Mask: A * r + e1 = mask + k * 2^64, row by row, checked as random combinations of the rows
+        let challenges = link_challenges(&public_key.digest, j, ct, witness_commitment, encoding.dimension)?;
+        for row_challenges in &challenges {
+            let (low_weights, high_weights) = column_weights(&public_key.mask, row_challenges);
+
+            let mut low = builder.zero();
+            let mut high = builder.zero();
+            for ((bit, low_weight), high_weight) in targets.binary.iter().zip(&low_weights).zip(&high_weights) {
+                low = builder.mul_const_add(*low_weight, bit.target, low);
+                high = builder.mul_const_add(*high_weight, bit.target, high);
+            }
+
+            let (mut low_expected, mut high_expected) = (F::ZERO, F::ZERO);
+            for (i, challenge) in row_challenges.iter().enumerate() {
+                let (noise_low, noise_high) = mask_limbs[i];
+                let (carry_low, carry_high) = targets.mask_carries[i];
+                // Low limb: (A * r)_lo + e1_lo = target_lo + carry_low * 2^32
+                low = builder.mul_const_add(*challenge, noise_low, low);
+                low = builder.mul_const_add(-(*challenge * two_32), carry_low, low);
+                // High limb: (A * r)_hi + e1_hi + carry_low = target_hi + carry_high * 2^32
+                high = builder.mul_const_add(*challenge, noise_high, high);
+                high = builder.mul_const_add(*challenge, carry_low, high);
+                high = builder.mul_const_add(-(*challenge * two_32), carry_high, high);
+
+                let target = mask[i].wrapping_add(noise_offset);
+                low_expected += *challenge * F::from_canonical_u64(target & 0xffff_ffff);
+                high_expected += *challenge * F::from_canonical_u64(target >> 32);
+            }
+            let low_expected = builder.constant(low_expected);
+            builder.connect(low, low_expected);
+            let high_expected = builder.constant(high_expected);
+            builder.connect(high, high_expected);
+        }
+
+        // Body: <B, r> + e2 + delta * digit = body + k * 2^64, checked exactly
+        let body = (*ct.ct.get_body().data).wrapping_add(noise_offset);
+        let (noise_low, noise_high) = noise_limbs(&mut builder, targets.body_noise, encoding);
+        let (carry_low, carry_high) = targets.body_carries;
+        let mut low = noise_low;
+        let mut high = builder.add(noise_high, carry_low);
+        for (bit, coefficient) in targets.binary.iter().zip(&public_key.body) {
+            low = builder.mul_const_add(F::from_canonical_u64(coefficient & 0xffff_ffff), bit.target, low);
+            high = builder.mul_const_add(F::from_canonical_u64(coefficient >> 32), bit.target, high);
+        }
+        let body_low = builder.constant(F::from_canonical_u64(body & 0xffff_ffff));
+        let rhs = builder.mul_const_add(two_32, carry_low, body_low);
+        builder.connect(low, rhs);
+        let high = builder.mul_const_add(digit_scale, targets.digit, high);
+        let body_high = builder.constant(F::from_canonical_u64(body >> 32));
+        let rhs = builder.mul_const_add(two_32, carry_high, body_high);
+        builder.connect(high, rhs);
+
+        let place = F::from_canonical_u64(encoding.message_modulus.pow(j as u32));
+        recomposed = builder.mul_const_add(place, targets.digit, recomposed);
+
+        // Commit to the randomness in the same order as commit_witnesses
+        committed.push(targets.digit);
+        for chunk in targets.binary.chunks(32) {
+            committed.push(builder.le_sum(chunk.iter()));
+        }
+        committed.extend_from_slice(&targets.mask_noise);
+        committed.extend(targets.mask_carries.iter().map(|(low, _)| *low));
+        committed.extend(targets.mask_carries.iter().map(|(_, high)| *high));
+        committed.extend([targets.body_noise, targets.body_carries.0, targets.body_carries.1]);
+        blocks.push(targets);
+    }
+
+    // The digits must spell out the committed salary
+    builder.connect(recomposed, salary);
+
+    let witness_hash = builder.hash_n_to_hash_no_pad::<PoseidonHash>(committed);
+    builder.register_public_inputs(&witness_hash.elements);
+
+    Ok(LinkCircuit {
+        data: builder.build::<C>(),
+        salary,
+        blinding,
+        blocks,
+    })
+}
+
+fn add_block_targets(builder: &mut CircuitBuilder<F, D>, encoding: &BlockEncoding) -> BlockTargets {
+    let carry = |builder: &mut CircuitBuilder<F, D>| {
+        let target = builder.add_virtual_target();
+        builder.range_check(target, encoding.carry_bits);
+        target
+    };
+
+    let digit = builder.add_virtual_target();
+    builder.range_check(digit, encoding.message_bits);
+    let binary = (0..encoding.dimension)
+        .map(|_| builder.add_virtual_bool_target_safe())
+        .collect();
+    let mask_noise = (0..encoding.dimension).map(|_| builder.add_virtual_target()).collect();
+    let mask_carries = (0..encoding.dimension)
+        .map(|_| (carry(builder), carry(builder)))
+        .collect();
+    let body_noise = builder.add_virtual_target();
+    let body_carries = (carry(builder), carry(builder));
+
+    BlockTargets {
+        digit,
+        binary,
+        mask_noise,
+        mask_carries,
+        body_noise,
+        body_carries,
+    }
+}
+
+/// Range checks offset noise and splits it into 32-bit limbs
+fn noise_limbs(builder: &mut CircuitBuilder<F, D>, noise: Target, encoding: &BlockEncoding) -> (Target, Target) {
+    let bits = builder.split_le(noise, encoding.noise_bits + 1);
+    let low_bits = bits.len().min(32);
+    let low = builder.le_sum(bits[..low_bits].iter());
+    let high = if bits.len() > 32 {
+        builder.le_sum(bits[32..].iter())
+    } else {
+        builder.zero()
+    };
+    (low, high)
+}
+
+/// Sums each column of the mask's linear map, weighted by the row challenges, per 32-bit limb
+fn column_weights(public_key_mask: &[u64], row_challenges: &[F]) -> (Vec<F>, Vec<F>) {
+    let n = public_key_mask.len();
+    let mut low = vec![F::ZERO; n];
+    let mut high = vec![F::ZERO; n];
+    for (i, challenge) in row_challenges.iter().enumerate() {
+        for j in 0..n {
+            let coefficient = mask_coefficient(public_key_mask, i, j);
+            low[j] += *challenge * F::from_canonical_u64(coefficient & 0xffff_ffff);
+            high[j] += *challenge * F::from_canonical_u64(coefficient >> 32);
+        }
+    }
+    (low, high)
+}
+
+/// Coefficient of `r_j` in row `i` of the mask `A * r`
+///
+/// The product is tfhe's semi-reversed negacyclic convolution: row `i` is
+/// `sum_k A[i - k] * r[n - 1 - k]`, negated where `i - k` wraps around.
+fn mask_coefficient(public_key_mask: &[u64], i: usize, j: usize) -> u64 {
+    let n = public_key_mask.len();
+    if i + j >= n - 1 {
+        public_key_mask[i + j + 1 - n]
+    } else {
+        public_key_mask[i + j + 1].wrapping_neg()
+    }
+}
+
+fn encrypt_block(randomness: &BlockRandomness, public_key: &PublicKeyPolynomials, encoding: &BlockEncoding) -> Ciphertext {
+    let n = encoding.dimension;
+    let mut container = Vec::with_capacity(n + 1);
+    for i in 0..n {
+        let product = (0..n)
+            .filter(|j| randomness.binary[*j])
+            .fold(0u64, |acc, j| acc.wrapping_add(mask_coefficient(&public_key.mask, i, j)));
+        container.push(product.wrapping_add(randomness.mask_noise[i] as u64));
+    }
+    let body = public_key
+        .body
+        .iter()
+        .zip(&randomness.binary)
+        .filter(|(_, bit)| **bit)
+        .fold(0u64, |acc, (coefficient, _)| acc.wrapping_add(*coefficient))
+        .wrapping_add(randomness.body_noise as u64)
+        .wrapping_add(randomness.digit << encoding.log_delta);
+    container.push(body);
+
+    Ciphertext::new(
+        LweCiphertextOwned::from_container(container, CiphertextModulus::new_native()),
+        Degree::new(encoding.message_modulus as usize - 1),
+        NoiseLevel::NOMINAL,
+        MessageModulus(encoding.message_modulus as usize),
+        CarryModulus(encoding.carry_modulus as usize),
+        PBSOrder::KeyswitchBootstrap,
+    )
+}
+
+/// Recomputes a block from its randomness and derives the carries the circuit needs
+fn block_witness(
+    index: usize,
+    block: &Ciphertext,
+    randomness: &BlockRandomness,
+    public_key: &PublicKeyPolynomials,
+    encoding: &BlockEncoding,
+) -> Result<BlockWitness, String> {
+    let mismatch = || format!("Salary block {} does not match its encryption randomness", index);
+    let offset = 1u64 << encoding.noise_bits;
+    let n = encoding.dimension;
+    let mask = block.ct.get_mask();
+
+    let mut mask_noise = Vec::with_capacity(n);
+    let mut mask_carries = Vec::with_capacity(n);
+    for (i, target) in mask.as_ref().iter().enumerate() {
+        let (low_sum, high_sum) = (0..n)
+            .filter(|j| randomness.binary[*j])
+            .map(|j| mask_coefficient(&public_key.mask, i, j))
+            .fold((0u64, 0u64), |(low, high), a| (low + (a & 0xffff_ffff), high + (a >> 32)));
+        let noise = (randomness.mask_noise[i] as u64).wrapping_add(offset);
+        let carries = limb_carries(low_sum, high_sum, noise, target.wrapping_add(offset)).ok_or_else(mismatch)?;
+        mask_noise.push(noise);
+        mask_carries.push(carries);
+    }
+
+    let (low_sum, high_sum) = public_key
+        .body
+        .iter()
+        .zip(&randomness.binary)
+        .filter(|(_, bit)| **bit)
+        .fold((0u64, 0u64), |(low, high), (b, _)| (low + (b & 0xffff_ffff), high + (b >> 32)));
+    let body_noise = (randomness.body_noise as u64).wrapping_add(offset);
+    let high_sum = high_sum + (randomness.digit << (encoding.log_delta - 32));
+    let body = (*block.ct.get_body().data).wrapping_add(offset);
+    let body_carries = limb_carries(low_sum, high_sum, body_noise, body).ok_or_else(mismatch)?;
+
+    Ok(BlockWitness {
+        digit: randomness.digit,
+        binary: randomness.binary.clone(),
+        mask_noise,
+        mask_carries,
+        body_noise,
+        body_carries,
+    })
+}
+
+/// Carries out of the low and high limbs of `sum + noise = target (mod 2^64)`
+fn limb_carries(low_sum: u64, high_sum: u64, noise: u64, target: u64) -> Option<(u64, u64)> {
+    let low_total = low_sum + (noise & 0xffff_ffff);
+    let low_carry = low_total.checked_sub(target & 0xffff_ffff)?;
+    if low_carry & 0xffff_ffff != 0 {
+        return None;
+    }
+    let low_carry = low_carry >> 32;
+
+    let high_total = high_sum + (noise >> 32) + low_carry;
+    let high_carry = high_total.checked_sub(target >> 32)?;
+    if high_carry & 0xffff_ffff != 0 {
+        return None;
+    }
+    Some((low_carry, high_carry >> 32))
+}
+
+/// Poseidon commitment to every block's randomness, in the order the circuit hashes it
+fn commit_witnesses(witnesses: &[BlockWitness]) -> WitnessCommitment {
+    let mut elements = Vec::new();
+    for witness in witnesses {
+        elements.push(F::from_canonical_u64(witness.digit));
+        for chunk in witness.binary.chunks(32) {
+            let packed = chunk.iter().enumerate().fold(0u64, |acc, (i, bit)| acc | (*bit as u64) << i);
+            elements.push(F::from_canonical_u64(packed));
+        }
+        elements.extend(witness.mask_noise.iter().map(|noise| F::from_canonical_u64(*noise)));
+        elements.extend(witness.mask_carries.iter().map(|(low, _)| F::from_canonical_u64(*low)));
+        elements.extend(witness.mask_carries.iter().map(|(_, high)| F::from_canonical_u64(*high)));
+        elements.extend([witness.body_noise, witness.body_carries.0, witness.body_carries.1].map(F::from_canonical_u64));
+    }
+    PoseidonHash::hash_no_pad(&elements).elements.map(|e| e.to_canonical_u64())
+}
+
+/// Derives a block's row challenges from the public key, the block and the witness commitment
+fn link_challenges(
+    public_key_digest: &[u8; 32],
+    index: usize,
+    block: &Ciphertext,
+    witness_commitment: &WitnessCommitment,
+    dimension: usize,
+) -> Result<Vec<Vec<F>>, String> {
+    let block_bytes = bincode::serialize(block).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    hasher.update(b"ciphertext-link");
+    hasher.update(public_key_digest);
+    hasher.update((index as u64).to_le_bytes());
+    hasher.update(&block_bytes);
+    for element in witness_commitment {
+        hasher.update(element.to_le_bytes());
+    }
+    let seed = hasher.finalize();
+
+    Ok((0..CHALLENGE_REPETITIONS)
+        .map(|repetition| {
+            (0..dimension)
+                .map(|row| {
+                    let digest = Sha256::new()
+                        .chain_update(seed)
+                        .chain_update((repetition as u32).to_le_bytes())
+                        .chain_update((row as u32).to_le_bytes())
+                        .finalize();
+                    F::from_noncanonical_u64(u64::from_le_bytes(digest[..8].try_into().unwrap()))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+fn public_key_polynomials(public_key: &CompactPublicKey) -> Result<PublicKeyPolynomials, String> {
+    let digest = Sha256::digest(bincode::serialize(public_key).map_err(|e| e.to_string())?).into();
+    let (key, _parameters) = public_key.clone().into_raw_parts();
+    let (mask, body) = key.get_mask_and_body();
+    Ok(PublicKeyPolynomials {
+        mask: mask.as_ref().to_vec(),
+        body: body.as_ref().to_vec(),
+        digest,
+    })
+}
+
+fn block_encoding(parameter_set: FheParameterSet, dimension: usize) -> Result<BlockEncoding, String> {
+    if !parameter_set.supports_compact_public_key() {
+        return Err(format!("Parameter set {} has no compact public key", parameter_set));
+    }
+    let message_bits = parameter_set.message_bits();
+    let log_delta = 63 - (message_bits + parameter_set.carry_bits());
+    if log_delta < 32 {
+        return Err("Parameter set leaves too little noise room for the link circuit".into());
+    }
+
+    let standard_deviation = parameter_set.parameters().glwe_modular_std_dev().0 * 2f64.powi(64);
+    let noise_bits = (standard_deviation * NOISE_TAIL_DEVIATIONS).log2().ceil().max(1.0) as usize;
+    if noise_bits + 1 >= 63 {
+        return Err("Parameter set noise is too wide for the link circuit".into());
+    }
+
+    Ok(BlockEncoding {
+        dimension,
+        num_blocks: fhe_utils::SALARY_BITS.div_ceil(message_bits) as usize,
+        message_modulus: 1 << message_bits,
+        carry_modulus: 1 << parameter_set.carry_bits(),
+        message_bits: message_bits as usize,
+        log_delta,
+        noise_bits,
+        // Carries are at most dimension + 2
+        carry_bits: (usize::BITS - (dimension + 2).leading_zeros()) as usize,
+    })
+}
+
+fn check_blocks(salary_blocks: &[Ciphertext], encoding: &BlockEncoding) -> Result<(), String> {
+    if salary_blocks.len() != encoding.num_blocks {
+        return Err(format!("Expected {} salary blocks, got {}", encoding.num_blocks, salary_blocks.len()));
+    }
+    for block in salary_blocks {
+        if block.ct.get_mask().as_ref().len() != encoding.dimension {
+            return Err("Salary blocks are not encrypted under the public key's dimension".into());
+        }
+        if block.message_modulus.0 as u64 != encoding.message_modulus {
+            return Err("Salary blocks use a different message modulus".into());
+        }
+    }
+    Ok(())
+}
+
+/// Samples rounded Gaussian noise, resampling the rare values outside the tail bound
+fn sample_noise(encoding: &BlockEncoding, rng: &mut impl Rng) -> i64 {
+    let bound = (1i64 << encoding.noise_bits) - 1;
+    let standard_deviation = 2f64.powi(encoding.noise_bits as i32) / NOISE_TAIL_DEVIATIONS;
+    loop {
+        // Box-Muller transform
+        let (u1, u2): (f64, f64) = (rng.gen_range(f64::EPSILON..1.0), rng.gen());
+        let sample = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+        let noise = (sample * standard_deviation).round() as i64;
+        if noise.abs() <= bound {
+            return noise;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zk_salary_circuit;
+    use std::sync::OnceLock;
+
+    const SALARY: u64 = 6000;
+
+    struct Fixture {
+        client_key: ClientKey,
+        public_key: CompactPublicKey,
+        encryption: LinkedSalaryEncryption,
+        commitment: SalaryCommitment,
+        proof: Vec<u8>,
+    }
+
+    fn fixture() -> &'static Fixture {
+        static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+        FIXTURE.get_or_init(|| {
+            let parameter_set = FheParameterSet::Message2Carry2CompactPk;
+            let (client_key, _, public_key) = fhe_utils::generate_keys_with_public_key_for(parameter_set).unwrap();
+            let encryption = encrypt_salary_for_link(SALARY, &public_key, parameter_set).unwrap();
+            let blinding = zk_salary_circuit::random_blinding();
+            let proof = prove_ciphertext_link(&encryption, SALARY, &blinding, &public_key, parameter_set).unwrap();
+            Fixture {
+                client_key,
+                public_key,
+                encryption,
+                commitment: zk_salary_circuit::commit_salary(SALARY, &blinding),
+                proof,
+            }
+        })
+    }
+
+    #[test]
+    fn linked_encryption_decrypts_to_salary() {
+        let fixture = fixture();
+        assert_eq!(fhe_utils::decrypt_radix(&fixture.encryption.blocks, &fixture.client_key).unwrap(), SALARY);
+    }
+
+    #[test]
+    fn link_proof_verifies_against_public_key() {
+        let fixture = fixture();
+        assert!(verify_ciphertext_link(
+            &fixture.encryption.blocks,
+            &fixture.proof,
+            &fixture.commitment,
+            &fixture.public_key,
+            FheParameterSet::Message2Carry2CompactPk,
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn link_proof_rejects_other_salary_commitment() {
+        let fixture = fixture();
+        let other = zk_salary_circuit::commit_salary(SALARY + 1, &zk_salary_circuit::random_blinding());
+        assert!(!verify_ciphertext_link(
+            &fixture.encryption.blocks,
+            &fixture.proof,
+            &other,
+            &fixture.public_key,
+            FheParameterSet::Message2Carry2CompactPk,
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn link_proof_rejects_swapped_blocks() {
+        let fixture = fixture();
+        let mut blocks = fixture.encryption.blocks.clone();
+        blocks.swap(0, 1);
+        let verified = verify_ciphertext_link(
+            &blocks,
+            &fixture.proof,
+            &fixture.commitment,
+            &fixture.public_key,
+            FheParameterSet::Message2Carry2CompactPk,
+        );
+        // The blocks are constants of the circuit, so the proof no longer verifies against it
+        assert_eq!(verified, Ok(false));
+    }
+
+    #[test]
+    fn limb_carries_match_wrapping_sum() {
+        let (sum, noise) = (u64::MAX - 5, 1u64 << 40);
+        let target = sum.wrapping_add(noise);
+        let (low, high) = limb_carries(sum & 0xffff_ffff, sum >> 32, noise, target).unwrap();
+        assert_eq!((low, high), (0, 1));
+        assert!(limb_carries(sum & 0xffff_ffff, sum >> 32, noise, target + 1).is_none());
+    }
+}
@@ -1,110 +1,276 @@
-use plonky2::field::types::Field;
-use plonky2::iop::witness::{PartialWitness, WitnessWrite};
-use plonky2::plonk::{
-    circuit_builder::CircuitBuilder,
-    circuit_data::CircuitConfig,
-    config::{GenericConfig, PoseidonGoldilocksConfig},
-};
-use plonky2::iop::target::Target;
-use plonky2::iop::target::BoolTarget;
-use std::time::Instant;
-
-// Define the configuration type
-type F = <PoseidonGoldilocksConfig as GenericConfig<2>>::F;
-type C = PoseidonGoldilocksConfig;
-const D: usize = 2;
-
-/// Creates a ZK circuit that proves the salary is greater than the threshold
-pub fn create_salary_threshold_circuit() -> (
-    CircuitBuilder<F, D>,
-    Target, // salary_target
-    Target, // threshold_target
-    BoolTarget, // result_target (true if salary > threshold)
-) {
-    // Create a new circuit with default configuration
-    let config = CircuitConfig::standard_recursion_config();
-    let mut builder = CircuitBuilder::<F, D>::new(config);
-
-    // Create targets for inputs
-    let salary_target = builder.add_virtual_target();
-    let threshold_target = builder.add_virtual_target();
-
-    // Add a range check for salary (to ensure it's a valid positive number)
-    let salary_bits = builder.split_le(salary_target, 64);
-    
-    // Add a range check for threshold
-    let threshold_bits = builder.split_le(threshold_target, 64);
-    
-    // Create the salary > threshold check by comparing the bits
-    // Start with most significant bit (MSB) and work downwards
-    let mut is_greater = builder.constant_bool(false);
-    let mut is_equal_so_far = builder.constant_bool(true);
-    
-    // Compare bits from most significant to least significant
-    for i in (0..64).rev() {
-        let s_bit = salary_bits[i];
-        let t_bit = threshold_bits[i];
-        
-        // salary_bit > threshold_bit at this position
-        // equivalent to s_bit AND (NOT t_bit)
-        let not_t_bit = builder.not(t_bit);
-        let bit_greater = builder.and(s_bit, not_t_bit);
-        
-        // salary_bit == threshold_bit at this position
-        let bit_equal = builder.is_equal(s_bit.target, t_bit.target);
-        
-        // is_greater = is_greater || (is_equal_so_far && bit_greater)
-        let new_greater = builder.and(is_equal_so_far, bit_greater);
-        is_greater = builder.or(is_greater, new_greater);
-        
-        // Update is_equal_so_far for next iteration
-        is_equal_so_far = builder.and(is_equal_so_far, bit_equal);
-    }
-    
-    // Constrain the result to be true (salary > threshold)
-    builder.assert_one(is_greater.target);
-    
-    (builder, salary_target, threshold_target, is_greater)
-}
-
-/// Generates a ZK proof that salary > threshold
-pub fn generate_salary_threshold_proof(
-    salary: u64,
-    threshold: u64,
-) -> Result<bool, String> {
-    // Only proceed if salary > threshold (otherwise we can't create a valid proof)
-    if salary <= threshold {
-        return Ok(false);
-    }
-
-    // Create the circuit
-    let (mut builder, salary_target, threshold_target, result_target) = create_salary_threshold_circuit();
-    
-    // Build the circuit
-    let start = Instant::now();
-    let circuit_data = builder.build::<C>();
-    println!("Circuit built in {:?}", start.elapsed());
-    
-    // Create a partial witness
-    let mut pw = PartialWitness::new();
-    
-    // Set the private witness (salary)
-    pw.set_target(salary_target, F::from_canonical_u64(salary));
-    
-    // Set the public input (threshold)
-    pw.set_target(threshold_target, F::from_canonical_u64(threshold));
-    
-    // Generate the proof
-    let start = Instant::now();
-    let proof = circuit_data.prove(pw).map_err(|e| format!("Proving error: {:?}", e))?;
-    println!("Proof generated in {:?}", start.elapsed());
-    
-    // Verify the proof
-    let start = Instant::now();
-    circuit_data
-        .verify(proof)
-        .map_err(|e| format!("Verification error: {:?}", e))?;
-    println!("Proof verified in {:?}", start.elapsed());
-    
-    Ok(true)
-}
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::{
+    circuit_builder::CircuitBuilder,
+    circuit_data::{CircuitConfig, CircuitData},
+    config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
+    proof::ProofWithPublicInputs,
+};
+use plonky2::iop::target::Target;
+use plonky2::iop::target::BoolTarget;
+use rand::Rng;
+use std::time::Instant;
+
+// Define the configuration type
+type F = <PoseidonGoldilocksConfig as GenericConfig<2>>::F;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Number of random field elements hiding the salary in its commitment
+pub const BLINDING_ELEMENTS: usize = 4;
+
+/// Poseidon commitment to a salary, as four canonical field elements
+pub type SalaryCommitment = [u64; 4];
+
+/// Length of the nonce a threshold proof is bound to
+pub const NONCE_BYTES: usize = 32;
+
+/// The nonce is exposed as little-endian 32-bit limbs, each of which fits a field element
+const NONCE_LIMBS: usize = NONCE_BYTES / 4;
+
+/// Fresh per-application nonce that a threshold proof is bound to
+pub type ProofNonce = [u8; NONCE_BYTES];
+
+/// Generates fresh blinding for a salary commitment
+pub fn random_blinding() -> [u64; BLINDING_ELEMENTS] {
+    let mut rng = rand::thread_rng();
+    [(); BLINDING_ELEMENTS].map(|_| rng.gen_range(0..F::ORDER))
+}
+
+/// Computes the Poseidon commitment to a salary under the given blinding
+pub fn commit_salary(salary: u64, blinding: &[u64; BLINDING_ELEMENTS]) -> SalaryCommitment {
+    let mut inputs = vec![F::from_canonical_u64(salary)];
+    inputs.extend(blinding.iter().map(|b| F::from_canonical_u64(*b)));
+    PoseidonHash::hash_no_pad(&inputs).elements.map(|e| e.to_canonical_u64())
+}
+
+/// Encodes a commitment as 32 little-endian bytes
+pub fn commitment_to_bytes(commitment: &SalaryCommitment) -> Vec<u8> {
+    commitment.iter().flat_map(|e| e.to_le_bytes()).collect()
+}
+
+/// Draws a fresh random proof nonce
+pub fn generate_nonce() -> ProofNonce {
+    let mut nonce = [0u8; NONCE_BYTES];
+    rand::thread_rng().fill(&mut nonce);
+    nonce
+}
+
+/// Checks a received nonce has the expected length
+pub fn nonce_from_bytes(bytes: &[u8]) -> Result<ProofNonce, String> {
+    bytes
+        .try_into()
+        .map_err(|_| format!("Nonce must be {} bytes, got {}", NONCE_BYTES, bytes.len()))
+}
+
+fn nonce_limbs(nonce: &ProofNonce) -> [u64; NONCE_LIMBS] {
+    let mut limbs = [0u64; NONCE_LIMBS];
+    for (limb, chunk) in limbs.iter_mut().zip(nonce.chunks(4)) {
+        *limb = u32::from_le_bytes(chunk.try_into().unwrap()) as u64;
+    }
+    limbs
+}
+
+/// Decodes a commitment from 32 little-endian bytes
+pub fn commitment_from_bytes(bytes: &[u8]) -> Result<SalaryCommitment, String> {
+    if bytes.len() != 32 {
+        return Err(format!("Commitment must be 32 bytes, got {}", bytes.len()));
+    }
+    let mut commitment = [0u64; 4];
+    for (element, chunk) in commitment.iter_mut().zip(bytes.chunks(8)) {
+        *element = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+    Ok(commitment)
+}
+
+/// Creates a ZK circuit that proves the salary is greater than the threshold
+///
+/// The threshold, a Poseidon commitment to the salary and the application nonce are public
+/// inputs, in that order, so the proof is bound to the same salary as any other proof over
+/// that commitment and cannot be presented again under a fresh nonce.
+pub fn create_salary_threshold_circuit() -> (
+    CircuitBuilder<F, D>,
+    Target, // salary_target
+    Target, // threshold_target
+    BoolTarget, // result_target (true if salary > threshold)
+    [Target; BLINDING_ELEMENTS], // blinding_targets
+    [Target; NONCE_LIMBS], // nonce_targets
+) {
+    // Create a new circuit with default configuration
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+
+    // Create targets for inputs
+    let salary_target = builder.add_virtual_target();
+    let threshold_target = builder.add_virtual_target();
+
+    // Add a range check for salary (to ensure it's a valid positive number)
+    let salary_bits = builder.split_le(salary_target, 64);
+    
+    // Add a range check for threshold
+    let threshold_bits = builder.split_le(threshold_target, 64);
+    
+    // Create the salary > threshold check by comparing the bits
+    // Start with most significant bit (MSB) and work downwards
+    let mut is_greater = builder.constant_bool(false);
+    let mut is_equal_so_far = builder.constant_bool(true);
+    
+    // Compare bits from most significant to least significant
+    for i in (0..64).rev() {
+        let s_bit = salary_bits[i];
+        let t_bit = threshold_bits[i];
+        
+        // salary_bit > threshold_bit at this position
+        // equivalent to s_bit AND (NOT t_bit)
+        let not_t_bit = builder.not(t_bit);
+        let bit_greater = builder.and(s_bit, not_t_bit);
+        
+        // salary_bit == threshold_bit at this position
+        let bit_equal = builder.is_equal(s_bit.target, t_bit.target);
+        
+        // is_greater = is_greater || (is_equal_so_far && bit_greater)
+        let new_greater = builder.and(is_equal_so_far, bit_greater);
+        is_greater = builder.or(is_greater, new_greater);
+        
+        // Update is_equal_so_far for next iteration
+        is_equal_so_far = builder.and(is_equal_so_far, bit_equal);
+    }
+    
+    // Constrain the result to be true (salary > threshold)
+    builder.assert_one(is_greater.target);
+    
+    // Expose the threshold and the salary commitment
+    builder.register_public_input(threshold_target);
+    let blinding_targets = [(); BLINDING_ELEMENTS].map(|_| builder.add_virtual_target());
+    let mut commitment_inputs = vec![salary_target];
+    commitment_inputs.extend_from_slice(&blinding_targets);
+    let commitment = builder.hash_n_to_hash_no_pad::<PoseidonHash>(commitment_inputs);
+    builder.register_public_inputs(&commitment.elements);
+    
+    // Expose the nonce, range checked so each limb has a single encoding
+    let nonce_targets = [(); NONCE_LIMBS].map(|_| builder.add_virtual_target());
+    for target in nonce_targets {
+        builder.range_check(target, 32);
+    }
+    builder.register_public_inputs(&nonce_targets);
+    
+    (builder, salary_target, threshold_target, is_greater, blinding_targets, nonce_targets)
+}
+
+/// Generates a ZK proof that salary > threshold
+pub fn generate_salary_threshold_proof(
+    salary: u64,
+    threshold: u64,
+) -> Result<bool, String> {
+    Ok(generate_committed_salary_threshold_proof(salary, threshold, &random_blinding(), &generate_nonce())?.is_some())
+}
+
+/// Generates a ZK proof, bound to `nonce`, that the salary committed under `blinding` exceeds the threshold
+///
+/// Returns the serialized proof, or `None` if the salary does not exceed the threshold.
+pub fn generate_committed_salary_threshold_proof(
+    salary: u64,
+    threshold: u64,
+    blinding: &[u64; BLINDING_ELEMENTS],
+    nonce: &ProofNonce,
+) -> Result<Option<Vec<u8>>, String> {
+    // Only proceed if salary > threshold (otherwise we can't create a valid proof)
+    if salary <= threshold {
+        return Ok(None);
+    }
+
+    // Create the circuit
+    let (builder, salary_target, threshold_target, _result_target, blinding_targets, nonce_targets) =
+        create_salary_threshold_circuit();
+    
+    // Build the circuit
+    let start = Instant::now();
+    let circuit_data = builder.build::<C>();
+    println!("Circuit built in {:?}", start.elapsed());
+    
+    // Create a partial witness
+    let mut pw = PartialWitness::new();
+    
+    // Set the private witness (salary and commitment blinding)
+    pw.set_target(salary_target, F::from_canonical_u64(salary));
+    for (target, value) in blinding_targets.iter().zip(blinding) {
+        pw.set_target(*target, F::from_canonical_u64(*value));
+    }
+    
+    // Set the public inputs (threshold and nonce)
+    pw.set_target(threshold_target, F::from_canonical_u64(threshold));
+    for (target, limb) in nonce_targets.iter().zip(nonce_limbs(nonce)) {
+        pw.set_target(*target, F::from_canonical_u64(limb));
+    }
+    
+    // Generate the proof
+    let start = Instant::now();
+    let proof = circuit_data.prove(pw).map_err(|e| format!("Proving error: {:?}", e))?;
+    println!("Proof generated in {:?}", start.elapsed());
+    
+    // Verify the proof
+    let start = Instant::now();
+    circuit_data
+        .verify(proof.clone())
+        .map_err(|e| format!("Verification error: {:?}", e))?;
+    println!("Proof verified in {:?}", start.elapsed());
+    
+    Ok(Some(proof.to_bytes()))
+}
+
+/// Verifies a serialized salary threshold proof against the expected threshold, commitment and nonce
+pub fn verify_committed_salary_threshold_proof(
+    proof_bytes: &[u8],
+    threshold: u64,
+    commitment: &SalaryCommitment,
+    nonce: &ProofNonce,
+) -> Result<bool, String> {
+    let (builder, ..) = create_salary_threshold_circuit();
+    let circuit_data: CircuitData<F, C, D> = builder.build::<C>();
+    
+    let proof = ProofWithPublicInputs::<F, C, D>::from_bytes(proof_bytes.to_vec(), &circuit_data.common)
+        .map_err(|e| format!("Malformed proof: {:?}", e))?;
+    
+    // Public inputs are the threshold, the salary commitment and the nonce
+    let mut expected = vec![threshold];
+    expected.extend_from_slice(commitment);
+    expected.extend_from_slice(&nonce_limbs(nonce));
+    let actual: Vec<u64> = proof.public_inputs.iter().map(|e| e.to_canonical_u64()).collect();
+    if actual != expected {
+        return Ok(false);
+    }
+    
+    Ok(circuit_data.verify(proof).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_proof_is_bound_to_its_nonce() {
+        let blinding = random_blinding();
+        let commitment = commit_salary(6000, &blinding);
+        let nonce = generate_nonce();
+        let proof = generate_committed_salary_threshold_proof(6000, 5000, &blinding, &nonce)
+            .unwrap()
+            .expect("salary exceeds the threshold");
+
+        assert!(verify_committed_salary_threshold_proof(&proof, 5000, &commitment, &nonce).unwrap());
+        assert!(!verify_committed_salary_threshold_proof(&proof, 5000, &commitment, &generate_nonce()).unwrap());
+        assert!(!verify_committed_salary_threshold_proof(&proof, 4000, &commitment, &nonce).unwrap());
+    }
+
+    #[test]
+    fn salary_at_threshold_has_no_proof() {
+        let proof = generate_committed_salary_threshold_proof(5000, 5000, &random_blinding(), &generate_nonce()).unwrap();
+        assert!(proof.is_none());
+    }
+
+    #[test]
+    fn nonce_must_be_32_bytes() {
+        assert!(nonce_from_bytes(&[5, 6, 7, 8]).is_err());
+        assert_eq!(nonce_from_bytes(&[7u8; NONCE_BYTES]).unwrap(), [7u8; NONCE_BYTES]);
+    }
+}
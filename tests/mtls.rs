@@ -0,0 +1,110 @@
+//! Round trips through a real gRPC server over mutual TLS with the development CA
+
+use privacy_preserving_credit::decryption_service::credit_evaluation::decryption_service_client::DecryptionServiceClient;
+use privacy_preserving_credit::decryption_service::credit_evaluation::decryption_service_server::{
+    DecryptionService, DecryptionServiceServer,
+};
+use privacy_preserving_credit::decryption_service::credit_evaluation::{DecisionInputRequest, DecisionInputResponse};
+use privacy_preserving_credit::tls::{self, TlsPaths};
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::time::Duration;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+/// Answers with the length of the caller's certificate identity
+struct EchoIdentity;
+
+#[tonic::async_trait]
+impl DecryptionService for EchoIdentity {
+    async fn decrypt_decision_input(
+        &self,
+        request: Request<DecisionInputRequest>,
+    ) -> Result<Response<DecisionInputResponse>, Status> {
+        let identity = tls::peer_identity(&request)?;
+        if identity != tls::BANK_IDENTITY {
+            return Err(Status::permission_denied(format!("unexpected caller {}", identity)));
+        }
+        Ok(Response::new(DecisionInputResponse { value: identity.len() as u64 }))
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("credit-mtls-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Starts the server on a free local port and returns its URL
+async fn serve(paths: &TlsPaths) -> String {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let router = Server::builder()
+        .tls_config(tls::server_tls_config(paths).unwrap())
+        .unwrap()
+        .add_service(DecryptionServiceServer::new(EchoIdentity));
+    tokio::spawn(router.serve(addr));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    format!("https://{}", addr)
+}
+
+async fn call(url: &str, paths: &TlsPaths) -> Result<u64, String> {
+    let channel = tls::connect(url, paths).await.map_err(|e| e.to_string())?;
+    let response = DecryptionServiceClient::new(channel)
+        .decrypt_decision_input(DecisionInputRequest::default())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(response.into_inner().value)
+}
+
+#[tokio::test]
+async fn client_certificate_identity_reaches_the_service() {
+    let dir = temp_dir("roundtrip");
+    tls::ensure_dev_ca(&dir, &[tls::KEY_HOLDER_IDENTITY, tls::BANK_IDENTITY]).unwrap();
+    let url = serve(&TlsPaths::in_dir(&dir, tls::KEY_HOLDER_IDENTITY)).await;
+
+    let value = call(&url, &TlsPaths::in_dir(&dir, tls::BANK_IDENTITY)).await.unwrap();
+    assert_eq!(value, tls::BANK_IDENTITY.len() as u64);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn identities_added_later_still_connect() {
+    let dir = temp_dir("added");
+    tls::ensure_dev_ca(&dir, &[tls::KEY_HOLDER_IDENTITY]).unwrap();
+    let url = serve(&TlsPaths::in_dir(&dir, tls::KEY_HOLDER_IDENTITY)).await;
+
+    tls::ensure_dev_ca(&dir, &[tls::KEY_HOLDER_IDENTITY, tls::BANK_IDENTITY]).unwrap();
+    assert!(call(&url, &TlsPaths::in_dir(&dir, tls::BANK_IDENTITY)).await.is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn certificates_from_another_ca_are_refused() {
+    let dir = temp_dir("server");
+    let other = temp_dir("other");
+    tls::ensure_dev_ca(&dir, &[tls::KEY_HOLDER_IDENTITY]).unwrap();
+    tls::ensure_dev_ca(&other, &[tls::BANK_IDENTITY]).unwrap();
+    let url = serve(&TlsPaths::in_dir(&dir, tls::KEY_HOLDER_IDENTITY)).await;
+
+    // A foreign client certificate, trusting the server's CA so only the client side differs
+    let mut foreign = TlsPaths::in_dir(&other, tls::BANK_IDENTITY);
+    foreign.ca_cert = dir.join("ca.pem");
+    assert!(call(&url, &foreign).await.is_err());
+
+    // A client that trusts a different CA refuses the server
+    assert!(call(&url, &TlsPaths::in_dir(&other, tls::BANK_IDENTITY)).await.is_err());
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(&other).unwrap();
+}
+
+#[tokio::test]
+async fn other_identities_are_told_apart() {
+    let dir = temp_dir("identity");
+    tls::ensure_dev_ca(&dir, &[tls::KEY_HOLDER_IDENTITY, tls::APPLICANT_IDENTITY]).unwrap();
+    let url = serve(&TlsPaths::in_dir(&dir, tls::KEY_HOLDER_IDENTITY)).await;
+
+    let error = call(&url, &TlsPaths::in_dir(&dir, tls::APPLICANT_IDENTITY)).await.unwrap_err();
+    assert!(error.contains("unexpected caller applicant"), "{}", error);
+    fs::remove_dir_all(&dir).unwrap();
+}